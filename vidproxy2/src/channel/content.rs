@@ -5,7 +5,9 @@ use chrono::{DateTime, Duration, Utc};
 use crate::engine::{
     InterpolationContext,
     executor::execute_steps,
+    fixtures::Fixtures,
     manifest::{ContentOutputs, ContentPhase},
+    trace::Tracer,
 };
 
 use super::types::{Channel, StreamInfo};
@@ -15,9 +17,11 @@ use super::types::{Channel, StreamInfo};
 */
 pub async fn execute_content(
     phase: &ContentPhase,
-    tab: &ChromeBrowserTab,
+    tab: Option<&ChromeBrowserTab>,
     channel: &Channel,
     proxy: Option<&str>,
+    fixtures: Option<&Fixtures>,
+    trace: Option<&Tracer>,
 ) -> Result<StreamInfo> {
     let mut context = InterpolationContext::new();
     context.set("channel", "id", channel.id.clone());
@@ -28,7 +32,7 @@ pub async fn execute_content(
         context.set("channel", "image", image.clone());
     }
 
-    let output = execute_steps(&phase.steps, tab, context, proxy).await?;
+    let output = execute_steps(&phase.steps, tab, context, proxy, fixtures, trace).await?;
 
     let manifest_url = output.context.interpolate(&phase.outputs.manifest_url)?;
     let license_url = phase
@@ -5,7 +5,9 @@ use chrono::{DateTime, Duration, Utc};
 use crate::engine::{
     InterpolationContext, PhaseOutput, Source,
     executor::execute_steps,
+    fixtures::Fixtures,
     manifest::{DiscoveryOutputs, DiscoveryPhase},
+    trace::Tracer,
 };
 
 use super::types::Channel;
@@ -22,63 +24,40 @@ pub struct DiscoveryResult {
     Execute the discovery phase, returning discovered channels.
 
     Supports two modes:
-    1. Multi-channel: An array extractor produces multiple items, each becoming a channel.
+    1. Multi-channel: The `id` output references an array (e.g.
+       `${{find_channels.channels.id}}`). The outputs are interpolated once per
+       item, with `find_channels.channels` bound to that item.
     2. Single-channel: Scalar extractors produce one channel via output interpolation.
 
-    Domain filtering happens here: items without an `id` field are skipped.
+    Domain filtering happens here: items whose `id` is undefined or empty are skipped.
 */
 pub async fn execute_discovery(
     phase: &DiscoveryPhase,
-    tab: &ChromeBrowserTab,
+    tab: Option<&ChromeBrowserTab>,
     source: &Source,
     proxy: Option<&str>,
+    fixtures: Option<&Fixtures>,
+    trace: Option<&Tracer>,
 ) -> Result<DiscoveryResult> {
     let context = InterpolationContext::new();
-    let output = execute_steps(&phase.steps, tab, context, proxy).await?;
-
-    let channels = if let Some((_key, items)) = first_array(&output) {
-        // Multi-channel mode: build channels from extracted array
-        items
-            .iter()
-            .filter_map(|item| {
-                let id = item.get("id").and_then(|v| v.clone())?;
-                let name = item.get("name").and_then(|v| v.clone());
-                let image = item.get("image").and_then(|v| v.clone());
-                Some(Channel {
-                    id,
-                    source_id: source.id.clone(),
-                    name,
-                    image,
-                    category: None,
-                    description: None,
-                })
-            })
-            .collect()
-    } else {
+    let output = execute_steps(&phase.steps, tab, context, proxy, fixtures, trace).await?;
+
+    let mut channels = Vec::new();
+    if let Some((step_name, output_name, items)) =
+        output.context.referenced_array(&phase.outputs.id)?
+    {
+        // Multi-channel mode: rebind the array to each item in turn
+        let mut context = output.context.clone();
+        for item in items {
+            context.set_value(&step_name, &output_name, item);
+            if let Some(channel) = build_channel(&phase.outputs, &context, source)? {
+                channels.push(channel);
+            }
+        }
+    } else if let Some(channel) = build_channel(&phase.outputs, &output.context, source)? {
         // Single-channel mode: interpolate outputs from context
-        let id = output.context.interpolate(&phase.outputs.id)?;
-        let name = phase
-            .outputs
-            .name
-            .as_ref()
-            .map(|t| output.context.interpolate(t))
-            .transpose()?;
-        let image = phase
-            .outputs
-            .image
-            .as_ref()
-            .map(|t| output.context.interpolate(t))
-            .transpose()?;
-
-        vec![Channel {
-            id,
-            source_id: source.id.clone(),
-            name,
-            image,
-            category: None,
-            description: None,
-        }]
-    };
+        channels.push(channel);
+    }
 
     if channels.is_empty() {
         return Err(anyhow!("Discovery found no channels"));
@@ -99,10 +78,38 @@ pub async fn execute_discovery(
 }
 
 /**
-    Get the first array from a phase output (there's typically only one).
+    Build a channel by interpolating discovery outputs against a context.
+
+    Returns `None` if the `id` is undefined or empty. Undefined `name` and
+    `image` values are left unset.
 */
-fn first_array(output: &PhaseOutput) -> Option<(&String, &crate::engine::ExtractedArray)> {
-    output.arrays.iter().next()
+fn build_channel(
+    outputs: &DiscoveryOutputs,
+    context: &InterpolationContext,
+    source: &Source,
+) -> Result<Option<Channel>> {
+    let Some(id) = context
+        .interpolate_optional(&outputs.id)?
+        .filter(|id| !id.is_empty())
+    else {
+        return Ok(None);
+    };
+
+    let optional = |template: &Option<String>| -> Result<Option<String>> {
+        Ok(match template {
+            Some(t) => context.interpolate_optional(t)?.filter(|v| !v.is_empty()),
+            None => None,
+        })
+    };
+
+    Ok(Some(Channel {
+        id,
+        source_id: source.id.clone(),
+        name: optional(&outputs.name)?,
+        image: optional(&outputs.image)?,
+        category: None,
+        description: None,
+    }))
 }
 
 /**
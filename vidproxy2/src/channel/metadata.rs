@@ -2,13 +2,19 @@ use std::collections::HashMap;
 
 use anyhow::{Result, anyhow};
 use chrome_browser::ChromeBrowserTab;
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Duration, NaiveDate, NaiveTime, Utc};
+use chrono_tz::Tz;
+use serde_json::Value;
 
 use crate::engine::{
     InterpolationContext,
     executor::execute_steps,
-    manifest::{MetadataOutputs, MetadataPhase},
+    fixtures::Fixtures,
+    interpolate::value_to_string,
+    manifest::{MetadataOutputs, MetadataPhase, Source},
+    trace::Tracer,
 };
+use crate::util::time;
 
 use super::types::Programme;
 
@@ -23,26 +29,48 @@ pub struct MetadataResult {
 /**
     Execute the metadata phase, returning EPG programmes keyed by channel ID.
 
-    Domain filtering happens here: items without `channel_id` or `title` are skipped.
-    Timestamps are parsed into `DateTime<Utc>` at this boundary.
+    The programme list is whatever array the `programmes` output references
+    (e.g. `${{get_schedule.programmes}}`). Domain filtering happens here:
+    items without `channel_id` or `title` are skipped.
+    Timestamps are parsed into `DateTime<Utc>` at this boundary; see
+    `ScheduleClock` for times without an offset. Programmes without an
+    `end_time` end when the channel's next programme starts.
 */
 pub async fn execute_metadata(
     phase: &MetadataPhase,
-    tab: &ChromeBrowserTab,
+    tab: Option<&ChromeBrowserTab>,
+    source: &Source,
     proxy: Option<&str>,
+    fixtures: Option<&Fixtures>,
+    trace: Option<&Tracer>,
 ) -> Result<MetadataResult> {
+    let timezone = phase.timezone.as_deref().or(source.timezone.as_deref());
+    let mut clock = ScheduleClock::new(timezone)?;
+
     let context = InterpolationContext::new();
-    let output = execute_steps(&phase.steps, tab, context, proxy).await?;
+    let output = execute_steps(&phase.steps, tab, context, proxy, fixtures, trace).await?;
 
-    let (_key, items) = output
-        .arrays
-        .iter()
-        .next()
-        .ok_or_else(|| anyhow!("Metadata phase must have an array extractor"))?;
+    let items = match output.context.lookup_template(&phase.outputs.programmes)? {
+        Some(Value::Array(items)) => items,
+        _ => {
+            return Err(anyhow!(
+                "Metadata output 'programmes' must reference an array, got '{}'",
+                phase.outputs.programmes
+            ));
+        }
+    };
 
-    let mut programmes_by_channel: HashMap<String, Vec<Programme>> = HashMap::new();
+    // Programmes whose end is inferred once every start is known
+    let mut pending: Vec<(String, Programme, bool)> = Vec::new();
 
-    for item in items {
+    for item in &items {
+        let item: HashMap<&str, Option<String>> = match item {
+            Value::Object(fields) => fields
+                .iter()
+                .map(|(k, v)| (k.as_str(), value_to_string(v)))
+                .collect(),
+            _ => continue,
+        };
         let channel_id = match item.get("channel_id").and_then(|v| v.clone()) {
             Some(id) => id,
             None => continue,
@@ -51,10 +79,11 @@ pub async fn execute_metadata(
             Some(t) => t,
             None => continue,
         };
+        let day = item.get("date").and_then(|v| v.as_deref());
         let start_time = match item
             .get("start_time")
             .and_then(|v| v.as_ref())
-            .and_then(|s| crate::util::time::parse_timestamp(s))
+            .and_then(|s| clock.start_time(&channel_id, s, day))
         {
             Some(t) => t,
             None => {
@@ -66,20 +95,18 @@ pub async fn execute_metadata(
                 continue;
             }
         };
-        let end_time = match item
-            .get("end_time")
-            .and_then(|v| v.as_ref())
-            .and_then(|s| crate::util::time::parse_timestamp(s))
-        {
-            Some(t) => t,
-            None => {
-                let raw = item.get("end_time").and_then(|v| v.clone());
-                eprintln!(
-                    "[metadata] Skipping programme '{}': invalid end_time {:?}",
-                    title, raw
-                );
-                continue;
-            }
+        let end_time = match item.get("end_time").and_then(|v| v.as_deref()) {
+            None | Some("") => None,
+            Some(raw) => match clock.end_time(raw, start_time) {
+                Some(t) => Some(t),
+                None => {
+                    eprintln!(
+                        "[metadata] Skipping programme '{}': invalid end_time {:?}",
+                        title, raw
+                    );
+                    continue;
+                }
+            },
         };
 
         let description = item.get("description").and_then(|v| v.clone());
@@ -96,22 +123,25 @@ pub async fn execute_metadata(
             .and_then(|v| v.as_ref())
             .map(|v| v == "true");
 
-        programmes_by_channel
-            .entry(channel_id)
-            .or_default()
-            .push(Programme {
+        pending.push((
+            channel_id,
+            Programme {
                 title,
                 description,
                 start_time,
-                end_time,
+                end_time: end_time.unwrap_or(start_time),
                 episode,
                 season,
                 genres,
                 image,
                 is_live,
-            });
+            },
+            end_time.is_some(),
+        ));
     }
 
+    let programmes_by_channel = infer_end_times(pending);
+
     let total: usize = programmes_by_channel.values().map(|p| p.len()).sum();
     println!(
         "[metadata] Got {} programmes across {} channels",
@@ -127,11 +157,225 @@ pub async fn execute_metadata(
     })
 }
 
+/**
+    Group programmes by channel, ending those without an `end_time` at the
+    start of the channel's next programme. A programme with nothing after it
+    is skipped.
+*/
+fn infer_end_times(pending: Vec<(String, Programme, bool)>) -> HashMap<String, Vec<Programme>> {
+    let mut starts: HashMap<&str, Vec<DateTime<Utc>>> = HashMap::new();
+    for (channel_id, programme, _) in &pending {
+        starts
+            .entry(channel_id.as_str())
+            .or_default()
+            .push(programme.start_time);
+    }
+    for times in starts.values_mut() {
+        times.sort();
+    }
+
+    let mut ends: Vec<Option<DateTime<Utc>>> = Vec::with_capacity(pending.len());
+    for (channel_id, programme, has_end) in &pending {
+        if *has_end {
+            ends.push(Some(programme.end_time));
+            continue;
+        }
+        let times = &starts[channel_id.as_str()];
+        let next = times.partition_point(|t| *t <= programme.start_time);
+        if next == times.len() {
+            eprintln!(
+                "[metadata] Skipping programme '{}': no end_time and no programme after it",
+                programme.title
+            );
+        }
+        ends.push(times.get(next).copied());
+    }
+
+    let mut programmes_by_channel: HashMap<String, Vec<Programme>> = HashMap::new();
+    for ((channel_id, mut programme, _), end) in pending.into_iter().zip(ends) {
+        let Some(end) = end else {
+            continue;
+        };
+        programme.end_time = end;
+        programmes_by_channel
+            .entry(channel_id)
+            .or_default()
+            .push(programme);
+    }
+    programmes_by_channel
+}
+
+/**
+    Turns schedule times into UTC timestamps.
+
+    RFC 3339 and epoch timestamps are absolute. Dates and times without an
+    offset are in the phase's time zone (UTC if none). A time of day alone
+    (`"8:00 p. m."`) is on the item's `date`, or today; a channel's times on
+    the same day that go backwards have crossed midnight, and move to the
+    next day.
+*/
+struct ScheduleClock {
+    timezone: Option<Tz>,
+    today: NaiveDate,
+    /// Latest start and days past midnight, per channel and day
+    days: HashMap<(String, NaiveDate), (DateTime<Utc>, i64)>,
+}
+
+impl ScheduleClock {
+    fn new(timezone: Option<&str>) -> Result<Self> {
+        let timezone = timezone
+            .map(|name| time::parse_tz(name).ok_or_else(|| anyhow!("Unknown time zone '{}'", name)))
+            .transpose()?;
+        let now = time::now();
+        let today = match timezone {
+            Some(tz) => now.with_timezone(&tz).date_naive(),
+            None => now.date_naive(),
+        };
+        Ok(Self {
+            timezone,
+            today,
+            days: HashMap::new(),
+        })
+    }
+
+    fn start_time(
+        &mut self,
+        channel_id: &str,
+        raw: &str,
+        day: Option<&str>,
+    ) -> Option<DateTime<Utc>> {
+        if let Some(t) = self.absolute(raw) {
+            return Some(t);
+        }
+
+        let time_of_day = time::parse_time_of_day(raw)?;
+        let day = match day {
+            Some(day) => time::parse_day(day, self.timezone)?,
+            None => self.today,
+        };
+
+        let key = (channel_id.to_string(), day);
+        let (latest, mut offset) = match self.days.get(&key) {
+            Some(&(latest, offset)) => (Some(latest), offset),
+            None => (None, 0),
+        };
+        let mut start = self.local(day + Duration::days(offset), time_of_day)?;
+        if latest.is_some_and(|latest| start < latest) {
+            offset += 1;
+            start = self.local(day + Duration::days(offset), time_of_day)?;
+        }
+        self.days.insert(key, (start, offset));
+        Some(start)
+    }
+
+    /**
+        An end time; a time of day alone is on the day the programme
+        started, or the next if that would end it before it starts.
+    */
+    fn end_time(&self, raw: &str, start: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        if let Some(t) = self.absolute(raw) {
+            return Some(t);
+        }
+
+        let time_of_day = time::parse_time_of_day(raw)?;
+        let day = match self.timezone {
+            Some(tz) => start.with_timezone(&tz).date_naive(),
+            None => start.date_naive(),
+        };
+        let end = self.local(day, time_of_day)?;
+        if end <= start {
+            self.local(day + Duration::days(1), time_of_day)
+        } else {
+            Some(end)
+        }
+    }
+
+    fn absolute(&self, raw: &str) -> Option<DateTime<Utc>> {
+        time::parse_timestamp(raw).or_else(|| {
+            time::parse_local_datetime(raw)
+                .and_then(|naive| time::local_to_utc(naive, self.timezone))
+        })
+    }
+
+    fn local(&self, day: NaiveDate, time_of_day: NaiveTime) -> Option<DateTime<Utc>> {
+        time::local_to_utc(day.and_time(time_of_day), self.timezone)
+    }
+}
+
 /**
     Resolve expiration from metadata outputs.
 */
 fn resolve_expiration(outputs: &MetadataOutputs) -> Option<DateTime<Utc>> {
     outputs
         .expires_in
-        .map(|secs| crate::util::time::now() + Duration::seconds(secs as i64))
+        .map(|secs| time::now() + Duration::seconds(secs as i64))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn programme(title: &str, start: DateTime<Utc>) -> Programme {
+        Programme {
+            title: title.to_string(),
+            description: None,
+            start_time: start,
+            end_time: start,
+            episode: None,
+            season: None,
+            genres: Vec::new(),
+            image: None,
+            is_live: None,
+        }
+    }
+
+    #[test]
+    fn test_schedule_clock() {
+        let mut clock = ScheduleClock::new(Some("America/Bogota")).unwrap();
+        let start = |clock: &mut ScheduleClock, raw| {
+            clock
+                .start_time("caracol", raw, Some("2026-02-08"))
+                .unwrap()
+                .to_rfc3339()
+        };
+
+        assert_eq!(start(&mut clock, "8:00 p. m."), "2026-02-09T01:00:00+00:00");
+        assert_eq!(
+            start(&mut clock, "11:30 p. m."),
+            "2026-02-09T04:30:00+00:00"
+        );
+        // Past midnight, on the next day
+        assert_eq!(start(&mut clock, "1:00 a. m."), "2026-02-09T06:00:00+00:00");
+        assert_eq!(
+            start(&mut clock, "2026-02-09T07:00:00Z"),
+            "2026-02-09T07:00:00+00:00"
+        );
+
+        let begin = time::parse_timestamp("2026-02-09T04:30:00Z").unwrap();
+        assert_eq!(
+            clock.end_time("00:15", begin).unwrap().to_rfc3339(),
+            "2026-02-09T05:15:00+00:00"
+        );
+        assert!(ScheduleClock::new(Some("Bogota")).is_err());
+    }
+
+    #[test]
+    fn test_infer_end_times() {
+        let at = |hour| time::parse_timestamp(&format!("2026-02-08T{:02}:00:00Z", hour)).unwrap();
+        let mut with_end = programme("News", at(10));
+        with_end.end_time = at(11);
+
+        let programmes = infer_end_times(vec![
+            ("a".to_string(), programme("Late", at(20)), false),
+            ("a".to_string(), programme("Morning", at(8)), false),
+            ("a".to_string(), with_end, true),
+            ("b".to_string(), programme("Other", at(9)), false),
+        ]);
+
+        let a = &programmes["a"];
+        assert_eq!(a.len(), 2);
+        assert_eq!((a[0].title.as_str(), a[0].end_time), ("Morning", at(10)));
+        assert_eq!((a[1].title.as_str(), a[1].end_time), ("News", at(11)));
+        assert!(!programmes.contains_key("b"));
+    }
 }
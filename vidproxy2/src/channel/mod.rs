@@ -3,6 +3,7 @@ pub mod discovery;
 pub mod metadata;
 pub mod process;
 pub mod registry;
+pub mod reload;
 pub mod resolver;
 pub mod types;
 
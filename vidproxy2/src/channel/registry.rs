@@ -5,6 +5,8 @@ use std::time::Duration;
 use chrono::{DateTime, Utc};
 use tokio::sync::Notify;
 
+use crate::engine::trace::PhaseTrace;
+
 use super::types::{ChannelContentState, ChannelEntry, ChannelId, SourceState, StreamInfo};
 
 /**
@@ -18,6 +20,7 @@ pub struct ChannelRegistry {
     source_notify: RwLock<HashMap<String, Arc<Notify>>>,
     channel_content_state: RwLock<HashMap<ChannelId, ChannelContentState>>,
     channel_content_notify: RwLock<HashMap<ChannelId, Arc<Notify>>>,
+    content_traces: RwLock<HashMap<ChannelId, PhaseTrace>>,
 }
 
 impl ChannelRegistry {
@@ -30,6 +33,7 @@ impl ChannelRegistry {
             source_notify: RwLock::new(HashMap::new()),
             channel_content_state: RwLock::new(HashMap::new()),
             channel_content_notify: RwLock::new(HashMap::new()),
+            content_traces: RwLock::new(HashMap::new()),
         }
     }
 
@@ -108,12 +112,41 @@ impl ChannelRegistry {
         source_name: &str,
         channels: Vec<ChannelEntry>,
         discovery_expires_at: Option<DateTime<Utc>>,
+    ) {
+        self.register(source_name, channels, discovery_expires_at, false);
+    }
+
+    /**
+        Like `register_source`, but channels that were already registered
+        keep their resolved stream info, so their pipelines carry on
+        undisturbed while the source is replaced.
+    */
+    pub fn reregister_source(
+        &self,
+        source_name: &str,
+        channels: Vec<ChannelEntry>,
+        discovery_expires_at: Option<DateTime<Utc>>,
+    ) {
+        self.register(source_name, channels, discovery_expires_at, true);
+    }
+
+    fn register(
+        &self,
+        source_name: &str,
+        channels: Vec<ChannelEntry>,
+        discovery_expires_at: Option<DateTime<Utc>>,
+        keep_stream_info: bool,
     ) {
         {
             let mut registry = self.channels.write().unwrap();
-            registry.retain(|id, _| id.source != source_name);
-            for entry in channels {
+            let mut previous: HashMap<ChannelId, ChannelEntry> = registry
+                .extract_if(|id, _| id.source == source_name)
+                .collect();
+            for mut entry in channels {
                 let id = ChannelId::new(source_name, &entry.channel.id);
+                if keep_stream_info && let Some(old) = previous.remove(&id) {
+                    entry.stream_info = old.stream_info;
+                }
                 registry.insert(id, entry);
             }
         }
@@ -134,6 +167,43 @@ impl ChannelRegistry {
         }
     }
 
+    /**
+        Forget a source entirely: its channels, expirations and state.
+
+        Anyone waiting on the source is woken and sees it as unknown.
+    */
+    pub fn remove_source(&self, source_name: &str) {
+        self.channels
+            .write()
+            .unwrap()
+            .retain(|id, _| id.source != source_name);
+        self.channel_content_state
+            .write()
+            .unwrap()
+            .retain(|id, _| id.source != source_name);
+        self.channel_content_notify
+            .write()
+            .unwrap()
+            .retain(|id, _| id.source != source_name);
+        self.content_traces
+            .write()
+            .unwrap()
+            .retain(|id, _| id.source != source_name);
+        self.discovery_expiration
+            .write()
+            .unwrap()
+            .remove(source_name);
+        self.metadata_expiration
+            .write()
+            .unwrap()
+            .remove(source_name);
+        self.source_state.write().unwrap().remove(source_name);
+
+        if let Some(notify) = self.source_notify.write().unwrap().remove(source_name) {
+            notify.notify_waiters();
+        }
+    }
+
     pub fn get(&self, id: &ChannelId) -> Option<ChannelEntry> {
         self.channels.read().unwrap().get(id).cloned()
     }
@@ -163,6 +233,20 @@ impl ChannelRegistry {
         }
     }
 
+    /**
+        Keep the trace of a channel's latest content phase run.
+    */
+    pub fn set_content_trace(&self, id: &ChannelId, trace: PhaseTrace) {
+        self.content_traces
+            .write()
+            .unwrap()
+            .insert(id.clone(), trace);
+    }
+
+    pub fn get_content_trace(&self, id: &ChannelId) -> Option<PhaseTrace> {
+        self.content_traces.read().unwrap().get(id).cloned()
+    }
+
     pub fn is_stream_expired(&self, id: &ChannelId) -> bool {
         let registry = self.channels.read().unwrap();
         if let Some(entry) = registry.get(id) {
@@ -0,0 +1,460 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime};
+
+use tokio::sync::watch;
+
+use crate::engine::manifest::{Manifest, load_dir, load_sources};
+use crate::engine::secrets::redact;
+use crate::media::PipelineStore;
+
+use super::resolver::Resolver;
+use super::types::ChannelId;
+
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/**
+    Modification snapshot of a sources directory: (path, mtime, size) per file.
+
+    Subdirectories are walked too, so editing a fragment pulled in by
+    `include:` or `extends:` (e.g. under `templates/`) triggers a reload.
+*/
+type DirSnapshot = Vec<(PathBuf, Option<SystemTime>, u64)>;
+
+fn snapshot(dir: &Path) -> DirSnapshot {
+    let mut files = DirSnapshot::new();
+    let mut dirs = vec![dir.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let Ok(entries) = std::fs::read_dir(&dir) else {
+            continue;
+        };
+        for entry in entries.filter_map(|entry| entry.ok()) {
+            // Symlinks aren't followed, so a link cycle can't loop forever
+            let Ok(meta) = entry.metadata() else {
+                continue;
+            };
+            if meta.is_dir() {
+                dirs.push(entry.path());
+            } else if meta.is_file() {
+                files.push((entry.path(), meta.modified().ok(), meta.len()));
+            }
+        }
+    }
+    files.sort();
+    files
+}
+
+/**
+    Watch a sources directory and apply manifest changes to a running server.
+
+    Polls the directory for changes. When something changed, the full set is
+    reloaded (embedded + directory) and compared against the `ManifestStore`:
+    - new or changed manifests are stored and re-discovered; pipelines of
+      channels that disappeared are stopped, all others keep running
+    - sources whose manifest is gone are removed along with their pipelines
+
+    Sources are only removed when every file loaded cleanly, since a file that
+    fails to parse can't tell us which source it was meant to define. A source
+    whose file loaded before but fails now keeps its current manifest, rather
+    than falling back to the embedded one.
+*/
+pub async fn watch_sources(
+    dir: PathBuf,
+    resolver: Arc<Resolver>,
+    pipeline_store: Arc<PipelineStore>,
+    mut shutdown_rx: watch::Receiver<bool>,
+) {
+    println!("[reload] Watching {} for changes", dir.display());
+    let mut last = snapshot(&dir);
+    let mut files = load_dir(&dir)
+        .map(|loaded| loaded.files)
+        .unwrap_or_default();
+
+    loop {
+        tokio::select! {
+            _ = tokio::time::sleep(POLL_INTERVAL) => {}
+            _ = shutdown_rx.changed() => {
+                if *shutdown_rx.borrow() {
+                    return;
+                }
+            }
+        }
+
+        let current = snapshot(&dir);
+        if current == last {
+            continue;
+        }
+        last = current;
+
+        println!("[reload] Change detected in {}", dir.display());
+        apply_changes(&dir, &mut files, &resolver, &pipeline_store).await;
+    }
+}
+
+/**
+    Reload the sources and reconcile them with the running server.
+
+    `files` maps each file of `dir` to the source id it defined when it last
+    loaded. Returns the IDs of the channels that were removed.
+*/
+async fn apply_changes(
+    dir: &Path,
+    files: &mut HashMap<PathBuf, String>,
+    resolver: &Arc<Resolver>,
+    pipeline_store: &Arc<PipelineStore>,
+) -> Vec<ChannelId> {
+    let loaded = match load_sources(Some(dir)) {
+        Ok(loaded) => loaded,
+        Err(e) => {
+            eprintln!("[reload] Failed to load sources: {}", e);
+            return Vec::new();
+        }
+    };
+    for error in &loaded.errors {
+        eprintln!("[manifest] Skipping {}", error);
+    }
+
+    // Sources whose file fails to load keep their current manifest
+    let broken: HashSet<String> = loaded
+        .errors
+        .iter()
+        .filter_map(|error| files.get(&error.path).cloned())
+        .collect();
+    files.retain(|path, _| loaded.errors.iter().any(|error| &error.path == path));
+    files.extend(loaded.files);
+
+    let existing = resolver.manifest_store.list().await;
+    let mut removed_ids = Vec::new();
+
+    // Sources whose manifest no longer exists
+    if loaded.errors.is_empty() {
+        for manifest in &existing {
+            let source_id = &manifest.source.id;
+            if loaded.manifests.iter().all(|m| &m.source.id != source_id) {
+                println!("[reload] Source '{}' removed", source_id);
+                let removed = resolver.remove_source(source_id).await;
+                stop_pipelines(pipeline_store, &removed).await;
+                removed_ids.extend(removed);
+            }
+        }
+    }
+
+    // New or changed sources, re-discovered in parallel
+    let mut handles = Vec::new();
+    for manifest in loaded.manifests {
+        if broken.contains(&manifest.source.id) {
+            println!(
+                "[reload] Source '{}' failed to load, keeping its current manifest",
+                manifest.source.id
+            );
+            continue;
+        }
+
+        let previous = existing.iter().find(|m| m.source.id == manifest.source.id);
+        if previous.is_some_and(|previous| same_manifest(previous, &manifest)) {
+            continue;
+        }
+
+        println!(
+            "[reload] Source '{}' {}, re-running discovery",
+            manifest.source.id,
+            if previous.is_some() {
+                "changed"
+            } else {
+                "added"
+            }
+        );
+
+        let resolver = Arc::clone(resolver);
+        let pipeline_store = Arc::clone(pipeline_store);
+        handles.push(tokio::spawn(async move {
+            let source_id = manifest.source.id.clone();
+            match resolver.reload_source(manifest).await {
+                Ok(removed) => {
+                    stop_pipelines(&pipeline_store, &removed).await;
+                    let count = resolver.registry.list_by_source(&source_id).len();
+                    println!(
+                        "[reload] Source '{}' reloaded: {} channels ({} removed)",
+                        source_id,
+                        count,
+                        removed.len()
+                    );
+                    removed
+                }
+                Err(e) => {
+                    eprintln!(
+                        "[reload] Source '{}' failed to reload: {}",
+                        source_id,
+                        redact(&e.to_string())
+                    );
+                    Vec::new()
+                }
+            }
+        }));
+    }
+
+    for handle in handles {
+        if let Ok(removed) = handle.await {
+            removed_ids.extend(removed);
+        }
+    }
+
+    // Idle browsers launched for configs no source uses anymore
+    let configs: HashSet<_> = resolver
+        .manifest_store
+        .list()
+        .await
+        .iter()
+        .flat_map(Manifest::browser_configs)
+        .collect();
+    resolver.browser_pool.retain_configs(&configs).await;
+
+    removed_ids
+}
+
+async fn stop_pipelines(pipeline_store: &PipelineStore, ids: &[ChannelId]) {
+    for id in ids {
+        if pipeline_store.remove(id).await {
+            println!("[reload] Stopped pipeline for {}", id.to_string());
+        }
+    }
+}
+
+fn same_manifest(a: &Manifest, b: &Manifest) -> bool {
+    match (serde_yaml::to_string(a), serde_yaml::to_string(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::channel::registry::ChannelRegistry;
+    use crate::channel::resolver::ManifestStore;
+    use crate::channel::types::StreamInfo;
+    use crate::engine::browser::BrowserPool;
+    use crate::engine::manifest::load_all;
+    use crate::media::PipelineConfig;
+
+    /**
+        Serve `/channels/<ids>`, listing the comma-separated channel ids.
+    */
+    async fn serve_channels() -> String {
+        use axum::{Json, Router, extract::Path, routing::get};
+
+        async fn channels(Path(ids): Path<String>) -> Json<serde_json::Value> {
+            let items: Vec<_> = ids
+                .split(',')
+                .map(|id| serde_json::json!({ "id": id }))
+                .collect();
+            Json(serde_json::json!({ "items": items }))
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = Router::new().route("/channels/{ids}", get(channels));
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    fn source(id: &str, base: &str, channels: &str) -> String {
+        format!(
+            r#"
+source:
+  id: "{id}"
+  name: "Reload {id}"
+discovery:
+  outputs:
+    id: "${{{{ list.items.id }}}}"
+  steps:
+    - name: "list"
+      kind: Fetch
+      url: "{base}/channels/{channels}"
+      extract:
+        items:
+          kind: jsonpath_array
+          path: "$.items[*]"
+          each:
+            id: "$.id"
+content:
+  outputs:
+    manifest_url: "${{{{ page.url }}}}"
+  steps: []
+"#
+        )
+    }
+
+    /**
+        A resolver already running the embedded sources, so only the
+        directory's sources are discovered.
+    */
+    async fn resolver() -> (Arc<Resolver>, Arc<PipelineStore>) {
+        let manifest_store = Arc::new(ManifestStore::new());
+        for manifest in load_all().manifests {
+            manifest_store.add(manifest).await;
+        }
+        let resolver = Resolver::new(
+            Arc::new(ChannelRegistry::new()),
+            manifest_store,
+            Arc::new(BrowserPool::new(Default::default())),
+        );
+
+        let (_, shutdown_rx) = watch::channel(false);
+        let config = PipelineConfig {
+            segment_count: 3,
+            segment_duration: Duration::from_secs(4),
+            idle_timeout: Duration::from_secs(30),
+            startup_timeout: Duration::from_secs(10),
+            base_output_dir: std::env::temp_dir(),
+        };
+        (
+            Arc::new(resolver),
+            Arc::new(PipelineStore::new(config, shutdown_rx)),
+        )
+    }
+
+    fn channel_ids(resolver: &Resolver, source_id: &str) -> Vec<String> {
+        let mut ids: Vec<String> = resolver
+            .registry
+            .list_by_source(source_id)
+            .into_iter()
+            .map(|entry| entry.channel.id)
+            .collect();
+        ids.sort();
+        ids
+    }
+
+    #[tokio::test]
+    async fn test_apply_changes() {
+        let base = serve_channels().await;
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, content: &str| {
+            std::fs::write(dir.path().join(name), content).unwrap();
+        };
+        write("a.yaml", &source("reload-a", &base, "one,two"));
+        write("b.yaml", &source("reload-b", &base, "three"));
+        write("caracol.yaml", &source("caracol", &base, "four"));
+
+        let (resolver, pipeline_store) = resolver().await;
+        let mut files = HashMap::new();
+        let removed = apply_changes(dir.path(), &mut files, &resolver, &pipeline_store).await;
+        assert!(removed.is_empty());
+        assert_eq!(channel_ids(&resolver, "reload-a"), ["one", "two"]);
+        assert_eq!(channel_ids(&resolver, "reload-b"), ["three"]);
+        assert_eq!(channel_ids(&resolver, "caracol"), ["four"]);
+
+        // A surviving channel keeps its stream info, a removed source and a
+        // vanished channel are dropped
+        let one = ChannelId::new("reload-a", "one");
+        resolver.registry.update_stream_info(
+            &one,
+            StreamInfo {
+                manifest_url: "https://cdn.example.com/one.m3u8".to_string(),
+                license_url: None,
+                expires_at: None,
+                headers: Vec::new(),
+            },
+        );
+        write("a.yaml", &source("reload-a", &base, "one,five"));
+        std::fs::remove_file(dir.path().join("b.yaml")).unwrap();
+
+        let mut removed = apply_changes(dir.path(), &mut files, &resolver, &pipeline_store).await;
+        removed.sort_by_key(|id| id.to_string());
+        assert_eq!(
+            removed,
+            [
+                ChannelId::new("reload-a", "two"),
+                ChannelId::new("reload-b", "three")
+            ]
+        );
+        assert_eq!(channel_ids(&resolver, "reload-a"), ["five", "one"]);
+        let stream_info = resolver.registry.get(&one).unwrap().stream_info.unwrap();
+        assert_eq!(stream_info.manifest_url, "https://cdn.example.com/one.m3u8");
+        assert!(resolver.manifest_store.get("reload-b").await.is_none());
+        assert!(channel_ids(&resolver, "reload-b").is_empty());
+
+        // A broken override keeps the current manifest instead of falling
+        // back to the embedded one
+        write("caracol.yaml", "source: [not, a, manifest");
+        let removed = apply_changes(dir.path(), &mut files, &resolver, &pipeline_store).await;
+        assert!(removed.is_empty());
+        let caracol = resolver.manifest_store.get("caracol").await.unwrap();
+        assert_eq!(caracol.source.name, "Reload caracol");
+        assert_eq!(channel_ids(&resolver, "caracol"), ["four"]);
+        assert_eq!(channel_ids(&resolver, "reload-a"), ["five", "one"]);
+
+        // Once it loads again, it is applied
+        write("caracol.yaml", &source("caracol", &base, "four,six"));
+        apply_changes(dir.path(), &mut files, &resolver, &pipeline_store).await;
+        assert_eq!(channel_ids(&resolver, "caracol"), ["four", "six"]);
+    }
+
+    #[tokio::test]
+    async fn test_failed_reload_keeps_manifest() {
+        let base = serve_channels().await;
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(dir.path().join("a.yaml"), source("reload-a", &base, "one")).unwrap();
+
+        let (resolver, pipeline_store) = resolver().await;
+        let mut files = HashMap::new();
+        apply_changes(dir.path(), &mut files, &resolver, &pipeline_store).await;
+        let current = resolver.manifest_store.get("reload-a").await.unwrap();
+
+        // Discovery of the new manifest fails: the current one stays
+        std::fs::write(
+            dir.path().join("a.yaml"),
+            source("reload-a", "http://127.0.0.1:9", "one"),
+        )
+        .unwrap();
+        apply_changes(dir.path(), &mut files, &resolver, &pipeline_store).await;
+
+        let stored = resolver.manifest_store.get("reload-a").await.unwrap();
+        assert!(same_manifest(&stored, &current));
+        assert_eq!(channel_ids(&resolver, "reload-a"), ["one"]);
+    }
+
+    #[test]
+    fn test_fragment_edit_reloads() {
+        let dir = tempfile::tempdir().unwrap();
+        let base = |name: &str| {
+            format!(
+                r#"
+source:
+  id: "base"
+  name: "{name}"
+discovery:
+  outputs:
+    id: "${{{{ page.id }}}}"
+  steps: []
+content:
+  outputs:
+    manifest_url: "${{{{ page.url }}}}"
+  steps: []
+"#
+            )
+        };
+        std::fs::create_dir(dir.path().join("templates")).unwrap();
+        std::fs::write(dir.path().join("templates/base.yaml"), base("Old")).unwrap();
+        std::fs::write(
+            dir.path().join("demo.yaml"),
+            "extends: templates/base.yaml\nsource:\n  id: \"demo\"\n",
+        )
+        .unwrap();
+
+        let before = snapshot(dir.path());
+        let old = load_dir(dir.path()).unwrap().manifests.remove(0);
+        assert_eq!(old.source.name, "Old");
+
+        std::fs::write(dir.path().join("templates/base.yaml"), base("Renamed")).unwrap();
+
+        assert_ne!(snapshot(dir.path()), before);
+        let new = load_dir(dir.path()).unwrap().manifests.remove(0);
+        assert_eq!(new.source.id, "demo");
+        assert_eq!(new.source.name, "Renamed");
+        assert!(!same_manifest(&old, &new));
+    }
+}
@@ -5,7 +5,12 @@ use std::time::Duration;
 use anyhow::{Result, anyhow};
 use tokio::sync::RwLock;
 
-use crate::engine::{browser::create_browser_for_phase, manifest::Manifest};
+use crate::engine::{
+    browser::{BrowserPool, create_browser_for_phase},
+    manifest::Manifest,
+    secrets::redact,
+    trace::Tracer,
+};
 
 use super::content::execute_content;
 use super::discovery::execute_discovery;
@@ -35,6 +40,10 @@ impl ManifestStore {
         manifests.insert(manifest.source.id.clone(), manifest);
     }
 
+    pub async fn remove(&self, source_id: &str) -> Option<Manifest> {
+        self.manifests.write().await.remove(source_id)
+    }
+
     pub async fn get(&self, source_id: &str) -> Option<Manifest> {
         self.manifests.read().await.get(source_id).cloned()
     }
@@ -56,18 +65,27 @@ impl Default for ManifestStore {
     Owns the manifest store and registry, and provides the high-level operations:
     - `run_initial_discovery()` — startup discovery for a single source
     - `refresh_discovery_if_needed()` — re-run discovery when expired
+    - `reload_source()` / `remove_source()` — apply manifest changes at runtime
     - `ensure_stream_info()` — on-demand content resolution with concurrent coalescing
+
+    Every phase leases its browser from the shared `browser_pool`.
 */
 pub struct Resolver {
     pub registry: Arc<ChannelRegistry>,
     pub manifest_store: Arc<ManifestStore>,
+    pub browser_pool: Arc<BrowserPool>,
 }
 
 impl Resolver {
-    pub fn new(registry: Arc<ChannelRegistry>, manifest_store: Arc<ManifestStore>) -> Self {
+    pub fn new(
+        registry: Arc<ChannelRegistry>,
+        manifest_store: Arc<ManifestStore>,
+        browser_pool: Arc<BrowserPool>,
+    ) -> Self {
         Self {
             registry,
             manifest_store,
+            browser_pool,
         }
     }
 
@@ -78,6 +96,10 @@ impl Resolver {
         then closes the browser.
     */
     pub async fn run_initial_discovery(&self, manifest: &Manifest) -> Result<()> {
+        self.run_discovery(manifest, false).await
+    }
+
+    async fn run_discovery(&self, manifest: &Manifest, keep_stream_info: bool) -> Result<()> {
         let source = &manifest.source;
         println!(
             "[resolver] Starting discovery for: {} ({})",
@@ -86,35 +108,48 @@ impl Resolver {
 
         self.registry.mark_source_loading(&source.id);
 
-        match self.run_discovery_inner(manifest).await {
+        match self.run_discovery_inner(manifest, keep_stream_info).await {
             Ok(()) => Ok(()),
             Err(e) => {
-                eprintln!("[resolver] Discovery failed for '{}': {}", source.id, e);
+                eprintln!(
+                    "[resolver] Discovery failed for '{}': {}",
+                    source.id,
+                    redact(&e.to_string())
+                );
                 self.registry.mark_source_failed(&source.id, e.to_string());
                 Err(e)
             }
         }
     }
 
-    async fn run_discovery_inner(&self, manifest: &Manifest) -> Result<()> {
+    async fn run_discovery_inner(&self, manifest: &Manifest, keep_stream_info: bool) -> Result<()> {
         let source = &manifest.source;
 
         // Create browser for discovery
-        let (browser, resolved_config) =
-            create_browser_for_phase(&manifest.discovery.browser, source).await?;
-        let tab = browser
-            .get_tab(0)
-            .await
-            .ok_or_else(|| anyhow!("No browser tab available"))?;
+        let browser = create_browser_for_phase(
+            &self.browser_pool,
+            &manifest.discovery.browser,
+            &manifest.discovery.steps,
+            source,
+        )
+        .await?;
 
-        let proxy = resolved_config.proxy.as_deref();
+        let proxy = browser.config.proxy.as_deref();
 
         // Run discovery phase
-        let discovery_result = execute_discovery(&manifest.discovery, &tab, source, proxy).await?;
-
-        // Close discovery browser
-        let _ = tab.navigate("about:blank").await;
-        let _ = browser.close().await;
+        let discovery_result = execute_discovery(
+            &manifest.discovery,
+            browser.tab(),
+            source,
+            proxy,
+            None,
+            None,
+        )
+        .await;
+
+        // Return discovery browser to the pool
+        browser.close().await;
+        let discovery_result = discovery_result?;
 
         let mut channels = discovery_result.channels;
         println!("[resolver] Discovery found {} channels", channels.len());
@@ -124,33 +159,45 @@ impl Resolver {
             channels = apply_process_phase(channels, process);
         }
 
-        // Run metadata phase if present (creates its own browser)
+        // Run metadata phase if present (leases its own browser)
         let mut channel_programmes = HashMap::new();
         if let Some(ref metadata_phase) = manifest.metadata {
             println!("[resolver] Running metadata phase...");
 
-            let (meta_browser, meta_config) =
-                create_browser_for_phase(&metadata_phase.browser, source).await?;
-            let meta_tab = meta_browser
-                .get_tab(0)
-                .await
-                .ok_or_else(|| anyhow!("No browser tab available for metadata"))?;
-
-            let meta_proxy = meta_config.proxy.as_deref();
-            match execute_metadata(metadata_phase, &meta_tab, meta_proxy).await {
+            let meta_browser = create_browser_for_phase(
+                &self.browser_pool,
+                &metadata_phase.browser,
+                &metadata_phase.steps,
+                source,
+            )
+            .await?;
+
+            let meta_proxy = meta_browser.config.proxy.as_deref();
+            match execute_metadata(
+                metadata_phase,
+                meta_browser.tab(),
+                source,
+                meta_proxy,
+                None,
+                None,
+            )
+            .await
+            {
                 Ok(result) => {
                     channel_programmes = result.programmes_by_channel;
                     self.registry
                         .set_metadata_expiration(&source.id, result.expires_at);
                 }
                 Err(e) => {
-                    eprintln!("[resolver] Metadata phase failed: {}", e);
+                    eprintln!(
+                        "[resolver] Metadata phase failed: {}",
+                        redact(&e.to_string())
+                    );
                     // Not fatal — continue without metadata
                 }
             }
 
-            let _ = meta_tab.navigate("about:blank").await;
-            let _ = meta_browser.close().await;
+            meta_browser.close().await;
         }
 
         // Build channel entries and register
@@ -173,12 +220,60 @@ impl Resolver {
             source.id
         );
 
-        self.registry
-            .register_source(&source.id, entries, discovery_result.expires_at);
+        if keep_stream_info {
+            self.registry
+                .reregister_source(&source.id, entries, discovery_result.expires_at);
+        } else {
+            self.registry
+                .register_source(&source.id, entries, discovery_result.expires_at);
+        }
 
         Ok(())
     }
 
+    /**
+        Replace a source's manifest and re-run its discovery.
+
+        Channels that survive keep their resolved stream info, so their
+        pipelines carry on undisturbed. The manifest is only stored once
+        discovery succeeded; until then the current one stays in use. Returns
+        the IDs of channels that no longer exist, whose pipelines the caller
+        should stop.
+    */
+    pub async fn reload_source(&self, manifest: Manifest) -> Result<Vec<ChannelId>> {
+        let source_id = manifest.source.id.clone();
+        let previous = self.registry.list_by_source(&source_id);
+
+        self.run_discovery(&manifest, true).await?;
+        self.manifest_store.add(manifest).await;
+
+        Ok(previous
+            .into_iter()
+            .map(|entry| ChannelId::new(&source_id, &entry.channel.id))
+            .filter(|id| self.registry.get(id).is_none())
+            .collect())
+    }
+
+    /**
+        Remove a source and all of its channels.
+
+        Returns the IDs of the removed channels, whose pipelines the caller
+        should stop.
+    */
+    pub async fn remove_source(&self, source_id: &str) -> Vec<ChannelId> {
+        self.manifest_store.remove(source_id).await;
+
+        let removed = self
+            .registry
+            .list_by_source(source_id)
+            .into_iter()
+            .map(|entry| ChannelId::new(source_id, &entry.channel.id))
+            .collect();
+        self.registry.remove_source(source_id);
+
+        removed
+    }
+
     /**
         Re-run discovery for a source if its results have expired.
     */
@@ -228,15 +323,25 @@ impl Resolver {
             source_id
         );
 
-        let (browser, config) =
-            create_browser_for_phase(&metadata_phase.browser, &manifest.source).await?;
-        let tab = browser
-            .get_tab(0)
-            .await
-            .ok_or_else(|| anyhow!("No browser tab available for metadata refresh"))?;
-
-        let proxy = config.proxy.as_deref();
-        match execute_metadata(metadata_phase, &tab, proxy).await {
+        let browser = create_browser_for_phase(
+            &self.browser_pool,
+            &metadata_phase.browser,
+            &metadata_phase.steps,
+            &manifest.source,
+        )
+        .await?;
+
+        let proxy = browser.config.proxy.as_deref();
+        match execute_metadata(
+            metadata_phase,
+            browser.tab(),
+            &manifest.source,
+            proxy,
+            None,
+            None,
+        )
+        .await
+        {
             Ok(result) => {
                 self.registry
                     .update_programmes(source_id, result.programmes_by_channel);
@@ -257,14 +362,14 @@ impl Resolver {
             Err(e) => {
                 eprintln!(
                     "[resolver] Metadata refresh failed for '{}': {}",
-                    source_id, e
+                    source_id,
+                    redact(&e.to_string())
                 );
                 // Not fatal — keep existing stale data rather than wiping it
             }
         }
 
-        let _ = tab.navigate("about:blank").await;
-        let _ = browser.close().await;
+        browser.close().await;
 
         Ok(true)
     }
@@ -347,24 +452,34 @@ impl Resolver {
         let channel_name = entry.channel.name.as_deref().unwrap_or(&entry.channel.id);
         println!("[resolver] Resolving content for '{}'...", channel_name);
 
-        let (browser, resolved_config) =
-            create_browser_for_phase(&manifest.content.browser, &manifest.source).await?;
-        let tab = browser
-            .get_tab(0)
-            .await
-            .ok_or_else(|| anyhow!("No browser tab available for content"))?;
-
-        let proxy = resolved_config.proxy.as_deref();
-        let stream_info = execute_content(&manifest.content, &tab, &entry.channel, proxy).await?;
+        let browser = create_browser_for_phase(
+            &self.browser_pool,
+            &manifest.content.browser,
+            &manifest.content.steps,
+            &manifest.source,
+        )
+        .await?;
+
+        let proxy = browser.config.proxy.as_deref();
+        let tracer = Tracer::new("content");
+        let stream_info = execute_content(
+            &manifest.content,
+            browser.tab(),
+            &entry.channel,
+            proxy,
+            None,
+            Some(&tracer),
+        )
+        .await;
+        browser.close().await;
+        self.registry.set_content_trace(id, tracer.trace());
+        let stream_info = stream_info?;
 
         println!(
             "[resolver] Content resolved for '{}': {}",
             channel_name, stream_info.manifest_url
         );
 
-        let _ = tab.navigate("about:blank").await;
-        let _ = browser.close().await;
-
         Ok(stream_info)
     }
 
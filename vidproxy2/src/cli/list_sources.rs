@@ -1,13 +1,19 @@
+use std::path::PathBuf;
+
 use anyhow::Result;
 use clap::Parser;
 
 #[derive(Parser, Debug)]
-pub struct ListSourcesCommand;
+pub struct ListSourcesCommand {
+    /// Directory of additional source manifests (overrides embedded sources by id)
+    #[arg(long)]
+    pub sources_dir: Option<PathBuf>,
+}
 
 impl ListSourcesCommand {
     pub async fn run(self) -> Result<()> {
         println!("Available sources:");
-        for name in crate::engine::list_sources()? {
+        for name in crate::engine::list_sources(self.sources_dir.as_deref())? {
             println!("  - {}", name);
         }
         Ok(())
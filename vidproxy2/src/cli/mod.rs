@@ -2,12 +2,18 @@ use anyhow::Result;
 use clap::{Parser, Subcommand};
 
 mod list_sources;
+mod schema;
 mod serve;
+mod test_har;
 mod test_source;
+mod validate;
 
 pub use list_sources::ListSourcesCommand;
+pub use schema::SchemaCommand;
 pub use serve::ServeCommand;
+pub use test_har::TestHarCommand;
 pub use test_source::TestSourceCommand;
+pub use validate::ValidateCommand;
 
 #[derive(Parser, Debug)]
 #[command(name = "vidproxy")]
@@ -25,6 +31,12 @@ pub enum Command {
     ListSources(ListSourcesCommand),
     /// Test a source by running all phases and printing results
     TestSource(TestSourceCommand),
+    /// Run a source's request extractors against a HAR capture
+    TestHar(TestHarCommand),
+    /// Check source manifests for errors without running them
+    Validate(ValidateCommand),
+    /// Print the JSON Schema for source manifests
+    Schema(SchemaCommand),
 }
 
 impl Args {
@@ -37,6 +49,9 @@ impl Args {
             Command::Serve(cmd) => cmd.run().await,
             Command::ListSources(cmd) => cmd.run().await,
             Command::TestSource(cmd) => cmd.run().await,
+            Command::TestHar(cmd) => cmd.run().await,
+            Command::Validate(cmd) => cmd.run().await,
+            Command::Schema(cmd) => cmd.run().await,
         }
     }
 }
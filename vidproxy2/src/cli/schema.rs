@@ -0,0 +1,30 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, anyhow};
+use clap::Parser;
+
+use crate::engine::schema::manifest_schema;
+
+#[derive(Parser, Debug)]
+pub struct SchemaCommand {
+    /// Write the schema to this file instead of stdout
+    #[arg(short, long)]
+    pub output: Option<PathBuf>,
+}
+
+impl SchemaCommand {
+    pub async fn run(self) -> Result<()> {
+        let schema = serde_json::to_string_pretty(&manifest_schema())?;
+
+        match self.output {
+            Some(path) => {
+                std::fs::write(&path, schema + "\n")
+                    .map_err(|e| anyhow!("Failed to write {}: {}", path.display(), e))?;
+                println!("Wrote manifest schema to {}", path.display());
+            }
+            None => println!("{}", schema),
+        }
+
+        Ok(())
+    }
+}
@@ -1,4 +1,5 @@
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -7,6 +8,7 @@ use clap::Parser;
 use tokio::{signal, sync::watch};
 
 use crate::channel::{ChannelRegistry, ManifestStore, Resolver};
+use crate::engine::browser::{BrowserPool, BrowserPoolConfig};
 use crate::media::{PipelineConfig, PipelineStore};
 use crate::server::ImageCache;
 
@@ -31,6 +33,36 @@ pub struct ServeCommand {
     /// Startup timeout in seconds (max wait for first segment)
     #[arg(long, default_value = "30")]
     pub startup_timeout: u64,
+
+    /// Maximum number of browsers a single source may use at once
+    #[arg(long, default_value = "2")]
+    pub browsers_per_source: usize,
+
+    /// Number of phases a browser runs before it is replaced
+    #[arg(long, default_value = "20")]
+    pub browser_max_uses: u32,
+
+    /// Maximum number of idle browsers kept warm across all sources
+    #[arg(long, default_value = "4")]
+    pub max_idle_browsers: usize,
+
+    /// Idle timeout in seconds before a warm browser is closed
+    #[arg(long, default_value = "300")]
+    pub browser_idle_timeout: u64,
+
+    /// YAML file of `NAME: value` secrets for `${{secret.NAME}}` placeholders
+    #[arg(long)]
+    pub secrets: Option<PathBuf>,
+
+    /// Directory of additional source manifests (overrides embedded sources by id),
+    /// watched for changes while the server runs
+    #[arg(long)]
+    pub sources_dir: Option<PathBuf>,
+
+    /// Save a screenshot, the DOM and the requests of the page into this
+    /// directory whenever a step fails (artifacts may contain session tokens)
+    #[arg(long)]
+    pub artifacts_dir: Option<PathBuf>,
 }
 
 impl Default for ServeCommand {
@@ -41,6 +73,13 @@ impl Default for ServeCommand {
             segment_duration: 4,
             idle_timeout: 30,
             startup_timeout: 30,
+            browsers_per_source: 2,
+            browser_max_uses: 20,
+            max_idle_browsers: 4,
+            browser_idle_timeout: 300,
+            secrets: None,
+            sources_dir: None,
+            artifacts_dir: None,
         }
     }
 }
@@ -53,9 +92,17 @@ impl ServeCommand {
         // Core state
         let registry = Arc::new(ChannelRegistry::new());
         let manifest_store = Arc::new(ManifestStore::new());
+        let browser_pool = Arc::new(BrowserPool::new(BrowserPoolConfig {
+            max_per_source: self.browsers_per_source,
+            max_uses: self.browser_max_uses,
+            max_idle: self.max_idle_browsers,
+            idle_timeout: Duration::from_secs(self.browser_idle_timeout),
+        }));
+        tokio::spawn(Arc::clone(&browser_pool).reap_idle(shutdown_rx.clone()));
         let resolver = Arc::new(Resolver::new(
             Arc::clone(&registry),
             Arc::clone(&manifest_store),
+            Arc::clone(&browser_pool),
         ));
 
         // Temp directory for HLS segments
@@ -74,12 +121,27 @@ impl ServeCommand {
         // Image cache
         let image_cache = Arc::new(ImageCache::new());
 
+        // Load secrets before manifests, which check their variables on load
+        if let Some(path) = &self.secrets {
+            let count = crate::engine::secrets::load_file(path)?;
+            println!("Loaded {} secret(s) from {}", count, path.display());
+        }
+
+        if let Some(path) = &self.artifacts_dir {
+            crate::engine::artifacts::set_dir(path)?;
+            println!("Saving step failure artifacts to {}", path.display());
+        }
+
         // Load manifests
         println!("Loading sources...");
-        let manifests = crate::engine::load_all()?;
+        let loaded = crate::engine::load_sources(self.sources_dir.as_deref())?;
+        for error in &loaded.errors {
+            eprintln!("[manifest] Skipping {}", error);
+        }
+        let manifests = loaded.manifests;
 
         if manifests.is_empty() {
-            eprintln!("No source manifests found in sources/");
+            eprintln!("No source manifests found");
             return Ok(());
         }
 
@@ -156,6 +218,16 @@ impl ServeCommand {
             });
         }
 
+        // Apply manifest changes from the sources directory live
+        if let Some(dir) = self.sources_dir.clone() {
+            tokio::spawn(crate::channel::reload::watch_sources(
+                dir,
+                Arc::clone(&resolver),
+                Arc::clone(&pipeline_store),
+                shutdown_rx.clone(),
+            ));
+        }
+
         // Wait for Ctrl+C
         signal::ctrl_c().await?;
         println!("\nShutting down...");
@@ -163,6 +235,7 @@ impl ServeCommand {
 
         pipeline_store.stop_all().await;
         let _ = server_handle.await;
+        browser_pool.close_all().await;
 
         drop(temp_dir);
 
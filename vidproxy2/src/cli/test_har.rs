@@ -0,0 +1,115 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
+use clap::Parser;
+
+use crate::engine::har::{ExtractorOutput, HarEntry, har_steps, load};
+use crate::engine::step::Step;
+
+#[derive(Parser, Debug)]
+pub struct TestHarCommand {
+    /// Source ID (or partial match)
+    pub source: String,
+
+    /// HAR file exported from the browser's dev tools
+    pub har: PathBuf,
+
+    /// Only run the step with this name
+    #[arg(long)]
+    pub step: Option<String>,
+
+    /// Number of array items to print per extractor
+    #[arg(long, default_value_t = 3)]
+    pub items: usize,
+
+    /// Directory of additional source manifests (overrides embedded sources by id)
+    #[arg(long)]
+    pub sources_dir: Option<PathBuf>,
+}
+
+impl TestHarCommand {
+    pub async fn run(self) -> Result<()> {
+        let manifest = crate::engine::find_by_id(&self.source, self.sources_dir.as_deref())?;
+        let entries = load(&self.har)?;
+
+        println!(
+            "Testing source: {} ({}) against {} HAR entries",
+            manifest.source.name,
+            manifest.source.id,
+            entries.len()
+        );
+
+        let mut phases: Vec<(&str, &[Step])> = vec![("Discovery", &manifest.discovery.steps)];
+        if let Some(metadata) = &manifest.metadata {
+            phases.push(("Metadata", &metadata.steps));
+        }
+        phases.push(("Content", &manifest.content.steps));
+
+        let mut step_count = 0;
+        for (phase, steps) in phases {
+            for step in har_steps(steps)? {
+                if self.step.as_deref().is_some_and(|name| name != step.name) {
+                    continue;
+                }
+                step_count += 1;
+
+                println!();
+                println!("=== {} / {} '{}' ===", phase, step.kind, step.name);
+
+                let matching: Vec<(usize, &HarEntry)> = entries
+                    .iter()
+                    .enumerate()
+                    .filter(|(_, entry)| step.matches(entry))
+                    .collect();
+                if matching.is_empty() {
+                    println!("  No matching entries");
+                    continue;
+                }
+
+                let mut names: Vec<&String> = step.extractors.keys().collect();
+                names.sort();
+
+                for (index, entry) in matching {
+                    println!(
+                        "  #{} {} {} ({})",
+                        index + 1,
+                        entry.method,
+                        entry.url,
+                        entry.status
+                    );
+                    for name in &names {
+                        match step.extract(&step.extractors[*name], entry) {
+                            Ok(ExtractorOutput::Value(value)) => {
+                                println!("    {}: {:?}", name, value)
+                            }
+                            Ok(ExtractorOutput::Items(items)) => {
+                                println!("    {}: {} item(s)", name, items.len());
+                                for item in items.iter().take(self.items) {
+                                    let mut fields: Vec<_> = item.iter().collect();
+                                    fields.sort();
+                                    let fields: Vec<String> = fields
+                                        .into_iter()
+                                        .map(|(k, v)| format!("{}={:?}", k, v.as_deref()))
+                                        .collect();
+                                    println!("      - {}", fields.join(", "));
+                                }
+                                if items.len() > self.items {
+                                    println!("      ... and {} more", items.len() - self.items);
+                                }
+                            }
+                            Err(e) => println!("    {}: FAILED: {}", name, e),
+                        }
+                    }
+                }
+            }
+        }
+
+        if step_count == 0 {
+            match &self.step {
+                Some(name) => bail!("No Sniff, SniffMany or Fetch step named '{}'", name),
+                None => bail!("Source has no Sniff, SniffMany or Fetch steps"),
+            }
+        }
+        Ok(())
+    }
+}
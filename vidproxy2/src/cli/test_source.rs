@@ -1,11 +1,18 @@
-use anyhow::{Context, Result, bail};
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+
+use anyhow::{Result, bail};
 use clap::Parser;
 
 use crate::channel::{
     content::execute_content, discovery::execute_discovery, metadata::execute_metadata,
     process::apply_process_phase, types::ChannelEntry,
 };
-use crate::engine::browser::create_browser_for_phase;
+use crate::engine::browser::{BrowserPool, PhaseBrowser, create_browser_for_phase};
+use crate::engine::fixtures::Fixtures;
+use crate::engine::manifest::{BrowserConfig, Source};
+use crate::engine::step::Step;
+use crate::engine::trace::Tracer;
 
 #[derive(Parser, Debug)]
 pub struct TestSourceCommand {
@@ -15,13 +22,45 @@ pub struct TestSourceCommand {
     /// Skip the content phase (only run discovery + metadata)
     #[arg(long)]
     pub skip_content: bool,
+
+    /// YAML file of `NAME: value` secrets for `${{secret.NAME}}` placeholders
+    #[arg(long)]
+    pub secrets: Option<PathBuf>,
+
+    /// Directory of additional source manifests (overrides embedded sources by id)
+    #[arg(long)]
+    pub sources_dir: Option<PathBuf>,
+
+    /// Record every response, sniffed request and page read by the phases into
+    /// this directory (credential headers, cookies and secret values are
+    /// redacted; other session tokens in URLs or bodies are kept)
+    #[arg(long, conflicts_with = "replay")]
+    pub record: Option<PathBuf>,
+
+    /// Run the phases against recordings in this directory, without a browser
+    /// or network access
+    #[arg(long)]
+    pub replay: Option<PathBuf>,
+
+    /// Save a screenshot, the DOM and the requests of the page into this
+    /// directory whenever a step fails (artifacts may contain session tokens)
+    #[arg(long)]
+    pub artifacts_dir: Option<PathBuf>,
 }
 
 impl TestSourceCommand {
     pub async fn run(self) -> Result<()> {
-        let manifest = crate::engine::find_by_id(&self.source)?;
+        if let Some(path) = &self.secrets {
+            crate::engine::secrets::load_file(path)?;
+        }
+        if let Some(path) = &self.artifacts_dir {
+            crate::engine::artifacts::set_dir(path)?;
+        }
+
+        let manifest = crate::engine::find_by_id(&self.source, self.sources_dir.as_deref())?;
         let source = &manifest.source;
         let proxy = source.proxy.as_deref();
+        let pool = Arc::new(BrowserPool::new(Default::default()));
 
         println!("Testing source: {} ({})", source.name, source.id);
         println!();
@@ -29,17 +68,32 @@ impl TestSourceCommand {
         // --- Discovery phase ---
         println!("=== Discovery Phase ===");
 
-        let (browser, _config) =
-            create_browser_for_phase(&manifest.discovery.browser, source).await?;
-        let tab = browser
-            .get_tab(0)
-            .await
-            .context("No browser tab available")?;
-
-        let discovery_result = execute_discovery(&manifest.discovery, &tab, source, proxy).await?;
-
-        drop(tab);
-        drop(browser);
+        let fixtures = self.fixtures("discovery")?;
+        let browser = self
+            .phase_browser(
+                &pool,
+                &manifest.discovery.browser,
+                &manifest.discovery.steps,
+                source,
+            )
+            .await?;
+        let tracer = Tracer::new("discovery");
+
+        let discovery_result = execute_discovery(
+            &manifest.discovery,
+            browser.tab(),
+            source,
+            proxy,
+            fixtures.as_ref(),
+            Some(&tracer),
+        )
+        .await;
+
+        browser.close().await;
+        println!("{}", tracer.trace());
+        println!();
+        self.save_recording("discovery", fixtures.as_ref())?;
+        let discovery_result = discovery_result?;
 
         let mut channels = discovery_result.channels;
 
@@ -86,14 +140,32 @@ impl TestSourceCommand {
             println!();
             println!("=== Metadata Phase ===");
 
-            let (browser, _config) =
-                create_browser_for_phase(&metadata_phase.browser, source).await?;
-            let tab = browser
-                .get_tab(0)
-                .await
-                .context("No browser tab available")?;
+            let fixtures = self.fixtures("metadata")?;
+            let browser = self
+                .phase_browser(
+                    &pool,
+                    &metadata_phase.browser,
+                    &metadata_phase.steps,
+                    source,
+                )
+                .await?;
+            let tracer = Tracer::new("metadata");
+
+            let result = execute_metadata(
+                metadata_phase,
+                browser.tab(),
+                source,
+                proxy,
+                fixtures.as_ref(),
+                Some(&tracer),
+            )
+            .await;
+            browser.close().await;
+            self.save_recording("metadata", fixtures.as_ref())?;
+            println!("{}", tracer.trace());
+            println!();
 
-            match execute_metadata(metadata_phase, &tab, proxy).await {
+            match result {
                 Ok(result) => {
                     let total: usize = result.programmes_by_channel.values().map(|p| p.len()).sum();
                     println!(
@@ -140,9 +212,6 @@ impl TestSourceCommand {
                     println!("  FAILED: {}", e);
                 }
             }
-
-            drop(tab);
-            drop(browser);
         }
 
         // --- Live check ---
@@ -212,17 +281,44 @@ impl TestSourceCommand {
             println!();
             println!("=== Content Phase ===");
 
-            let (browser, _config) =
-                create_browser_for_phase(&manifest.content.browser, source).await?;
-            let tab = browser
-                .get_tab(0)
-                .await
-                .context("No browser tab available")?;
+            let browser = self
+                .phase_browser(
+                    &pool,
+                    &manifest.content.browser,
+                    &manifest.content.steps,
+                    source,
+                )
+                .await?;
 
             for ch in &channels {
                 let label = ch.name.as_deref().unwrap_or(&ch.id);
+                let name = content_recording_name(&ch.id);
+                let fixtures = match self.fixtures(&name) {
+                    Ok(fixtures) => fixtures,
+                    Err(e) => {
+                        fail_count += 1;
+                        println!("  FAIL  {}", label);
+                        println!("        {}", e);
+                        continue;
+                    }
+                };
+
+                let tracer = Tracer::new("content");
+                let result = execute_content(
+                    &manifest.content,
+                    browser.tab(),
+                    ch,
+                    proxy,
+                    fixtures.as_ref(),
+                    Some(&tracer),
+                )
+                .await;
+                if let Err(e) = self.save_recording(&name, fixtures.as_ref()) {
+                    browser.close().await;
+                    return Err(e);
+                }
 
-                match execute_content(&manifest.content, &tab, ch, proxy).await {
+                match result {
                     Ok(info) => {
                         success_count += 1;
                         println!("  OK  {}", label);
@@ -248,11 +344,13 @@ impl TestSourceCommand {
                         println!("        {}", e);
                     }
                 }
+                println!("{}", tracer.trace());
+                println!();
             }
 
-            drop(tab);
-            drop(browser);
+            browser.close().await;
         }
+        pool.close_all().await;
 
         // --- Summary ---
         println!();
@@ -273,4 +371,63 @@ impl TestSourceCommand {
 
         Ok(())
     }
+
+    /**
+        Browser for a phase. Replays run without one.
+    */
+    async fn phase_browser(
+        &self,
+        pool: &Arc<BrowserPool>,
+        browser_config: &BrowserConfig,
+        steps: &[Step],
+        source: &Source,
+    ) -> Result<PhaseBrowser> {
+        if self.replay.is_some() {
+            return Ok(PhaseBrowser::without_browser(
+                browser_config.resolve(source),
+            ));
+        }
+        create_browser_for_phase(pool, browser_config, steps, source).await
+    }
+
+    /**
+        Fixtures for a phase: a new recording, the recording to replay, or
+        none for a live run.
+    */
+    fn fixtures(&self, name: &str) -> Result<Option<Fixtures>> {
+        if let Some(dir) = &self.replay {
+            return Fixtures::load(&recording_path(dir, name)).map(Some);
+        }
+        Ok(self.record.as_ref().map(|_| Fixtures::record()))
+    }
+
+    fn save_recording(&self, name: &str, fixtures: Option<&Fixtures>) -> Result<()> {
+        if let (Some(dir), Some(fixtures)) = (&self.record, fixtures) {
+            let path = recording_path(dir, name);
+            fixtures.save(&path)?;
+            println!("  Recorded {}", path.display());
+        }
+        Ok(())
+    }
+}
+
+fn recording_path(dir: &Path, name: &str) -> PathBuf {
+    dir.join(format!("{}.json", name))
+}
+
+/**
+    Recording name for a channel's content phase, safe to use as a file name.
+*/
+fn content_recording_name(channel_id: &str) -> String {
+    let id: String = channel_id
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    format!("content-{}", id)
 }
@@ -0,0 +1,96 @@
+use std::path::PathBuf;
+
+use anyhow::{Result, bail};
+use clap::Parser;
+
+use crate::engine::fragments::Fragments;
+use crate::engine::manifest::embedded_files;
+use crate::engine::validate::{Severity, validate_file};
+
+#[derive(Parser, Debug)]
+pub struct ValidateCommand {
+    /// Manifest files or directories to validate (defaults to the embedded sources)
+    pub files: Vec<PathBuf>,
+}
+
+impl ValidateCommand {
+    pub async fn run(self) -> Result<()> {
+        let embedded = self.files.is_empty();
+        let files = if embedded {
+            embedded_files()
+        } else {
+            let mut files = Vec::new();
+            for path in expand_paths(self.files)? {
+                let content = std::fs::read_to_string(&path)
+                    .map_err(|e| anyhow::anyhow!("Failed to read {}: {}", path.display(), e))?;
+                files.push((path, content));
+            }
+            files
+        };
+
+        let mut error_count = 0;
+        for (path, content) in &files {
+            // Files on disk name fragments relative to their own directory
+            let fragments = match path.parent() {
+                Some(dir) if !embedded => Fragments::in_dir(dir),
+                _ => Fragments::embedded(),
+            };
+            for diagnostic in validate_file(content, &fragments) {
+                match &diagnostic.fragment {
+                    // Problems in a base or fragment point into that file
+                    Some(fragment) => {
+                        let fragment_path = match path.parent() {
+                            Some(dir) if !embedded && dir.join(fragment).is_file() => {
+                                dir.join(fragment)
+                            }
+                            _ => PathBuf::from(fragment),
+                        };
+                        println!(
+                            "{}:{} (used by {})",
+                            fragment_path.display(),
+                            diagnostic,
+                            path.display()
+                        );
+                    }
+                    None => println!("{}:{}", path.display(), diagnostic),
+                }
+                if diagnostic.severity == Severity::Error {
+                    error_count += 1;
+                }
+            }
+        }
+
+        if error_count > 0 {
+            bail!(
+                "{} error(s) in {} manifest file(s)",
+                error_count,
+                files.len()
+            );
+        }
+
+        println!("{} manifest file(s) OK", files.len());
+        Ok(())
+    }
+}
+
+/**
+    Expand directories into the `.yaml`/`.yml` files they contain.
+*/
+fn expand_paths(paths: Vec<PathBuf>) -> Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+
+    for path in paths {
+        if path.is_dir() {
+            let mut entries: Vec<PathBuf> = std::fs::read_dir(&path)?
+                .filter_map(|entry| entry.ok().map(|e| e.path()))
+                .filter(|p| p.is_file() && p.extension().is_some_and(|e| e == "yaml" || e == "yml"))
+                .collect();
+            entries.sort();
+            files.extend(entries);
+        } else {
+            files.push(path);
+        }
+    }
+
+    Ok(files)
+}
@@ -0,0 +1,188 @@
+use std::path::{Path, PathBuf};
+use std::sync::RwLock;
+use std::time::Duration;
+
+use anyhow::{Result, anyhow};
+use chrome_browser::{ChromeBrowserTab, NetworkRequestStream};
+use futures::FutureExt;
+
+use super::secrets::redact;
+
+/**
+    Process-wide directory that failure artifacts are written to. Nothing
+    is captured until it is set.
+*/
+static DIR: RwLock<Option<PathBuf>> = RwLock::new(None);
+
+/// How long to wait for the page when capturing, so a hung page cannot
+/// hold up the error
+const CAPTURE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Failures captured under the same name within one second
+const MAX_CAPTURES_PER_NAME: u32 = 100;
+
+/**
+    Write failure artifacts into `dir`, creating it if needed.
+*/
+pub fn set_dir(dir: &Path) -> Result<()> {
+    std::fs::create_dir_all(dir)
+        .map_err(|e| anyhow!("Failed to create artifacts directory {:?}: {}", dir, e))?;
+    *DIR.write().expect("artifacts lock poisoned") = Some(dir.to_path_buf());
+    Ok(())
+}
+
+pub fn dir() -> Option<PathBuf> {
+    DIR.read().expect("artifacts lock poisoned").clone()
+}
+
+/**
+    Every request a tab made during a phase.
+
+    Reads its own subscription to the tab's requests, so logging never
+    takes a request from a `Sniff` step waiting for it.
+*/
+pub(super) struct RequestLog {
+    stream: NetworkRequestStream,
+    requests: Vec<String>,
+}
+
+impl RequestLog {
+    pub fn new(tab: &ChromeBrowserTab) -> Self {
+        Self {
+            stream: tab.network().requests(),
+            requests: Vec::new(),
+        }
+    }
+
+    /**
+        Take the requests that arrived since the last call, without waiting
+        for more.
+    */
+    fn drain(&mut self) -> &[String] {
+        while let Some(Some(request)) = self.stream.next().now_or_never() {
+            self.requests
+                .push(format!("{} {}", request.method(), redact(request.url())));
+        }
+        &self.requests
+    }
+}
+
+/**
+    Save what the page looked like when a step failed: a screenshot, the
+    serialized DOM, the requests seen during the phase and the error.
+
+    Returns the directory the artifacts were written to, or `None` if it
+    could not be created. Each artifact is captured independently, so a
+    page that cannot be screenshotted still gets its DOM saved.
+*/
+pub(super) async fn capture(
+    tab: &ChromeBrowserTab,
+    requests: Option<&mut RequestLog>,
+    label: &str,
+    error: &str,
+) -> Option<PathBuf> {
+    let base = dir()?;
+    let dir = match create_capture_dir(&base, label) {
+        Ok(dir) => dir,
+        Err(e) => {
+            eprintln!("[artifacts] {}", e);
+            return None;
+        }
+    };
+
+    let mut written = 0;
+    let mut write = |name: &str, content: &[u8]| match std::fs::write(dir.join(name), content) {
+        Ok(()) => written += 1,
+        Err(e) => eprintln!("[artifacts] Failed to write {}: {}", name, e),
+    };
+
+    write("error.txt", redact(error).as_bytes());
+
+    match tokio::time::timeout(CAPTURE_TIMEOUT, tab.screenshot()).await {
+        Ok(Ok(png)) => write("screenshot.png", &png),
+        Ok(Err(e)) => eprintln!("[artifacts] Failed to take screenshot: {}", e),
+        Err(_) => eprintln!("[artifacts] Timed out taking screenshot"),
+    }
+
+    let dom = tab.eval_json("document.documentElement.outerHTML", false);
+    match tokio::time::timeout(CAPTURE_TIMEOUT, dom).await {
+        Ok(Ok(serde_json::Value::String(html))) => write("dom.html", redact(&html).as_bytes()),
+        Ok(Ok(other)) => write("dom.html", redact(&other.to_string()).as_bytes()),
+        Ok(Err(e)) => eprintln!("[artifacts] Failed to read DOM: {}", e),
+        Err(_) => eprintln!("[artifacts] Timed out reading DOM"),
+    }
+
+    if let Some(requests) = requests {
+        let mut list = requests.drain().join("\n");
+        list.push('\n');
+        write("requests.txt", list.as_bytes());
+    }
+
+    println!(
+        "[artifacts] Saved {} artifact(s) to {}",
+        written,
+        dir.display()
+    );
+    Some(dir)
+}
+
+/**
+    Create a new directory for one failure, named by time and `label`.
+*/
+fn create_capture_dir(base: &Path, label: &str) -> Result<PathBuf> {
+    let label: String = label
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect();
+    let name = format!(
+        "{}-{}",
+        crate::util::time::now().format("%Y%m%d-%H%M%S"),
+        label
+    );
+
+    for attempt in 1..=MAX_CAPTURES_PER_NAME {
+        let dir = match attempt {
+            1 => base.join(&name),
+            n => base.join(format!("{}-{}", name, n)),
+        };
+        match std::fs::create_dir(&dir) {
+            Ok(()) => return Ok(dir),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => {
+                return Err(anyhow!(
+                    "Failed to create artifacts directory {:?}: {}",
+                    dir,
+                    e
+                ));
+            }
+        }
+    }
+    Err(anyhow!(
+        "Too many artifact directories named {:?} in {:?}",
+        name,
+        base
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_create_capture_dir() {
+        let base = tempfile::tempdir().unwrap();
+        let first = create_capture_dir(base.path(), "espn/open player").unwrap();
+        let second = create_capture_dir(base.path(), "espn/open player").unwrap();
+
+        let name = first.file_name().unwrap().to_str().unwrap();
+        assert!(name.ends_with("-espn_open_player"), "{}", name);
+        assert!(first.is_dir() && second.is_dir());
+        assert_ne!(first, second);
+    }
+}
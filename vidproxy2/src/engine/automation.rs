@@ -0,0 +1,598 @@
+use anyhow::{Result, anyhow};
+use chrome_browser::ChromeBrowserTab;
+
+use super::executor::apply_wait_condition;
+use super::interpolate::InterpolationContext;
+use super::secrets::redact;
+use super::step::{AutomationAction, WaitCondition};
+
+/**
+    Execute the actions of an Automation step in order.
+
+    Actions run in the top-level page until a `SwitchFrame` enters an iframe.
+    Clicks in the top-level page, key presses and hovers go through the
+    browser's input events, so pages see them as trusted user input; the
+    rest (and clicks inside a frame) is driven by JavaScript in the target
+    document.
+*/
+pub(super) async fn execute_automation(
+    actions: &[AutomationAction],
+    tab: &ChromeBrowserTab,
+    context: &InterpolationContext,
+) -> Result<()> {
+    // Selector chain from the top-level page down to the current frame
+    let mut frames: Vec<String> = Vec::new();
+
+    for action in actions {
+        let wait_for = match action {
+            AutomationAction::Click { selector, wait_for } => {
+                let selector = context.interpolate(selector)?;
+                println!("[executor] Clicking element: {}", redact(&selector));
+                click(tab, &frames, &selector).await?;
+                wait_for.as_ref()
+            }
+            AutomationAction::ClickIframe { selector, wait_for } => {
+                let selector = context.interpolate(selector)?;
+                println!("[executor] Clicking iframe: {}", redact(&selector));
+                click(tab, &frames, &selector).await?;
+                wait_for.as_ref()
+            }
+            AutomationAction::Type {
+                selector,
+                text,
+                append,
+                wait_for,
+            } => {
+                let selector = context.interpolate(selector)?;
+                let text = context.interpolate(text)?;
+                // The text is not logged, it is often a credential
+                println!("[executor] Typing into: {}", redact(&selector));
+                wait_for_element(tab, &frames, &selector).await?;
+                run_script(tab, &frames, Some(&selector), &type_body(&text, *append)).await?;
+                wait_for.as_ref()
+            }
+            AutomationAction::PressKey {
+                key,
+                selector,
+                wait_for,
+            } => {
+                let key = context.interpolate(key)?;
+                let selector = selector
+                    .as_deref()
+                    .map(|s| context.interpolate(s))
+                    .transpose()?;
+                match &selector {
+                    Some(selector) => {
+                        println!(
+                            "[executor] Pressing key '{}' on: {}",
+                            redact(&key),
+                            redact(selector)
+                        );
+                        wait_for_element(tab, &frames, selector).await?;
+                    }
+                    None => println!("[executor] Pressing key '{}'", redact(&key)),
+                }
+                let definition = key_definition(&key)?;
+                // Key events go to whatever has focus
+                if let Some(selector) = &selector {
+                    run_script(tab, &frames, Some(selector), "el.focus();").await?;
+                }
+                for params in key_events(&definition) {
+                    tab.cdp("Input.dispatchKeyEvent", params).await?;
+                }
+                wait_for.as_ref()
+            }
+            AutomationAction::Hover { selector, wait_for } => {
+                let selector = context.interpolate(selector)?;
+                println!("[executor] Hovering over: {}", redact(&selector));
+                wait_for_element(tab, &frames, &selector).await?;
+                let center = run_script(tab, &frames, Some(&selector), ELEMENT_CENTER).await?;
+                let (Some(x), Some(y)) = (center["x"].as_f64(), center["y"].as_f64()) else {
+                    return Err(anyhow!("Could not locate '{}' on the page", selector));
+                };
+                tab.cdp("Input.dispatchMouseEvent", mouse_move(x, y))
+                    .await?;
+                wait_for.as_ref()
+            }
+            AutomationAction::Scroll {
+                selector,
+                x,
+                y,
+                wait_for,
+            } => {
+                let selector = selector
+                    .as_deref()
+                    .map(|s| context.interpolate(s))
+                    .transpose()?;
+                let body = scroll_body(selector.is_some(), *x, *y)?;
+                match &selector {
+                    Some(selector) => {
+                        println!("[executor] Scrolling to: {}", redact(selector));
+                        wait_for_element(tab, &frames, selector).await?;
+                    }
+                    None => println!(
+                        "[executor] Scrolling by: {}, {}",
+                        x.unwrap_or(0.0),
+                        y.unwrap_or(0.0)
+                    ),
+                }
+                run_script(tab, &frames, selector.as_deref(), &body).await?;
+                wait_for.as_ref()
+            }
+            AutomationAction::SelectOption {
+                selector,
+                value,
+                label,
+                wait_for,
+            } => {
+                let selector = context.interpolate(selector)?;
+                let value = value
+                    .as_deref()
+                    .map(|v| context.interpolate(v))
+                    .transpose()?;
+                let label = label
+                    .as_deref()
+                    .map(|l| context.interpolate(l))
+                    .transpose()?;
+                let body =
+                    select_option_body(value.as_deref(), label.as_deref()).ok_or_else(|| {
+                        anyhow!(
+                            "SelectOption on '{}' needs a value or a label",
+                            redact(&selector)
+                        )
+                    })?;
+                println!("[executor] Selecting option in: {}", redact(&selector));
+                wait_for_element(tab, &frames, &selector).await?;
+                run_script(tab, &frames, Some(&selector), &body).await?;
+                wait_for.as_ref()
+            }
+            AutomationAction::WaitFor { condition } => Some(condition),
+            AutomationAction::SwitchFrame { selector, wait_for } => {
+                match selector {
+                    Some(selector) => {
+                        let selector = context.interpolate(selector)?;
+                        println!("[executor] Switching to frame: {}", redact(&selector));
+                        wait_for_element(tab, &frames, &selector).await?;
+                        run_script(tab, &frames, Some(&selector), ENTER_FRAME).await?;
+                        frames.push(selector);
+                    }
+                    None => {
+                        println!("[executor] Switching to top-level page");
+                        frames.clear();
+                    }
+                }
+                wait_for.as_ref()
+            }
+        };
+
+        if let Some(wait_condition) = wait_for {
+            apply_frame_wait_condition(wait_condition, tab, &frames, context).await?;
+        }
+    }
+
+    Ok(())
+}
+
+async fn click(tab: &ChromeBrowserTab, frames: &[String], selector: &str) -> Result<()> {
+    if frames.is_empty() {
+        let element = tab.wait_for_selector(selector).await?;
+        element.click().await?;
+    } else {
+        wait_for_element(tab, frames, selector).await?;
+        run_script(tab, frames, Some(selector), "el.click();").await?;
+    }
+    Ok(())
+}
+
+/**
+    Wait for a selector to match in the current frame.
+*/
+async fn wait_for_element(tab: &ChromeBrowserTab, frames: &[String], selector: &str) -> Result<()> {
+    if frames.is_empty() {
+        tab.wait_for_selector(selector).await?;
+    } else {
+        tab.wait_for_function(element_exists(frames, selector))
+            .await?;
+    }
+    Ok(())
+}
+
+/**
+    Apply a wait condition, resolving its selector in the current frame.
+
+    Functions are always evaluated in the top-level page.
+*/
+async fn apply_frame_wait_condition(
+    wait_for: &WaitCondition,
+    tab: &ChromeBrowserTab,
+    frames: &[String],
+    context: &InterpolationContext,
+) -> Result<()> {
+    if frames.is_empty() {
+        return apply_wait_condition(wait_for, tab, context).await;
+    }
+
+    if let Some(selector_template) = &wait_for.selector {
+        let selector = context.interpolate(selector_template)?;
+        println!("[executor] Waiting for selector: {}", redact(&selector));
+        wait_for_element(tab, frames, &selector).await?;
+    }
+
+    let remaining = WaitCondition {
+        selector: None,
+        ..wait_for.clone()
+    };
+    apply_wait_condition(&remaining, tab, context).await
+}
+
+/**
+    Run `body` against the element matching `selector` in the current frame,
+    or the focused element if no selector is given.
+
+    The body sees `doc` and `el`, can fail by returning `{ error: "..." }`
+    and can return a result of its own instead of `{ ok: true }`.
+*/
+async fn run_script(
+    tab: &ChromeBrowserTab,
+    frames: &[String],
+    selector: Option<&str>,
+    body: &str,
+) -> Result<serde_json::Value> {
+    let script = element_script(frames, selector, body);
+    let result = tab.eval_json(script, true).await?;
+
+    if let Some(error) = result.get("error").and_then(|e| e.as_str()) {
+        return Err(anyhow!(
+            "Automation on '{}' failed: {}",
+            redact(selector.unwrap_or("focused element")),
+            error
+        ));
+    }
+    Ok(result)
+}
+
+fn element_script(frames: &[String], selector: Option<&str>, body: &str) -> String {
+    let element = match selector {
+        Some(selector) => format!("doc.querySelector({})", js_string(selector)),
+        None => "doc.activeElement || doc.body".to_string(),
+    };
+
+    format!(
+        r#"(() => {{
+  try {{
+    const doc = {doc};
+    const el = {element};
+    if (!el) return {{ error: "Element not found" }};
+    {body}
+    return {{ ok: true }};
+  }} catch (e) {{
+    return {{ error: String((e && e.message) || e) }};
+  }}
+}})()"#,
+        doc = frame_document(frames),
+    )
+}
+
+/**
+    JavaScript expression for the document of the current frame.
+*/
+fn frame_document(frames: &[String]) -> String {
+    if frames.is_empty() {
+        return "document".to_string();
+    }
+
+    format!(
+        r#"(() => {{
+    let doc = document;
+    for (const selector of {frames}) {{
+      const frame = doc.querySelector(selector);
+      if (!frame || !frame.contentDocument) throw new Error("Frame not accessible: " + selector);
+      doc = frame.contentDocument;
+    }}
+    return doc;
+  }})()"#,
+        frames = serde_json::Value::from(frames.to_vec()),
+    )
+}
+
+/**
+    JavaScript expression that is true once `selector` matches in the
+    current frame.
+*/
+fn element_exists(frames: &[String], selector: &str) -> String {
+    format!(
+        "(() => {{ try {{ return !!{}.querySelector({}); }} catch (e) {{ return false; }} }})()",
+        frame_document(frames),
+        js_string(selector)
+    )
+}
+
+const ENTER_FRAME: &str =
+    r#"if (!el.contentDocument) return { error: "Frame is not accessible (cross-origin?)" };"#;
+
+/**
+    Scroll the element into view and return its center in top-level page
+    coordinates, adding the offsets of the frames it is in.
+*/
+const ELEMENT_CENTER: &str = r#"el.scrollIntoView({ block: "center", inline: "center" });
+    const rect = el.getBoundingClientRect();
+    let x = rect.left + rect.width / 2;
+    let y = rect.top + rect.height / 2;
+    for (let win = doc.defaultView; win.frameElement; win = win.parent) {
+      const frame = win.frameElement;
+      const box = frame.getBoundingClientRect();
+      x += box.left + frame.clientLeft;
+      y += box.top + frame.clientTop;
+    }
+    return { x, y };"#;
+
+fn type_body(text: &str, append: bool) -> String {
+    format!(
+        r#"el.focus();
+    const text = {text};
+    const value = {append} ? el.value + text : text;
+    const setter = Object.getOwnPropertyDescriptor(Object.getPrototypeOf(el), "value")?.set;
+    if (setter) setter.call(el, value); else el.value = value;
+    el.dispatchEvent(new Event("input", {{ bubbles: true }}));
+    el.dispatchEvent(new Event("change", {{ bubbles: true }}));"#,
+        text = js_string(text),
+    )
+}
+
+/**
+    Scroll the element into view and then by the offset, or only by the
+    offset when there is no element.
+*/
+fn scroll_body(has_selector: bool, x: Option<f64>, y: Option<f64>) -> Result<String> {
+    let offset = match (x, y) {
+        (None, None) => None,
+        (x, y) => Some(format!(
+            "doc.defaultView.scrollBy({}, {});",
+            x.unwrap_or(0.0),
+            y.unwrap_or(0.0)
+        )),
+    };
+    match (has_selector, offset) {
+        (true, None) => Ok(r#"el.scrollIntoView({ block: "center" });"#.to_string()),
+        (true, Some(offset)) => Ok(format!(
+            r#"el.scrollIntoView({{ block: "center" }});
+    {}"#,
+            offset
+        )),
+        (false, Some(offset)) => Ok(offset),
+        (false, None) => Err(anyhow!("Scroll needs a selector or an x/y offset")),
+    }
+}
+
+/**
+    Select the option matching `value`, or else `label`. `None` when
+    neither is given.
+*/
+fn select_option_body(value: Option<&str>, label: Option<&str>) -> Option<String> {
+    let matcher = match (value, label) {
+        (Some(value), _) => format!("option => option.value === {}", js_string(value)),
+        (None, Some(label)) => format!(
+            "option => option.text.trim() === {}",
+            js_string(label.trim())
+        ),
+        (None, None) => return None,
+    };
+    Some(format!(
+        r#"const option = Array.from(el.options || []).find({matcher});
+    if (!option) return {{ error: "No matching option" }};
+    el.value = option.value;
+    option.selected = true;
+    el.dispatchEvent(new Event("input", {{ bubbles: true }}));
+    el.dispatchEvent(new Event("change", {{ bubbles: true }}));"#,
+    ))
+}
+
+/**
+    What the browser needs to know about a key to press it like a keyboard
+    would.
+*/
+#[derive(Debug, PartialEq)]
+struct KeyDefinition {
+    key: String,
+    code: String,
+    key_code: u32,
+    /// Text the key inserts; keys without it have no character input
+    text: Option<String>,
+}
+
+/**
+    Look up a named key (`Enter`, `Escape`, `ArrowDown`, ...) or a single
+    character.
+*/
+fn key_definition(key: &str) -> Result<KeyDefinition> {
+    let named = |code: &str, key_code: u32, text: Option<&str>| KeyDefinition {
+        key: key.to_string(),
+        code: code.to_string(),
+        key_code,
+        text: text.map(str::to_string),
+    };
+
+    let definition = match key {
+        "Enter" => named("Enter", 13, Some("\r")),
+        "Tab" => named("Tab", 9, None),
+        "Escape" => named("Escape", 27, None),
+        "Backspace" => named("Backspace", 8, None),
+        "Delete" => named("Delete", 46, None),
+        "Space" | " " => KeyDefinition {
+            key: " ".to_string(),
+            code: "Space".to_string(),
+            key_code: 32,
+            text: Some(" ".to_string()),
+        },
+        "ArrowLeft" => named("ArrowLeft", 37, None),
+        "ArrowUp" => named("ArrowUp", 38, None),
+        "ArrowRight" => named("ArrowRight", 39, None),
+        "ArrowDown" => named("ArrowDown", 40, None),
+        "Home" => named("Home", 36, None),
+        "End" => named("End", 35, None),
+        "PageUp" => named("PageUp", 33, None),
+        "PageDown" => named("PageDown", 34, None),
+        _ => {
+            let mut chars = key.chars();
+            let (Some(c), None) = (chars.next(), chars.next()) else {
+                return Err(anyhow!("Unknown key '{}'", key));
+            };
+            let (code, key_code) = if c.is_ascii_alphabetic() {
+                (
+                    format!("Key{}", c.to_ascii_uppercase()),
+                    c.to_ascii_uppercase() as u32,
+                )
+            } else if c.is_ascii_digit() {
+                (format!("Digit{}", c), c as u32)
+            } else {
+                (String::new(), 0)
+            };
+            KeyDefinition {
+                key: key.to_string(),
+                code,
+                key_code,
+                text: Some(key.to_string()),
+            }
+        }
+    };
+    Ok(definition)
+}
+
+/**
+    `Input.dispatchKeyEvent` parameters for pressing and releasing a key.
+*/
+fn key_events(definition: &KeyDefinition) -> [serde_json::Value; 2] {
+    let mut down = serde_json::json!({
+        // Keys that insert text are "keyDown", which also emits keypress/input
+        "type": if definition.text.is_some() { "keyDown" } else { "rawKeyDown" },
+        "key": definition.key,
+        "code": definition.code,
+        "windowsVirtualKeyCode": definition.key_code,
+    });
+    if let Some(text) = &definition.text {
+        down["text"] = text.clone().into();
+        down["unmodifiedText"] = text.clone().into();
+    }
+    let up = serde_json::json!({
+        "type": "keyUp",
+        "key": definition.key,
+        "code": definition.code,
+        "windowsVirtualKeyCode": definition.key_code,
+    });
+    [down, up]
+}
+
+/**
+    `Input.dispatchMouseEvent` parameters for moving the mouse to a point.
+*/
+fn mouse_move(x: f64, y: f64) -> serde_json::Value {
+    serde_json::json!({ "type": "mouseMoved", "x": x, "y": y, "button": "none" })
+}
+
+/**
+    Quote a string as a JavaScript string literal.
+*/
+pub(super) fn js_string(value: &str) -> String {
+    serde_json::Value::from(value).to_string()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_element_script_quoting() {
+        let script = element_script(&[], Some(r#"a[title="it's"]"#), "el.click();");
+        assert!(script.contains(r#"const doc = document;"#));
+        assert!(script.contains(r#"doc.querySelector("a[title=\"it's\"]")"#));
+
+        let frames = vec!["iframe#player".to_string(), "iframe.inner".to_string()];
+        let script = element_script(&frames, None, "");
+        assert!(script.contains(r#"for (const selector of ["iframe#player","iframe.inner"])"#));
+        assert!(script.contains("doc.activeElement || doc.body"));
+    }
+
+    #[test]
+    fn test_type() {
+        let body = type_body(r#"pa"ss"#, true);
+        assert!(body.contains(r#"const text = "pa\"ss";"#));
+        assert!(body.contains("const value = true ? el.value + text : text;"));
+        assert!(type_body("x", false).contains("const value = false ?"));
+    }
+
+    #[test]
+    fn test_press_key() {
+        let enter = key_definition("Enter").unwrap();
+        let [down, up] = key_events(&enter);
+        assert_eq!(down["type"], "keyDown");
+        assert_eq!(down["text"], "\r");
+        assert_eq!(down["windowsVirtualKeyCode"], 13);
+        assert_eq!(up["type"], "keyUp");
+        assert_eq!(up["code"], "Enter");
+
+        let [down, _] = key_events(&key_definition("Escape").unwrap());
+        assert_eq!(down["type"], "rawKeyDown");
+        assert!(down.get("text").is_none());
+
+        let a = key_definition("a").unwrap();
+        assert_eq!((a.code.as_str(), a.key_code), ("KeyA", 65));
+        assert_eq!(key_definition("7").unwrap().code, "Digit7");
+        assert_eq!(key_definition("Space").unwrap().key, " ");
+        assert!(key_definition("Enterr").is_err());
+    }
+
+    #[test]
+    fn test_hover() {
+        let frames = vec!["iframe#player".to_string()];
+        let script = element_script(&frames, Some("button.menu"), ELEMENT_CENTER);
+        assert!(script.contains(r#"doc.querySelector("button.menu")"#));
+        assert!(script.contains("win.frameElement"));
+        assert!(script.contains("return { x, y };"));
+
+        let event = mouse_move(10.5, 20.0);
+        assert_eq!(event["type"], "mouseMoved");
+        assert_eq!(
+            (event["x"].as_f64(), event["y"].as_f64()),
+            (Some(10.5), Some(20.0))
+        );
+    }
+
+    #[test]
+    fn test_scroll() {
+        let body = scroll_body(true, None, Some(-80.0)).unwrap();
+        assert!(body.starts_with("el.scrollIntoView("));
+        assert!(body.ends_with("doc.defaultView.scrollBy(0, -80);"));
+
+        assert_eq!(
+            scroll_body(true, None, None).unwrap(),
+            r#"el.scrollIntoView({ block: "center" });"#
+        );
+        assert_eq!(
+            scroll_body(false, Some(5.0), None).unwrap(),
+            "doc.defaultView.scrollBy(5, 0);"
+        );
+        assert!(scroll_body(false, None, None).is_err());
+    }
+
+    #[test]
+    fn test_select_option() {
+        let body = select_option_body(Some("co"), Some("Colombia")).unwrap();
+        assert!(body.contains(r#"find(option => option.value === "co")"#));
+
+        let body = select_option_body(None, Some(" Colombia ")).unwrap();
+        assert!(body.contains(r#"find(option => option.text.trim() === "Colombia")"#));
+
+        assert!(select_option_body(None, None).is_none());
+    }
+
+    #[test]
+    fn test_wait_for_and_switch_frame() {
+        let frames = vec!["iframe#login".to_string()];
+        let expr = element_exists(&frames, "input[name=email]");
+        assert!(expr.contains(r#"for (const selector of ["iframe#login"])"#));
+        assert!(expr.contains(r#".querySelector("input[name=email]")"#));
+
+        let script = element_script(&[], Some("iframe#login"), ENTER_FRAME);
+        assert!(script.contains("if (!el.contentDocument)"));
+    }
+}
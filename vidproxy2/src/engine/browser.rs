@@ -1,7 +1,14 @@
-use anyhow::Result;
-use chrome_browser::{ChromeBrowser, ChromeLaunchOptions};
+use std::collections::{HashMap, HashSet};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
 
-use super::manifest::{BrowserConfig, ResolvedBrowserConfig, Source};
+use anyhow::{Result, anyhow};
+use chrome_browser::{ChromeBrowser, ChromeBrowserTab, ChromeLaunchOptions};
+use serde_json::json;
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, watch};
+
+use super::manifest::{BrowserConfig, BrowserMode, ResolvedBrowserConfig, Source};
+use super::step::Step;
 
 /**
     Create a browser instance from resolved browser config.
@@ -20,13 +27,514 @@ pub async fn create_browser(config: &ResolvedBrowserConfig) -> Result<ChromeBrow
 }
 
 /**
-    Create a browser from a phase's browser config + source defaults.
+    Limits for a `BrowserPool`.
+*/
+#[derive(Debug, Clone)]
+pub struct BrowserPoolConfig {
+    /// Browsers a single source may use at once
+    pub max_per_source: usize,
+    /// Phases a browser runs before it is replaced by a fresh one
+    pub max_uses: u32,
+    /// Idle browsers kept across all configs
+    pub max_idle: usize,
+    /// Time an idle browser is kept before it is closed
+    pub idle_timeout: Duration,
+}
+
+impl Default for BrowserPoolConfig {
+    fn default() -> Self {
+        Self {
+            max_per_source: 2,
+            max_uses: 20,
+            max_idle: 4,
+            idle_timeout: Duration::from_secs(300),
+        }
+    }
+}
+
+/// How often `BrowserPool::reap_idle` closes expired idle browsers
+const REAP_INTERVAL: Duration = Duration::from_secs(30);
+
+/**
+    Warm browsers shared by all phases, keyed by their resolved config.
+
+    A browser is leased to one phase at a time and goes back to the pool
+    afterwards. Each lease runs in a fresh browser context (like an
+    incognito window) with its own tab, disposed of on release, so cookies,
+    storage and service workers never carry over between sources or phases.
+    Browsers that stop responding (crashed) or have run `max_uses` phases
+    are closed instead of reused. Each source holds at most `max_per_source`
+    leases at once; more phases for that source wait until one is released.
+
+    Idle browsers are closed after `idle_timeout`, and the longest idle ones
+    once more than `max_idle` are waiting. Browsers are kept in the order
+    they were released, each with the time it went idle.
+*/
+pub struct BrowserPool {
+    config: BrowserPoolConfig,
+    idle: Mutex<HashMap<ResolvedBrowserConfig, Vec<(Instant, PooledBrowser)>>>,
+    sources: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+struct PooledBrowser {
+    browser: ChromeBrowser,
+    uses: u32,
+}
+
+impl PooledBrowser {
+    async fn launch(config: &ResolvedBrowserConfig) -> Result<Self> {
+        Ok(Self {
+            browser: create_browser(config).await?,
+            uses: 0,
+        })
+    }
+
+    async fn is_responsive(&self) -> bool {
+        self.browser
+            .cdp("Browser.getVersion", json!({}))
+            .await
+            .is_ok()
+    }
+
+    /**
+        Open a tab in a new browser context.
+    */
+    async fn open_context(&self) -> Result<(String, ChromeBrowserTab)> {
+        let context = self
+            .browser
+            .cdp("Target.createBrowserContext", json!({}))
+            .await?;
+        let context_id = context["browserContextId"]
+            .as_str()
+            .ok_or_else(|| anyhow!("Browser did not return a context id"))?
+            .to_string();
+
+        let target = async {
+            let target = self
+                .browser
+                .cdp(
+                    "Target.createTarget",
+                    json!({ "url": "about:blank", "browserContextId": context_id }),
+                )
+                .await?;
+            let target_id = target["targetId"]
+                .as_str()
+                .ok_or_else(|| anyhow!("Browser did not return a target id"))?;
+            self.browser.attach_tab(target_id).await
+        };
+        match target.await {
+            Ok(tab) => Ok((context_id, tab)),
+            Err(e) => {
+                let _ = self.dispose_context(&context_id).await;
+                Err(e)
+            }
+        }
+    }
+
+    /**
+        Close a context along with its tabs and everything they stored.
+    */
+    async fn dispose_context(&self, context_id: &str) -> Result<()> {
+        self.browser
+            .cdp(
+                "Target.disposeBrowserContext",
+                json!({ "browserContextId": context_id }),
+            )
+            .await?;
+        Ok(())
+    }
+
+    async fn close(self) {
+        let _ = self.browser.close().await;
+    }
+}
+
+impl BrowserPool {
+    pub fn new(config: BrowserPoolConfig) -> Self {
+        Self {
+            config,
+            idle: Mutex::new(HashMap::new()),
+            sources: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /**
+        Lease a browser for a phase of a source, launching one if no warm
+        browser with the same config is available.
+    */
+    pub async fn acquire(
+        self: &Arc<Self>,
+        config: &ResolvedBrowserConfig,
+        source_id: &str,
+    ) -> Result<BrowserLease> {
+        let semaphore = Arc::clone(
+            self.sources
+                .lock()
+                .unwrap()
+                .entry(source_id.to_string())
+                .or_insert_with(|| Arc::new(Semaphore::new(self.config.max_per_source.max(1)))),
+        );
+        let permit = semaphore.acquire_owned().await?;
+
+        self.close_idle(|_| true).await;
+        while let Some(pooled) = self.take_idle(config) {
+            if !pooled.is_responsive().await {
+                println!("[browser] Discarding unresponsive browser");
+            } else {
+                match pooled.open_context().await {
+                    Ok((context_id, tab)) => {
+                        println!(
+                            "[browser] Reusing browser for '{}' ({} previous uses)",
+                            source_id, pooled.uses
+                        );
+                        return Ok(self.lease(config, pooled, context_id, tab, permit));
+                    }
+                    Err(e) => println!("[browser] Discarding browser without a new context: {}", e),
+                }
+            }
+            pooled.close().await;
+        }
+
+        println!("[browser] Launching browser for '{}'", source_id);
+        let pooled = PooledBrowser::launch(config).await?;
+        match pooled.open_context().await {
+            Ok((context_id, tab)) => Ok(self.lease(config, pooled, context_id, tab, permit)),
+            Err(e) => {
+                pooled.close().await;
+                Err(e)
+            }
+        }
+    }
+
+    /**
+        Close every idle browser. Leased browsers close or return to the
+        pool when released.
+    */
+    pub async fn close_all(&self) {
+        let idle: Vec<PooledBrowser> = self
+            .idle
+            .lock()
+            .unwrap()
+            .drain()
+            .flat_map(|(_, browsers)| browsers)
+            .map(|(_, pooled)| pooled)
+            .collect();
+        for pooled in idle {
+            pooled.close().await;
+        }
+    }
+
+    /**
+        Close idle browsers whose config is not in `configs`, e.g. after a
+        reload changed the proxy or headless setting of the sources that
+        used them.
+    */
+    pub async fn retain_configs(&self, configs: &HashSet<ResolvedBrowserConfig>) {
+        self.close_idle(|config| configs.contains(config)).await;
+    }
+
+    /**
+        Close expired idle browsers every `REAP_INTERVAL` until shutdown.
+    */
+    pub async fn reap_idle(self: Arc<Self>, mut shutdown_rx: watch::Receiver<bool>) {
+        loop {
+            tokio::select! {
+                _ = tokio::time::sleep(REAP_INTERVAL) => {}
+                _ = shutdown_rx.changed() => {
+                    if *shutdown_rx.borrow() {
+                        return;
+                    }
+                }
+            }
+            self.close_idle(|_| true).await;
+        }
+    }
+
+    /**
+        Close idle browsers that expired, are over the idle limit, or whose
+        config `keep` rejects.
+    */
+    async fn close_idle(&self, keep: impl Fn(&ResolvedBrowserConfig) -> bool) {
+        let evicted = evict(
+            &mut self.idle.lock().unwrap(),
+            keep,
+            self.config.max_idle,
+            self.config.idle_timeout,
+            Instant::now(),
+        );
+        if !evicted.is_empty() {
+            println!("[browser] Closing {} idle browser(s)", evicted.len());
+        }
+        for pooled in evicted {
+            pooled.close().await;
+        }
+    }
+
+    fn take_idle(&self, config: &ResolvedBrowserConfig) -> Option<PooledBrowser> {
+        let (_, pooled) = self.idle.lock().unwrap().get_mut(config)?.pop()?;
+        Some(pooled)
+    }
+
+    fn lease(
+        self: &Arc<Self>,
+        config: &ResolvedBrowserConfig,
+        browser: PooledBrowser,
+        context_id: String,
+        tab: ChromeBrowserTab,
+        permit: OwnedSemaphorePermit,
+    ) -> BrowserLease {
+        BrowserLease {
+            pool: Arc::clone(self),
+            config: config.clone(),
+            tab: Some(tab),
+            context_id,
+            browser: Some(browser),
+            _permit: permit,
+        }
+    }
+}
+
+/**
+    A browser leased from a `BrowserPool`, with a tab in a context of its
+    own.
+
+    Call `release` to hand it back. A lease that is dropped instead (an
+    early return or a cancelled phase) closes its browser in the background.
+*/
+pub struct BrowserLease {
+    pool: Arc<BrowserPool>,
+    config: ResolvedBrowserConfig,
+    /// `None` only while the lease is being released
+    tab: Option<ChromeBrowserTab>,
+    context_id: String,
+    /// Taken by `release`; still set when the lease is dropped instead
+    browser: Option<PooledBrowser>,
+    _permit: OwnedSemaphorePermit,
+}
+
+impl BrowserLease {
+    pub fn tab(&self) -> &ChromeBrowserTab {
+        self.tab.as_ref().expect("lease tab taken before release")
+    }
+
+    /**
+        Dispose of the lease's context and return the browser to the pool,
+        or close it if disposing failed or it has reached its use limit.
+    */
+    pub async fn release(mut self) {
+        // The tab belongs to the context and goes with it
+        drop(self.tab.take());
+        let Some(mut browser) = self.browser.take() else {
+            return;
+        };
+        browser.uses += 1;
+
+        if let Err(e) = browser.dispose_context(&self.context_id).await {
+            println!("[browser] Browser failed to reset, closing: {}", e);
+            browser.close().await;
+        } else if browser.uses >= self.pool.config.max_uses {
+            println!("[browser] Recycling browser after {} uses", browser.uses);
+            browser.close().await;
+        } else {
+            self.pool
+                .idle
+                .lock()
+                .unwrap()
+                .entry(self.config.clone())
+                .or_default()
+                .push((Instant::now(), browser));
+            self.pool.close_idle(|_| true).await;
+        }
+    }
+}
+
+/**
+    Remove the idle entries to close: those idle for `timeout` or longer,
+    those whose config `keep` rejects, then the longest idle ones past
+    `max_idle`. Entries of a config are oldest first.
+*/
+fn evict<T>(
+    idle: &mut HashMap<ResolvedBrowserConfig, Vec<(Instant, T)>>,
+    keep: impl Fn(&ResolvedBrowserConfig) -> bool,
+    max_idle: usize,
+    timeout: Duration,
+    now: Instant,
+) -> Vec<T> {
+    let mut evicted = Vec::new();
+    for (config, entries) in idle.iter_mut() {
+        let kept = keep(config);
+        let expired = entries
+            .extract_if(.., |(since, _)| {
+                !kept || now.duration_since(*since) >= timeout
+            })
+            .map(|(_, item)| item);
+        evicted.extend(expired);
+    }
+
+    let mut count: usize = idle.values().map(Vec::len).sum();
+    while count > max_idle {
+        let Some(entries) = idle
+            .values_mut()
+            .filter(|entries| !entries.is_empty())
+            .min_by_key(|entries| entries[0].0)
+        else {
+            break;
+        };
+        evicted.push(entries.remove(0).1);
+        count -= 1;
+    }
+
+    idle.retain(|_, entries| !entries.is_empty());
+    evicted
+}
+
+impl Drop for BrowserLease {
+    fn drop(&mut self) {
+        drop(self.tab.take());
+        let Some(browser) = self.browser.take() else {
+            return;
+        };
+        println!("[browser] Lease dropped without release, closing browser");
+        // Without a runtime, dropping the browser kills it
+        if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+            runtime.spawn(browser.close());
+        }
+    }
+}
+
+/**
+    The browser used by a phase, if it needs one.
+*/
+pub struct PhaseBrowser {
+    pub config: ResolvedBrowserConfig,
+    lease: Option<BrowserLease>,
+}
+
+impl PhaseBrowser {
+    /**
+        A phase that runs without a browser.
+    */
+    pub fn without_browser(config: ResolvedBrowserConfig) -> Self {
+        Self {
+            config,
+            lease: None,
+        }
+    }
+
+    /**
+        The tab to run steps in, or `None` for a browser-less phase.
+    */
+    pub fn tab(&self) -> Option<&ChromeBrowserTab> {
+        self.lease.as_ref().map(BrowserLease::tab)
+    }
+
+    /**
+        Hand the browser back to its pool, if the phase used one.
+    */
+    pub async fn close(self) {
+        if let Some(lease) = self.lease {
+            lease.release().await;
+        }
+    }
+}
+
+/**
+    Lease a browser from the pool for a phase, using its browser config +
+    source defaults.
+
+    No browser is leased when the phase doesn't need one, see
+    `needs_browser`.
 */
 pub async fn create_browser_for_phase(
+    pool: &Arc<BrowserPool>,
     browser_config: &BrowserConfig,
+    steps: &[Step],
     source: &Source,
-) -> Result<(ChromeBrowser, ResolvedBrowserConfig)> {
-    let resolved = browser_config.resolve(source);
-    let browser = create_browser(&resolved).await?;
-    Ok((browser, resolved))
+) -> Result<PhaseBrowser> {
+    let config = browser_config.resolve(source);
+    if !needs_browser(browser_config, steps) {
+        println!("[browser] Running phase without a browser");
+        return Ok(PhaseBrowser::without_browser(config));
+    }
+
+    let lease = pool.acquire(&config, &source.id).await?;
+    Ok(PhaseBrowser {
+        config,
+        lease: Some(lease),
+    })
+}
+
+/**
+    Whether a phase runs with a browser.
+
+    In `auto` mode a browser is only launched when some step (including
+    nested and fallback steps) needs a page. `Fetch` and `Document` steps
+    don't: without a browser, `Document` extracts from the last fetched body.
+*/
+pub fn needs_browser(browser_config: &BrowserConfig, steps: &[Step]) -> bool {
+    match browser_config.browser {
+        BrowserMode::Auto => steps.iter().any(step_needs_browser),
+        BrowserMode::Chrome => true,
+        BrowserMode::None => false,
+    }
+}
+
+/**
+    Whether a step, or any step nested in it, needs a browser page.
+*/
+pub fn step_needs_browser(step: &Step) -> bool {
+    step_needs_page(step) || step.nested_steps().into_iter().any(step_needs_browser)
+}
+
+/**
+    Whether a step itself (not counting nested steps) needs a browser page.
+*/
+pub fn step_needs_page(step: &Step) -> bool {
+    match step {
+        Step::Fetch { .. } | Step::Document { .. } | Step::ForEach { .. } => false,
+        Step::If { condition, .. } => condition.selector.is_some() || condition.function.is_some(),
+        Step::Navigate { .. }
+        | Step::Sniff { .. }
+        | Step::SniffMany { .. }
+        | Step::FetchInBrowser { .. }
+        | Step::Script { .. }
+        | Step::Automation { .. } => true,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_evict() {
+        let config = |proxy: &str| ResolvedBrowserConfig {
+            proxy: Some(proxy.to_string()),
+            headless: true,
+        };
+        let now = Instant::now();
+        let ago = |secs| now - Duration::from_secs(secs);
+        let timeout = Duration::from_secs(300);
+
+        let mut idle = HashMap::from([
+            (
+                config("a"),
+                vec![(ago(400), 1), (ago(200), 2), (ago(10), 3)],
+            ),
+            (config("b"), vec![(ago(100), 4)]),
+            (config("c"), vec![(ago(50), 5)]),
+        ]);
+
+        // Expired browsers go first, then the longest idle over the limit
+        let mut evicted = evict(&mut idle, |_| true, 3, timeout, now);
+        evicted.sort();
+        assert_eq!(evicted, [1, 2]);
+        assert_eq!(idle.values().map(Vec::len).sum::<usize>(), 3);
+
+        // Browsers of configs no longer in use are closed
+        let evicted = evict(&mut idle, |c| c != &config("b"), 3, timeout, now);
+        assert_eq!(evicted, [4]);
+        assert!(!idle.contains_key(&config("b")));
+        assert_eq!(idle[&config("a")], [(ago(10), 3)]);
+    }
 }
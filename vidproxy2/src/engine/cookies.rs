@@ -0,0 +1,345 @@
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Mutex;
+
+use anyhow::{Result, anyhow};
+use chrome_browser::ChromeBrowserTab;
+use chrono::DateTime;
+use reqwest::Url;
+use reqwest::cookie::{CookieStore, Jar};
+use reqwest::header::HeaderValue;
+use serde::Deserialize;
+use serde_json::json;
+
+use super::interpolate::InterpolationContext;
+
+/**
+    Context step under which cookies are exposed to templates.
+*/
+pub const COOKIES_BINDING: &str = "cookies";
+
+/**
+    Cookies shared by the browser tab and the HTTP client during a phase.
+
+    The HTTP client keeps cookies in a regular jar, so `Fetch` steps hold a
+    session across requests. `sync` copies the browser's cookies into the
+    jar, and cookies set by `Fetch` responses into the browser. Both go
+    through the DevTools protocol rather than `document.cookie`, so HttpOnly
+    session cookies are shared too and keep their HttpOnly, Secure and
+    SameSite attributes on either side.
+
+    Every cookie seen in the phase is available as `${{cookies.NAME}}`.
+*/
+#[derive(Default)]
+pub(super) struct PhaseCookies {
+    jar: Jar,
+    /// Every cookie seen so far, by name
+    values: Mutex<HashMap<String, String>>,
+    /// `Set-Cookie` values from HTTP responses not yet copied to the browser
+    pending: Mutex<Vec<(Url, String)>>,
+}
+
+impl CookieStore for PhaseCookies {
+    fn set_cookies(&self, cookie_headers: &mut dyn Iterator<Item = &HeaderValue>, url: &Url) {
+        let headers: Vec<HeaderValue> = cookie_headers.cloned().collect();
+
+        for raw in headers.iter().filter_map(|h| h.to_str().ok()) {
+            if let Some((name, value)) = name_value(raw) {
+                self.values
+                    .lock()
+                    .unwrap()
+                    .insert(name.to_string(), value.to_string());
+                self.pending
+                    .lock()
+                    .unwrap()
+                    .push((url.clone(), raw.to_string()));
+            }
+        }
+
+        self.jar.set_cookies(&mut headers.iter(), url);
+    }
+
+    fn cookies(&self, url: &Url) -> Option<HeaderValue> {
+        self.jar.cookies(url)
+    }
+}
+
+impl PhaseCookies {
+    /**
+        Every cookie seen so far, by name.
+    */
+    pub fn values(&self) -> BTreeMap<String, String> {
+        let values = self.values.lock().unwrap();
+        values.iter().map(|(k, v)| (k.clone(), v.clone())).collect()
+    }
+
+    /**
+        Exchange cookies with the browser and expose them in the context.
+
+        Does nothing with the browser when the phase runs without one.
+    */
+    pub async fn sync(
+        &self,
+        tab: Option<&ChromeBrowserTab>,
+        context: &mut InterpolationContext,
+    ) -> Result<()> {
+        if let Some(tab) = tab {
+            self.exchange(tab).await?;
+        }
+
+        for (name, value) in self.values.lock().unwrap().iter() {
+            context.set(COOKIES_BINDING, name, value.clone());
+        }
+        Ok(())
+    }
+
+    async fn exchange(&self, tab: &ChromeBrowserTab) -> Result<()> {
+        // Browser → HTTP client, for the current page and its frames
+        let response = tab.cdp("Network.getCookies", json!({})).await?;
+        let cookies: Vec<BrowserCookie> =
+            serde_json::from_value(response.get("cookies").cloned().unwrap_or_default())
+                .map_err(|e| anyhow!("Invalid Network.getCookies response: {}", e))?;
+        for cookie in &cookies {
+            self.add_browser_cookie(cookie);
+        }
+
+        // HTTP client → browser, scoped to the URL that set each cookie
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        let params: Vec<serde_json::Value> = pending
+            .iter()
+            .filter_map(|(url, raw)| cookie_param(raw, url))
+            .collect();
+        if !params.is_empty() {
+            tab.cdp("Network.setCookies", json!({ "cookies": params }))
+                .await?;
+        }
+        Ok(())
+    }
+
+    fn add_browser_cookie(&self, cookie: &BrowserCookie) {
+        let Some(url) = cookie.url() else {
+            return;
+        };
+        self.jar.add_cookie_str(&cookie.set_cookie(), &url);
+        self.values
+            .lock()
+            .unwrap()
+            .insert(cookie.name.clone(), cookie.value.clone());
+    }
+}
+
+/**
+    A cookie as reported by `Network.getCookies`.
+*/
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct BrowserCookie {
+    name: String,
+    value: String,
+    /// Starts with a dot for domain cookies, bare for host-only cookies
+    domain: String,
+    path: String,
+    /// Unix timestamp, or -1 for session cookies
+    #[serde(default)]
+    expires: f64,
+    #[serde(default)]
+    http_only: bool,
+    #[serde(default)]
+    secure: bool,
+    #[serde(default)]
+    session: bool,
+    #[serde(default)]
+    same_site: Option<String>,
+}
+
+impl BrowserCookie {
+    /**
+        A URL the cookie is sent to, for adding it to the jar.
+    */
+    fn url(&self) -> Option<Url> {
+        let scheme = if self.secure { "https" } else { "http" };
+        let host = self.domain.trim_start_matches('.');
+        Url::parse(&format!("{}://{}{}", scheme, host, self.path)).ok()
+    }
+
+    /**
+        The cookie as a `Set-Cookie` value, with its attributes.
+    */
+    fn set_cookie(&self) -> String {
+        let mut raw = format!("{}={}; Path={}", self.name, self.value, self.path);
+        if self.domain.starts_with('.') {
+            raw.push_str(&format!("; Domain={}", self.domain));
+        }
+        if !self.session
+            && self.expires > 0.0
+            && let Some(expires) = DateTime::from_timestamp(self.expires as i64, 0)
+        {
+            raw.push_str(
+                &expires
+                    .format("; Expires=%a, %d %b %Y %H:%M:%S GMT")
+                    .to_string(),
+            );
+        }
+        if self.secure {
+            raw.push_str("; Secure");
+        }
+        if self.http_only {
+            raw.push_str("; HttpOnly");
+        }
+        if let Some(same_site) = &self.same_site {
+            raw.push_str(&format!("; SameSite={}", same_site));
+        }
+        raw
+    }
+}
+
+/**
+    `Network.setCookies` parameters for a `Set-Cookie` value received from
+    `url`, keeping its attributes.
+*/
+fn cookie_param(raw: &str, url: &Url) -> Option<serde_json::Value> {
+    let (name, value) = name_value(raw)?;
+    let mut param = json!({ "name": name, "value": value, "url": url.as_str() });
+    let mut max_age = None;
+    let mut expires = None;
+
+    for part in raw.split(';').skip(1) {
+        let (key, value) = part.split_once('=').unwrap_or((part, ""));
+        let value = value.trim();
+        match key.trim().to_ascii_lowercase().as_str() {
+            "domain" if !value.is_empty() => param["domain"] = value.into(),
+            "path" if value.starts_with('/') => param["path"] = value.into(),
+            "secure" => param["secure"] = true.into(),
+            "httponly" => param["httpOnly"] = true.into(),
+            "samesite" => {
+                let same_site = match value.to_ascii_lowercase().as_str() {
+                    "strict" => "Strict",
+                    "lax" => "Lax",
+                    "none" => "None",
+                    _ => continue,
+                };
+                param["sameSite"] = same_site.into();
+            }
+            "max-age" => max_age = value.parse::<i64>().ok(),
+            "expires" => {
+                expires = DateTime::parse_from_rfc2822(value)
+                    .ok()
+                    .map(|expires| expires.timestamp())
+            }
+            _ => {}
+        }
+    }
+
+    // Max-Age wins over Expires; neither makes a session cookie
+    let expires = max_age
+        .map(|seconds| crate::util::time::now().timestamp() + seconds)
+        .or(expires);
+    if let Some(expires) = expires {
+        param["expires"] = expires.into();
+    }
+    Some(param)
+}
+
+/**
+    Name and value of a `name=value` pair, or of the first pair of a
+    `Set-Cookie` header.
+*/
+fn name_value(raw: &str) -> Option<(&str, &str)> {
+    let pair = raw.split(';').next()?;
+    let (name, value) = pair.split_once('=')?;
+    let name = name.trim();
+    (!name.is_empty()).then(|| (name, value.trim()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_set_cookies_from_responses() {
+        let cookies = PhaseCookies::default();
+        let url = Url::parse("https://api.example.com/login").unwrap();
+        let headers = [
+            HeaderValue::from_static("session=abc123; Path=/; HttpOnly; Secure"),
+            HeaderValue::from_static("theme = dark"),
+        ];
+        cookies.set_cookies(&mut headers.iter(), &url);
+
+        let values = cookies.values.lock().unwrap().clone();
+        assert_eq!(values.get("session").map(String::as_str), Some("abc123"));
+        assert_eq!(values.get("theme").map(String::as_str), Some("dark"));
+        assert_eq!(cookies.pending.lock().unwrap().len(), 2);
+
+        let sent = cookies
+            .cookies(&Url::parse("https://api.example.com/epg").unwrap())
+            .unwrap();
+        assert!(sent.to_str().unwrap().contains("session=abc123"));
+        assert!(
+            cookies
+                .cookies(&Url::parse("https://other.example.org/").unwrap())
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_cookie_attributes() {
+        let raw = "session=abc; Domain=example.com; HttpOnly; Path=/";
+        assert_eq!(name_value(raw), Some(("session", "abc")));
+        assert_eq!(name_value(" a=b=c"), Some(("a", "b=c")));
+        assert_eq!(name_value("=x"), None);
+
+        let url = Url::parse("https://www.example.com/login").unwrap();
+        let param = cookie_param(
+            "id=7; Max-Age=60; Expires=Wed, 21 Oct 2015 07:28:00 GMT; SameSite=lax",
+            &url,
+        )
+        .unwrap();
+        assert_eq!(param["sameSite"], "Lax");
+        assert!(param["expires"].as_i64().unwrap() > crate::util::time::now().timestamp());
+        assert!(param.get("domain").is_none() && param.get("httpOnly").is_none());
+
+        let param = cookie_param("id=7; Expires=Wed, 21 Oct 2015 07:28:00 GMT", &url).unwrap();
+        assert_eq!(param["expires"], 1445412480);
+    }
+
+    #[test]
+    fn test_http_only_round_trip() {
+        let url = Url::parse("https://api.example.com/login").unwrap();
+        let raw = "session=abc123; Domain=example.com; Path=/; HttpOnly; Secure; SameSite=None";
+
+        // HTTP response → browser
+        let param = cookie_param(raw, &url).unwrap();
+        assert_eq!(param["name"], "session");
+        assert_eq!(param["url"], "https://api.example.com/login");
+        assert_eq!(param["domain"], "example.com");
+        assert_eq!(param["path"], "/");
+        assert_eq!(param["httpOnly"], true);
+        assert_eq!(param["secure"], true);
+        assert_eq!(param["sameSite"], "None");
+
+        // Browser → HTTP client, as Network.getCookies reports it back
+        let cookie: BrowserCookie = serde_json::from_value(json!({
+            "name": "session", "value": "abc123", "domain": ".example.com", "path": "/",
+            "expires": -1, "size": 13, "httpOnly": true, "secure": true,
+            "session": true, "sameSite": "None"
+        }))
+        .unwrap();
+        assert_eq!(
+            cookie.set_cookie(),
+            "session=abc123; Path=/; Domain=.example.com; Secure; HttpOnly; SameSite=None"
+        );
+
+        let cookies = PhaseCookies::default();
+        cookies.add_browser_cookie(&cookie);
+        assert_eq!(cookies.values()["session"], "abc123");
+        let sent = cookies
+            .cookies(&Url::parse("https://www.example.com/epg").unwrap())
+            .unwrap();
+        assert_eq!(sent.to_str().unwrap(), "session=abc123");
+        // Secure cookies are not sent over plain HTTP
+        assert!(
+            cookies
+                .cookies(&Url::parse("http://www.example.com/epg").unwrap())
+                .is_none()
+        );
+    }
+}
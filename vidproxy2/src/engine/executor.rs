@@ -1,16 +1,25 @@
 use std::collections::HashMap;
+use std::sync::Arc;
 
 use anyhow::{Result, anyhow};
 use chrome_browser::{ChromeBrowserTab, NetworkRequestStream};
+use futures::future::BoxFuture;
 use regex::Regex;
 use reqwest::{Client, Proxy};
 
+use super::artifacts::RequestLog;
+use super::automation::{execute_automation, js_string};
+use super::cookies::{COOKIES_BINDING, PhaseCookies};
 use super::extractor::{ExtractedArray, extract, extract_array};
-use super::interpolate::InterpolationContext;
+use super::fixtures::{Event, Fixtures, RequestFeed};
+use super::interpolate::{InterpolationContext, value_to_string};
+use super::request::{HttpClient, HttpRequest};
+use super::secrets::redact;
 use super::step::{
-    AutomationAction, Extractor, ExtractorKind, RequestMatch, Step, WaitCondition,
-    is_array_extractor,
+    Condition, ErrorPolicy, Extractor, ExtractorKind, OnError, Paginate, RequestBody, RequestMatch,
+    Step, WaitCondition, is_array_extractor,
 };
+use super::trace::{ExtractorOutcome, Tracer};
 
 const FETCH_USER_AGENT: &str = "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36 (KHTML, like Gecko) Chrome/120.0.0.0 Safari/537.36";
 
@@ -37,6 +46,8 @@ enum StepResult {
     Arrays are keyed by output name only (not step name), so multiple steps
     producing the same array name will merge their items — consistent with
     how `SniffMany` accumulates items across multiple network responses.
+    They are also stored in the context under the producing step, so
+    templates can address them (`${{step.items[0].url}}`).
 */
 fn store_result(output: &mut PhaseOutput, step_name: &str, result: StepResult) {
     match result {
@@ -46,6 +57,7 @@ fn store_result(output: &mut PhaseOutput, step_name: &str, result: StepResult) {
             }
         }
         StepResult::Array { name, items } => {
+            output.context.extend_array(step_name, &name, &items);
             output.arrays.entry(name).or_default().extend(items);
         }
         StepResult::Empty => {}
@@ -54,118 +66,624 @@ fn store_result(output: &mut PhaseOutput, step_name: &str, result: StepResult) {
 
 /**
     Execute a list of steps, returning the accumulated phase output.
+
+    Without a tab only steps that don't need a page can run, and `Document`
+    steps extract from the last fetched body instead. With `fixtures`, the
+    phase's inputs are recorded, or replayed without a browser or network.
+    With `trace`, a span is recorded for every step, including failed ones.
+    When an artifacts directory is set and a step fails the phase, the page
+    is saved there and the error names the directory. Failures handled by
+    `on_error` are not captured.
 */
 pub async fn execute_steps(
     steps: &[Step],
-    tab: &ChromeBrowserTab,
+    tab: Option<&ChromeBrowserTab>,
     initial_context: InterpolationContext,
     proxy: Option<&str>,
+    fixtures: Option<&Fixtures>,
+    trace: Option<&Tracer>,
 ) -> Result<PhaseOutput> {
     let mut output = PhaseOutput {
         context: initial_context,
         arrays: HashMap::new(),
     };
 
-    let mut requests = tab.network().requests();
+    let mut env = StepEnv::new(tab, proxy, fixtures, trace)?;
+    if let Err(e) = run_steps(steps, &mut env, &mut output).await {
+        return Err(capture_artifacts(&mut env, &output.context, e).await);
+    }
+    sync_cookies(&env, None, &mut output.context).await;
+
+    Ok(output)
+}
 
-    let http_client = if let Some(proxy_url) = proxy {
-        let proxy = Proxy::all(proxy_url)
-            .map_err(|e| anyhow!("Invalid proxy URL '{}': {}", proxy_url, e))?;
-        Client::builder()
-            .proxy(proxy)
+/**
+    Resources shared by every step in a phase, including nested steps.
+*/
+struct StepEnv<'a> {
+    tab: Option<&'a ChromeBrowserTab>,
+    requests: Option<NetworkRequestStream>,
+    http: HttpClient<'a>,
+    cookies: Arc<PhaseCookies>,
+    fixtures: Option<&'a Fixtures>,
+    trace: Option<&'a Tracer>,
+    /// Body of the most recent `Fetch` response
+    last_body: Option<String>,
+    /// Requests made by the tab, for failure artifacts
+    request_log: Option<RequestLog>,
+    /// Innermost step whose error is failing the phase, cleared when an
+    /// enclosing step handles the error
+    failed_step: Option<String>,
+}
+
+impl<'a> StepEnv<'a> {
+    fn new(
+        tab: Option<&'a ChromeBrowserTab>,
+        proxy: Option<&str>,
+        fixtures: Option<&'a Fixtures>,
+        trace: Option<&'a Tracer>,
+    ) -> Result<Self> {
+        let cookies = Arc::new(PhaseCookies::default());
+        let mut builder = Client::builder().cookie_provider(Arc::clone(&cookies));
+        if let Some(proxy_url) = proxy {
+            let proxy = Proxy::all(proxy_url)
+                .map_err(|e| anyhow!("Invalid proxy URL '{}': {}", proxy_url, e))?;
+            builder = builder.proxy(proxy);
+        }
+        let http_client = builder
             .build()
-            .map_err(|e| anyhow!("Failed to create HTTP client with proxy: {}", e))?
-    } else {
-        Client::new()
+            .map_err(|e| anyhow!("Failed to create HTTP client: {}", e))?;
+
+        Ok(Self {
+            tab,
+            requests: tab.map(|tab| tab.network().requests()),
+            http: HttpClient {
+                client: http_client,
+                fixtures,
+                trace,
+            },
+            cookies,
+            fixtures,
+            trace,
+            last_body: None,
+            request_log: tab
+                .filter(|_| super::artifacts::dir().is_some())
+                .map(RequestLog::new),
+            failed_step: None,
+        })
+    }
+
+    fn tab(&self) -> Result<&'a ChromeBrowserTab> {
+        self.tab.ok_or_else(no_browser)
+    }
+
+    /**
+        The recording being replayed, if any.
+    */
+    fn replaying(&self) -> Option<&'a Fixtures> {
+        self.fixtures.filter(|fixtures| fixtures.is_replay())
+    }
+
+    fn request_feed(&mut self, step_name: &str) -> Result<RequestFeed<'_>> {
+        if let Some(fixtures) = self.replaying() {
+            return Ok(RequestFeed::replay(fixtures, step_name));
+        }
+        let fixtures = self.fixtures;
+        let stream = self.requests.as_mut().ok_or_else(no_browser)?;
+        Ok(RequestFeed::live(stream, fixtures, step_name))
+    }
+}
+
+fn no_browser() -> anyhow::Error {
+    anyhow!("This step needs a browser, but the phase runs without one")
+}
+
+/**
+    Exchange cookies between the tab and the HTTP client.
+
+    Failures only mean cookies are out of date, so they are logged and the
+    phase goes on.
+*/
+async fn sync_cookies(env: &StepEnv<'_>, step: Option<&str>, context: &mut InterpolationContext) {
+    if let Some(fixtures) = env.replaying() {
+        let values = step.and_then(|step| fixtures.cookies(step));
+        for (name, value) in values.into_iter().flatten() {
+            context.set(COOKIES_BINDING, &name, value);
+        }
+        return;
+    }
+
+    if let Err(e) = env.cookies.sync(env.tab, context).await {
+        eprintln!("[executor] Failed to sync cookies: {}", e);
+    }
+
+    if let (Some(fixtures), Some(step)) = (env.fixtures, step) {
+        let values = env.cookies.values();
+        if !values.is_empty() {
+            fixtures.push(Event::Cookies {
+                step: step.to_string(),
+                values,
+            });
+        }
+    }
+}
+
+/**
+    Run steps in order, storing each result into the phase output.
+
+    Boxed so that steps with nested step lists (`If`, `ForEach`, fallbacks)
+    can recurse.
+*/
+fn run_steps<'a>(
+    steps: &'a [Step],
+    env: &'a mut StepEnv<'_>,
+    output: &'a mut PhaseOutput,
+) -> BoxFuture<'a, Result<()>> {
+    Box::pin(async move {
+        for step in steps {
+            let step_name = step.name();
+            println!("[executor] Running step: {}", step_name);
+
+            let span = env.trace.map(|trace| trace.begin(step));
+            let result = execute_step_with_retry(step, env, output).await;
+            if let (Some(trace), Some(span)) = (env.trace, span) {
+                let error = result.as_ref().err().map(ToString::to_string);
+                trace.finish(span, extractor_outcomes(step, &result), error);
+            }
+
+            match result {
+                Ok(result) => {
+                    env.failed_step = None;
+                    store_result(output, step_name, result);
+                }
+                Err(e) => match step.on_error() {
+                    OnError::Policy(ErrorPolicy::Fail) => {
+                        // A failing nested step is the one to name
+                        env.failed_step.get_or_insert_with(|| step_name.to_string());
+                        return Err(anyhow!("Step '{}' failed: {}", step_name, e));
+                    }
+                    OnError::Policy(ErrorPolicy::Continue) => {
+                        env.failed_step = None;
+                        eprintln!(
+                            "[executor] Step '{}' failed, continuing: {}",
+                            step_name,
+                            redact(&e.to_string())
+                        );
+                    }
+                    OnError::Fallback { fallback } => {
+                        env.failed_step = None;
+                        eprintln!(
+                            "[executor] Step '{}' failed, running fallback steps: {}",
+                            step_name,
+                            redact(&e.to_string())
+                        );
+                        run_steps(fallback, env, output).await?;
+                    }
+                },
+            }
+        }
+        Ok(())
+    })
+}
+
+/**
+    Save failure artifacts for the step that failed the phase, adding their
+    location to the error. Replayed phases have no page to capture.
+*/
+async fn capture_artifacts(
+    env: &mut StepEnv<'_>,
+    context: &InterpolationContext,
+    error: anyhow::Error,
+) -> anyhow::Error {
+    let Some(tab) = env.tab.filter(|_| env.replaying().is_none()) else {
+        return error;
     };
 
-    for step in steps {
-        let step_name = step.name();
-        println!("[executor] Running step: {}", step_name);
+    let step_name = env.failed_step.as_deref().unwrap_or("phase");
+    let label = match context.get("channel", "id").and_then(value_to_string) {
+        Some(channel) => format!("{}-{}", channel, step_name),
+        None => step_name.to_string(),
+    };
+    let message = error.to_string();
+
+    match super::artifacts::capture(tab, env.request_log.as_mut(), &label, &message).await {
+        Some(dir) => anyhow!("{} (artifacts: {})", error, dir.display()),
+        None => error,
+    }
+}
+
+/**
+    Execute a step, retrying according to its retry policy.
+*/
+async fn execute_step_with_retry(
+    step: &Step,
+    env: &mut StepEnv<'_>,
+    output: &mut PhaseOutput,
+) -> Result<StepResult> {
+    let Some(retry) = step.retry() else {
+        if let Some(trace) = env.trace {
+            trace.attempt();
+        }
+        return execute_step(step, env, output).await;
+    };
+
+    let attempts = retry.attempts.max(1);
+    let mut attempt = 1;
 
-        let result = match step {
-            Step::Navigate { url, wait_for, .. } => {
-                execute_navigate(url, wait_for.as_ref(), tab, &output.context).await?;
-                StepResult::Empty
+    loop {
+        if let Some(trace) = env.trace {
+            trace.attempt();
+        }
+        // A nested step that failed an earlier attempt is not the culprit
+        env.failed_step = None;
+        match execute_step(step, env, output).await {
+            Ok(result) => return Ok(result),
+            Err(e) if attempt < attempts => {
+                let delay = retry.delay(attempt);
+                eprintln!(
+                    "[executor] Step '{}' failed (attempt {}/{}), retrying in {:.1}s: {}",
+                    step.name(),
+                    attempt,
+                    attempts,
+                    delay.as_secs_f64(),
+                    redact(&e.to_string())
+                );
+                tokio::time::sleep(delay).await;
+                attempt += 1;
             }
-            Step::Sniff {
-                request,
-                extract: extractors,
-                ..
-            } => execute_sniff(request, extractors, &mut requests, &output.context).await?,
-            Step::SniffMany {
-                request,
-                extract: extractors,
-                ..
-            } => execute_sniff_many(request, extractors, &mut requests, &output.context).await?,
-            Step::Fetch {
+            Err(e) => return Err(e),
+        }
+    }
+}
+
+/**
+    Execute a single step against the current phase output.
+*/
+async fn execute_step(
+    step: &Step,
+    env: &mut StepEnv<'_>,
+    output: &mut PhaseOutput,
+) -> Result<StepResult> {
+    let step_name = step.name();
+    sync_cookies(env, Some(step_name), &mut output.context).await;
+
+    let result = match step {
+        Step::Navigate { .. } | Step::Script { .. } | Step::Automation { .. }
+            if env.replaying().is_some() =>
+        {
+            println!("[executor] Replaying, skipping page step '{}'", step_name);
+            StepResult::Empty
+        }
+        Step::Navigate { url, wait_for, .. } => {
+            execute_navigate(
                 url,
-                urls,
+                wait_for.as_ref(),
+                env.tab()?,
+                &output.context,
+                env.trace,
+            )
+            .await?;
+            StepResult::Empty
+        }
+        Step::Sniff {
+            request,
+            extract: extractors,
+            ..
+        } => {
+            let trace = env.trace;
+            let mut feed = env.request_feed(step_name)?;
+            execute_sniff(request, extractors, &mut feed, &output.context, trace).await?
+        }
+        Step::SniffMany {
+            request,
+            extract: extractors,
+            ..
+        } => {
+            let trace = env.trace;
+            let mut feed = env.request_feed(step_name)?;
+            execute_sniff_many(request, extractors, &mut feed, &output.context, trace).await?
+        }
+        Step::Fetch {
+            url,
+            urls,
+            method,
+            query,
+            headers,
+            body,
+            paginate: Some(paginate),
+            extract: extractors,
+            ..
+        } => {
+            let Some(url) = url.as_deref().filter(|_| urls.is_none()) else {
+                return Err(anyhow!(
+                    "Fetch step '{}' with 'paginate' needs a single 'url'",
+                    step_name
+                ));
+            };
+            let template = FetchTemplate {
+                url,
+                method: method.as_deref(),
+                query,
+                headers,
+                body: body.as_ref(),
+            };
+            execute_paginated_fetch(
+                &template,
+                extractors,
+                paginate,
+                output,
+                &env.http,
+                &mut env.last_body,
+            )
+            .await?
+        }
+        Step::Fetch {
+            url,
+            urls,
+            method,
+            query,
+            headers,
+            body,
+            extract: extractors,
+            ..
+        } => {
+            let resolved_urls = match (url, urls) {
+                (_, Some(urls)) => urls
+                    .iter()
+                    .map(|u| output.context.interpolate(u))
+                    .collect::<Result<Vec<_>>>()?,
+                (Some(url), None) => vec![output.context.interpolate(url)?],
+                (None, None) => {
+                    return Err(anyhow!(
+                        "Fetch step '{}' has neither 'url' nor 'urls'",
+                        step_name
+                    ));
+                }
+            };
+            let request =
+                HttpRequest::resolve(method.as_deref(), query, body.as_ref(), &output.context)?;
+            execute_fetch(
+                &resolved_urls,
+                &request,
                 headers,
-                extract: extractors,
-                ..
-            } => {
-                let resolved_urls = match (url, urls) {
-                    (_, Some(urls)) => urls
-                        .iter()
-                        .map(|u| output.context.interpolate(u))
-                        .collect::<Result<Vec<_>>>()?,
-                    (Some(url), None) => vec![output.context.interpolate(url)?],
-                    (None, None) => {
+                extractors,
+                &output.context,
+                &env.http,
+                &mut env.last_body,
+            )
+            .await?
+        }
+        Step::FetchInBrowser {
+            url,
+            method,
+            query,
+            body,
+            extract: extractors,
+            ..
+        } => {
+            let request =
+                HttpRequest::resolve(method.as_deref(), query, body.as_ref(), &output.context)?;
+            let url = request.url(&output.context.interpolate(url)?)?;
+            if let Some(trace) = env.trace {
+                trace.request(&url);
+            }
+            let response = match env.replaying() {
+                Some(fixtures) => {
+                    println!(
+                        "[executor] FetchInBrowser (replay): {} {}",
+                        request.method,
+                        redact(&url)
+                    );
+                    fixtures.response(&request.method, &url)?
+                }
+                None => {
+                    let any_status = reads_status(extractors);
+                    let response = fetch_in_browser(&url, &request, any_status, env.tab()?).await?;
+                    if let Some(fixtures) = env.fixtures {
+                        fixtures.record_response(&request.method, &url, &response);
+                    }
+                    response
+                }
+            };
+
+            let has_array = extractors.values().any(|e| is_array_extractor(&e.kind));
+            run_extractors(
+                extractors,
+                &response.body,
+                &response.url,
+                Some(&response.headers),
+                Some(response.status),
+                has_array,
+                &output.context,
+            )?
+        }
+        Step::Document {
+            extract: extractors,
+            ..
+        } => {
+            let html = match (env.replaying(), env.tab) {
+                (Some(fixtures), _) => fixtures.document(step_name),
+                (None, Some(tab)) => {
+                    let html = read_document(tab).await?;
+                    if let Some(fixtures) = env.fixtures {
+                        fixtures.push(Event::Document {
+                            step: step_name.to_string(),
+                            html: html.clone(),
+                        });
+                    }
+                    Some(html)
+                }
+                (None, None) => None,
+            };
+            let body = match html {
+                Some(html) => html,
+                None => {
+                    println!("[executor] Reading last fetched body");
+                    env.last_body.clone().ok_or_else(|| {
+                        anyhow!("Document step without a browser needs a Fetch before it")
+                    })?
+                }
+            };
+
+            let has_array = extractors.values().any(|e| is_array_extractor(&e.kind));
+            run_extractors(
+                extractors,
+                &body,
+                "",
+                None,
+                None,
+                has_array,
+                &output.context,
+            )?
+        }
+        Step::Script { script, .. } => {
+            execute_script(script, env.tab()?, &output.context).await?;
+            StepResult::Empty
+        }
+        Step::Automation { steps: actions, .. } => {
+            execute_automation(actions, env.tab()?, &output.context).await?;
+            StepResult::Empty
+        }
+        Step::If {
+            condition,
+            then,
+            otherwise,
+            ..
+        } => {
+            let matched = evaluate_condition(condition, step_name, env, output)
+                .await
+                .map_err(|e| anyhow!("Condition of step '{}' failed: {}", step_name, e))?;
+            println!("[executor] Condition matched: {}", matched);
+            let branch = if matched { then } else { otherwise };
+            run_steps(branch, env, output).await?;
+            StepResult::Empty
+        }
+        Step::ForEach {
+            array,
+            binding,
+            steps,
+            ..
+        } => {
+            let binding = binding.as_deref().unwrap_or("item");
+            execute_for_each(step_name, array, binding, steps, env, output).await?;
+            StepResult::Empty
+        }
+    };
+
+    Ok(result)
+}
+
+// ── Step handlers ────────────────────────────────────────────────────────────
+
+/**
+    Run nested steps for every item of an array, enriching items in place.
+
+    Each iteration runs against a copy of the phase context with the item
+    bound under `binding`, so iterations cannot see each other's outputs.
+    Array outputs produced by nested steps are discarded, and two nested
+    steps producing the same scalar output fail the step.
+*/
+async fn execute_for_each(
+    step_name: &str,
+    array_name: &str,
+    binding: &str,
+    steps: &[Step],
+    env: &mut StepEnv<'_>,
+    output: &mut PhaseOutput,
+) -> Result<()> {
+    let items = output.arrays.get(array_name).cloned().ok_or_else(|| {
+        anyhow!(
+            "ForEach step '{}' references unknown array '{}'",
+            step_name,
+            array_name
+        )
+    })?;
+
+    let mut nested_names = Vec::new();
+    for step in steps {
+        collect_step_names(step, &mut nested_names);
+    }
+
+    let total = items.len();
+    let mut enriched: ExtractedArray = Vec::with_capacity(total);
+
+    for (i, mut item) in items.into_iter().enumerate() {
+        println!(
+            "[executor] ForEach: item {}/{} of {}",
+            i + 1,
+            total,
+            array_name
+        );
+
+        let mut item_output = PhaseOutput {
+            context: output.context.clone(),
+            arrays: HashMap::new(),
+        };
+        for (field, value) in &item {
+            if let Some(value) = value {
+                item_output.context.set(binding, field, value.clone());
+            }
+        }
+
+        run_steps(steps, env, &mut item_output).await?;
+
+        let mut producers: HashMap<&str, &str> = HashMap::new();
+        for name in &nested_names {
+            if let Some(values) = item_output.context.outputs(name) {
+                for (field, value) in values {
+                    if value.is_array() || value.is_object() {
+                        continue;
+                    }
+                    if let Some(other) = producers.insert(field, name) {
                         return Err(anyhow!(
-                            "Fetch step '{}' has neither 'url' nor 'urls'",
-                            step_name
+                            "ForEach step '{}': nested steps '{}' and '{}' both produce '{}'",
+                            step_name,
+                            other,
+                            name,
+                            field
                         ));
                     }
-                };
-                execute_fetch(
-                    &resolved_urls,
-                    headers,
-                    extractors,
-                    &output.context,
-                    &http_client,
-                )
-                .await?
-            }
-            Step::FetchInBrowser {
-                url,
-                extract: extractors,
-                ..
-            } => execute_fetch_in_browser(url, extractors, tab, &output.context).await?,
-            Step::Document {
-                extract: extractors,
-                ..
-            } => execute_document(extractors, tab, &output.context).await?,
-            Step::Script { script, .. } => {
-                execute_script(script, tab, &output.context).await?;
-                StepResult::Empty
-            }
-            Step::Automation { steps: actions, .. } => {
-                execute_automation(actions, tab, &output.context).await?;
-                StepResult::Empty
+                    item.insert(field.clone(), value_to_string(value));
+                }
             }
-        };
+        }
 
-        store_result(&mut output, step_name, result);
+        enriched.push(item);
     }
 
-    Ok(output)
+    output.context.replace_arrays(array_name, &enriched);
+    output.arrays.insert(array_name.to_string(), enriched);
+    Ok(())
 }
 
-// ── Step handlers ────────────────────────────────────────────────────────────
+fn collect_step_names<'a>(step: &'a Step, names: &mut Vec<&'a str>) {
+    names.push(step.name());
+    for nested in step.nested_steps() {
+        collect_step_names(nested, names);
+    }
+}
 
 async fn execute_navigate(
     url_template: &str,
     wait_for: Option<&WaitCondition>,
     tab: &ChromeBrowserTab,
     context: &InterpolationContext,
+    trace: Option<&Tracer>,
 ) -> Result<()> {
     let url = context.interpolate(url_template)?;
-    println!("[executor] Navigating to: {}", url);
+    println!("[executor] Navigating to: {}", redact(&url));
+    if let Some(trace) = trace {
+        trace.request(&url);
+    }
     tab.navigate(&url).await?;
 
     if let Some(wait_for) = wait_for {
-        apply_wait_condition(wait_for, tab, context).await?;
+        let started = std::time::Instant::now();
+        let waited = apply_wait_condition(wait_for, tab, context).await;
+        if let Some(trace) = trace {
+            trace.waited(started.elapsed());
+        }
+        waited?;
     }
 
     Ok(())
@@ -174,8 +692,9 @@ async fn execute_navigate(
 async fn execute_sniff(
     request_match: &RequestMatch,
     extractors: &HashMap<String, Extractor>,
-    requests: &mut NetworkRequestStream,
+    feed: &mut RequestFeed<'_>,
     context: &InterpolationContext,
+    trace: Option<&Tracer>,
 ) -> Result<StepResult> {
     use std::time::Duration;
 
@@ -183,6 +702,7 @@ async fn execute_sniff(
         .map_err(|e| anyhow!("Invalid URL regex '{}': {}", request_match.url, e))?;
 
     let timeout_secs = request_match.timeout.unwrap_or(30.0);
+    let mut last_error: Option<anyhow::Error> = None;
 
     println!(
         "[executor] Waiting for request matching: {} (timeout: {}s)",
@@ -194,43 +714,63 @@ async fn execute_sniff(
     let has_array = extractors.values().any(|e| is_array_extractor(&e.kind));
 
     loop {
-        let next_request = tokio::time::timeout_at(deadline, requests.next()).await;
+        let next_request = tokio::time::timeout_at(deadline, feed.next()).await;
 
         let request = match next_request {
             Ok(Some(req)) => req,
-            Ok(None) => return Err(anyhow!("Network stream closed before finding match")),
+            Ok(None) => {
+                return Err(with_last_error(
+                    anyhow!("Network stream closed before finding match"),
+                    last_error,
+                ));
+            }
             Err(_) => {
-                return Err(anyhow!(
-                    "Timeout waiting for request matching '{}'",
-                    request_match.url
+                return Err(with_last_error(
+                    anyhow!(
+                        "Timeout waiting for request matching '{}'",
+                        request_match.url
+                    ),
+                    last_error,
                 ));
             }
         };
 
-        let url = request.url().to_string();
+        let url = request.url.clone();
         if !url_regex.is_match(&url) {
             continue;
         }
 
         if let Some(expected_method) = &request_match.method
-            && request.method().as_str() != expected_method.as_str()
+            && request.method != *expected_method
         {
             continue;
         }
 
-        let headers = request.headers().clone();
-        println!("[executor] Matched request: {}", &url[..url.len().min(80)]);
-
-        let body = if let Ok(response) = request.response().await {
-            response.text().await.unwrap_or_default()
-        } else {
-            String::new()
-        };
+        let headers = request.headers.clone();
+        let shown = redact(&url);
+        println!(
+            "[executor] Matched request: {}",
+            &shown[..shown.len().min(80)]
+        );
+        if let Some(trace) = trace {
+            trace.request(&url);
+        }
 
-        match run_extractors(extractors, &body, &url, Some(&headers), has_array, context) {
+        let body = feed.body(request).await.unwrap_or_default();
+
+        match run_extractors(
+            extractors,
+            &body,
+            &url,
+            Some(&headers),
+            None,
+            has_array,
+            context,
+        ) {
             Ok(result) => return Ok(result),
-            Err(_) => {
+            Err(e) => {
                 println!("[executor] Extraction failed, trying next request...");
+                last_error = Some(e);
                 continue;
             }
         }
@@ -240,8 +780,9 @@ async fn execute_sniff(
 async fn execute_sniff_many(
     request_match: &RequestMatch,
     extractors: &HashMap<String, Extractor>,
-    requests: &mut NetworkRequestStream,
+    feed: &mut RequestFeed<'_>,
     context: &InterpolationContext,
+    trace: Option<&Tracer>,
 ) -> Result<StepResult> {
     use std::time::Duration;
 
@@ -271,7 +812,7 @@ async fn execute_sniff_many(
             std::cmp::min(idle_deadline, deadline)
         };
 
-        let next_request = tokio::time::timeout_at(wait_timeout, requests.next()).await;
+        let next_request = tokio::time::timeout_at(wait_timeout, feed.next()).await;
 
         let request = match next_request {
             Ok(Some(req)) => req,
@@ -292,26 +833,28 @@ async fn execute_sniff_many(
             }
         };
 
-        let url = request.url().to_string();
+        let url = request.url.clone();
         if !url_regex.is_match(&url) {
             continue;
         }
 
         if let Some(expected_method) = &request_match.method
-            && request.method().as_str() != expected_method.as_str()
+            && request.method != *expected_method
         {
             continue;
         }
 
+        let shown = redact(&url);
         println!(
             "[executor] SniffMany: matched request #{}: {}",
             match_count + 1,
-            &url[..url.len().min(80)]
+            &shown[..shown.len().min(80)]
         );
+        if let Some(trace) = trace {
+            trace.request(&url);
+        }
 
-        let body = if let Ok(response) = request.response().await {
-            response.text().await.unwrap_or_default()
-        } else {
+        let Some(body) = feed.body(request).await else {
             continue;
         };
 
@@ -321,7 +864,7 @@ async fn execute_sniff_many(
                     array_extractor_name = Some(output_name.clone());
                 }
                 let extractor = interpolate_extractor(extractor, context)?;
-                match extract_array(&extractor, &body) {
+                match extract_array(&extractor, &body, &url) {
                     Ok(items) => {
                         println!(
                             "[executor] SniffMany: extracted {} items from response",
@@ -331,7 +874,10 @@ async fn execute_sniff_many(
                         match_count += 1;
                     }
                     Err(e) => {
-                        println!("[executor] SniffMany: extraction failed: {}", e);
+                        println!(
+                            "[executor] SniffMany: extraction failed: {}",
+                            redact(&e.to_string())
+                        );
                     }
                 }
                 break;
@@ -356,17 +902,31 @@ async fn execute_sniff_many(
 
 async fn execute_fetch(
     urls: &[String],
+    request: &HttpRequest,
     step_headers: &HashMap<String, String>,
     extractors: &HashMap<String, Extractor>,
     context: &InterpolationContext,
-    http_client: &Client,
+    http: &HttpClient<'_>,
+    last_body: &mut Option<String>,
 ) -> Result<StepResult> {
     let has_array = extractors.values().any(|e| is_array_extractor(&e.kind));
+    let any_status = reads_status(extractors);
 
     // Single URL — simple path, supports both scalar and array extraction
     if urls.len() == 1 {
-        let body = fetch_one(&urls[0], step_headers, context, http_client).await?;
-        return run_extractors(extractors, &body, &urls[0], None, has_array, context);
+        let response =
+            fetch_one(&urls[0], request, step_headers, any_status, context, http).await?;
+        let result = run_extractors(
+            extractors,
+            &response.body,
+            &response.url,
+            Some(&response.headers),
+            Some(response.status),
+            has_array,
+            context,
+        );
+        *last_body = Some(response.body);
+        return result;
     }
 
     // Multiple URLs — fetch all concurrently and merge array results
@@ -384,20 +944,21 @@ async fn execute_fetch(
 
     let fetches = urls
         .iter()
-        .map(|url| fetch_one(url, step_headers, context, http_client));
+        .map(|url| fetch_one(url, request, step_headers, any_status, context, http));
     let results = futures::future::join_all(fetches).await;
 
     let mut all_items: ExtractedArray = Vec::new();
     for (i, result) in results.into_iter().enumerate() {
-        let body = result?;
-        let items = extract_array(&array_extractor, &body)?;
+        let response = result?;
+        let items = extract_array(&array_extractor, &response.body, &response.url)?;
         println!(
             "[executor] Extracted {} items from {} ({})",
             items.len(),
             array_name,
-            urls[i]
+            redact(&urls[i])
         );
         all_items.extend(items);
+        *last_body = Some(response.body);
     }
 
     println!(
@@ -411,111 +972,386 @@ async fn execute_fetch(
     })
 }
 
+/**
+    The request templates of a paginated `Fetch` step, resolved again for
+    every page.
+*/
+struct FetchTemplate<'a> {
+    url: &'a str,
+    method: Option<&'a str>,
+    query: &'a HashMap<String, String>,
+    headers: &'a HashMap<String, String>,
+    body: Option<&'a RequestBody>,
+}
+
+/**
+    Context step under which the current page's values are bound.
+*/
+const PAGE_BINDING: &str = "page";
+
+/// Page limit for paginated fetches without `max_pages`
+const DEFAULT_MAX_PAGES: u32 = 100;
+
+async fn execute_paginated_fetch(
+    template: &FetchTemplate<'_>,
+    extractors: &HashMap<String, Extractor>,
+    paginate: &Paginate,
+    output: &PhaseOutput,
+    http: &HttpClient<'_>,
+    last_body: &mut Option<String>,
+) -> Result<StepResult> {
+    let (array_name, array_extractor) = extractors
+        .iter()
+        .find(|(_, e)| is_array_extractor(&e.kind))
+        .ok_or_else(|| anyhow!("Paginated Fetch requires an array extractor"))?;
+    let any_status = reads_status(extractors);
+
+    let mut context = output.context.clone();
+    let mut next_url: Option<String> = None;
+    let mut previous_body: Option<String> = None;
+    let mut all_items: ExtractedArray = Vec::new();
+    let mut pages = 0;
+
+    let max_pages = paginate.max_pages.unwrap_or(DEFAULT_MAX_PAGES);
+
+    loop {
+        if pages >= max_pages {
+            match paginate.max_pages {
+                Some(_) => println!("[executor] Reached max_pages ({})", pages),
+                None => eprintln!(
+                    "[executor] Stopped after {} pages, set max_pages to fetch more",
+                    pages
+                ),
+            }
+            break;
+        }
+
+        let number = paginate.start + pages as u64;
+        context.set(PAGE_BINDING, "number", number.to_string());
+
+        let mut request =
+            HttpRequest::resolve(template.method, template.query, template.body, &context)?;
+        let url = match &next_url {
+            Some(url) => {
+                // Next links carry their own query
+                request.query.clear();
+                url.clone()
+            }
+            None => context.interpolate(template.url)?,
+        };
+
+        let response =
+            fetch_one(&url, &request, template.headers, any_status, &context, http).await?;
+        pages += 1;
+        *last_body = Some(response.body.clone());
+
+        if previous_body.as_ref() == Some(&response.body) {
+            println!(
+                "[executor] Page {} repeats the previous page, stopping",
+                number
+            );
+            break;
+        }
+
+        let extractor = interpolate_extractor(array_extractor, &context)?;
+        let items = extract_array(&extractor, &response.body, &response.url)?;
+        println!(
+            "[executor] Extracted {} items from {} (page {})",
+            items.len(),
+            array_name,
+            number
+        );
+        let empty = items.is_empty();
+        if empty && !paginate.skip_empty {
+            break;
+        }
+
+        for (name, extractor) in &paginate.extract {
+            let extractor = interpolate_extractor(extractor, &context)?;
+            let value = extract_page_value(name, &extractor, &response, number)?;
+            context.set(PAGE_BINDING, name, value);
+        }
+
+        // Array conditions look at this page, not at earlier steps' output
+        let stop = match &paginate.stop_when {
+            Some(condition) => {
+                let array_len = |name: &str| {
+                    if name == array_name {
+                        items.len()
+                    } else {
+                        output.arrays.get(name).map_or(0, Vec::len)
+                    }
+                };
+                check_condition_values(condition, &context, array_len)? != condition.not
+            }
+            None => false,
+        };
+        all_items.extend(items);
+        if stop {
+            println!("[executor] Stop condition met after page {}", number);
+            break;
+        }
+
+        if let Some(next) = &paginate.next {
+            let next = interpolate_extractor(next, &context)?;
+            let link = extract_page_value("next", &next, &response, number)?;
+            if link.trim().is_empty() {
+                break;
+            }
+            let base = reqwest::Url::parse(&response.url)
+                .map_err(|e| anyhow!("Invalid URL '{}': {}", redact(&response.url), e))?;
+            let resolved = base
+                .join(link.trim())
+                .map_err(|e| anyhow!("Invalid next page URL '{}': {}", redact(&link), e))?;
+            next_url = Some(resolved.into());
+        }
+
+        // Empty pages alike each other are not a repeat
+        if !empty {
+            previous_body = Some(response.body);
+        }
+    }
+
+    println!(
+        "[executor] Total {} items from {} pages",
+        all_items.len(),
+        pages
+    );
+    Ok(StepResult::Array {
+        name: array_name.clone(),
+        items: all_items,
+    })
+}
+
+/**
+    Run a pagination extractor on a page, falling back to its `default`
+    when it fails.
+*/
+fn extract_page_value(
+    name: &str,
+    extractor: &Extractor,
+    response: &FetchedResponse,
+    number: u64,
+) -> Result<String> {
+    extract(
+        extractor,
+        &response.body,
+        &response.url,
+        Some(&response.headers),
+        Some(response.status),
+    )
+    .or_else(|e| {
+        extractor.default.clone().ok_or_else(|| {
+            anyhow!(
+                "Pagination value '{}' failed on page {}: {}",
+                name,
+                number,
+                e
+            )
+        })
+    })
+}
+
+/**
+    A fetched response, as seen by extractors.
+*/
+pub(super) struct FetchedResponse {
+    pub url: String,
+    pub status: u16,
+    pub headers: axum::http::HeaderMap,
+    pub body: String,
+}
+
 async fn fetch_one(
     url: &str,
+    request: &HttpRequest,
     step_headers: &HashMap<String, String>,
+    any_status: bool,
     context: &InterpolationContext,
-    http_client: &Client,
-) -> Result<String> {
-    println!("[executor] Fetching: {}", url);
+    http: &HttpClient<'_>,
+) -> Result<FetchedResponse> {
+    let url = request.url(url)?;
+    if let Some(trace) = http.trace {
+        trace.request(&url);
+    }
+    if let Some(fixtures) = http.fixtures.filter(|fixtures| fixtures.is_replay()) {
+        println!(
+            "[executor] Fetching (replay): {} {}",
+            request.method,
+            redact(&url)
+        );
+        return fixtures.response(&request.method, &url);
+    }
+    println!("[executor] Fetching: {} {}", request.method, redact(&url));
 
-    let mut request = http_client.get(url).header("User-Agent", FETCH_USER_AGENT);
+    let mut builder = http
+        .client
+        .request(request.method.clone(), &url)
+        .header("User-Agent", FETCH_USER_AGENT);
+
+    if let Some(body) = &request.body {
+        if let Some(content_type) = &body.content_type {
+            builder = builder.header("Content-Type", content_type.as_str());
+        }
+        builder = builder.body(body.content.clone());
+    }
 
     for (key, value_template) in step_headers {
         let value = context.interpolate(value_template)?;
         if !value.trim().is_empty() {
-            request = request.header(key.as_str(), value);
+            builder = builder.header(key.as_str(), value);
         }
     }
 
-    let response = request
+    let response = builder
         .send()
         .await
-        .map_err(|e| anyhow!("HTTP request failed for '{}': {}", url, e))?;
+        .map_err(|e| anyhow!("HTTP request failed for '{}': {}", redact(&url), e))?;
 
-    if !response.status().is_success() {
+    let status = response.status();
+    if !status.is_success() && !any_status {
         return Err(anyhow!(
             "HTTP request failed for '{}': status {}",
-            url,
-            response.status()
+            redact(&url),
+            status
         ));
     }
 
+    let headers = response.headers().clone();
     let body = response
         .text()
         .await
         .map_err(|e| anyhow!("Failed to read response body: {}", e))?;
 
-    println!("[executor] Fetched {} bytes", body.len());
-    Ok(body)
+    println!("[executor] Fetched {} bytes ({})", body.len(), status);
+    let response = FetchedResponse {
+        url,
+        status: status.as_u16(),
+        headers,
+        body,
+    };
+    if let Some(fixtures) = http.fixtures {
+        fixtures.record_response(&request.method, &response.url, &response);
+    }
+    Ok(response)
 }
 
-async fn execute_fetch_in_browser(
-    url_template: &str,
-    extractors: &HashMap<String, Extractor>,
+/**
+    Fetch a URL from the page, so the request carries the page's cookies and
+    origin.
+*/
+async fn fetch_in_browser(
+    url: &str,
+    request: &HttpRequest,
+    any_status: bool,
     tab: &ChromeBrowserTab,
-    context: &InterpolationContext,
-) -> Result<StepResult> {
-    let url = context.interpolate(url_template)?;
-    println!("[executor] FetchInBrowser: {}", url);
-
-    let script = format!(
-        r#"(async () => {{
-            const tryFetch = async (options) => {{
-                const res = await fetch({url:?}, options);
-                if (!res.ok) throw new Error('HTTP ' + res.status);
-                const body = await res.text();
-                return {{ url: res.url, body }};
-            }};
-            try {{
-                return await tryFetch({{ credentials: 'include', mode: 'cors' }});
-            }} catch (err) {{
-                return await tryFetch({{ credentials: 'omit', mode: 'cors' }});
-            }}
-        }})()"#
+) -> Result<FetchedResponse> {
+    println!(
+        "[executor] FetchInBrowser: {} {}",
+        request.method,
+        redact(url)
     );
 
-    let value = tab.eval_json(script, true).await?;
-    let (response_url, body) = match value {
+    let value = tab
+        .eval_json(fetch_in_browser_script(url, request, any_status), true)
+        .await?;
+    let response = match value {
         serde_json::Value::Object(mut obj) => {
             let response_url = obj
                 .remove("url")
                 .and_then(|v| v.as_str().map(ToString::to_string))
-                .unwrap_or_else(|| url.clone());
+                .unwrap_or_else(|| url.to_string());
+            let status = obj.get("status").and_then(|v| v.as_u64()).unwrap_or(200) as u16;
+            let mut headers = axum::http::HeaderMap::new();
+            if let Some(serde_json::Value::Object(entries)) = obj.get("headers") {
+                for (name, value) in entries {
+                    if let (Ok(name), Some(Ok(value))) = (
+                        axum::http::HeaderName::from_bytes(name.as_bytes()),
+                        value.as_str().map(axum::http::HeaderValue::from_str),
+                    ) {
+                        headers.append(name, value);
+                    }
+                }
+            }
             let body = obj
                 .remove("body")
                 .and_then(|v| v.as_str().map(ToString::to_string))
                 .unwrap_or_default();
-            (response_url, body)
+            FetchedResponse {
+                url: response_url,
+                status,
+                headers,
+                body,
+            }
         }
-        serde_json::Value::String(s) => (url.clone(), s),
-        other => (url.clone(), other.to_string()),
+        serde_json::Value::String(s) => FetchedResponse {
+            url: url.to_string(),
+            status: 200,
+            headers: axum::http::HeaderMap::new(),
+            body: s,
+        },
+        other => FetchedResponse {
+            url: url.to_string(),
+            status: 200,
+            headers: axum::http::HeaderMap::new(),
+            body: other.to_string(),
+        },
     };
 
-    println!("[executor] Browser fetched {} bytes", body.len());
+    println!(
+        "[executor] Browser fetched {} bytes ({})",
+        response.body.len(),
+        response.status
+    );
+    Ok(response)
+}
+
+/**
+    Script for `fetch_in_browser`. A GET or HEAD that fails on the network or
+    CORS (a `TypeError`) is retried without credentials, as servers answering
+    `Access-Control-Allow-Origin: *` reject credentialed requests. Other
+    methods are never sent twice, and neither are requests that got an HTTP
+    status back.
+*/
+fn fetch_in_browser_script(url: &str, request: &HttpRequest, any_status: bool) -> String {
+    let mut options = serde_json::json!({ "method": request.method.as_str() });
+    if let Some(body) = &request.body {
+        options["body"] = body.content.clone().into();
+        if let Some(content_type) = &body.content_type {
+            options["headers"] = serde_json::json!({ "Content-Type": content_type });
+        }
+    }
+    let retry_without_credentials = matches!(request.method.as_str(), "GET" | "HEAD");
 
-    let has_array = extractors.values().any(|e| is_array_extractor(&e.kind));
-    run_extractors(extractors, &body, &response_url, None, has_array, context)
+    format!(
+        r#"(async () => {{
+            const tryFetch = async (credentials) => {{
+                const res = await fetch({url}, {{ ...{options}, ...credentials }});
+                if (!res.ok && !{any_status}) throw new Error('HTTP ' + res.status);
+                const body = await res.text();
+                const headers = Object.fromEntries(res.headers.entries());
+                return {{ url: res.url, status: res.status, headers, body }};
+            }};
+            try {{
+                return await tryFetch({{ credentials: 'include', mode: 'cors' }});
+            }} catch (err) {{
+                if (!{retry_without_credentials} || !(err instanceof TypeError)) throw err;
+                return await tryFetch({{ credentials: 'omit', mode: 'cors' }});
+            }}
+        }})()"#,
+        url = serde_json::Value::from(url),
+    )
 }
 
-async fn execute_document(
-    extractors: &HashMap<String, Extractor>,
-    tab: &ChromeBrowserTab,
-    context: &InterpolationContext,
-) -> Result<StepResult> {
+async fn read_document(tab: &ChromeBrowserTab) -> Result<String> {
     println!("[executor] Reading document HTML");
-
     let value = tab
         .eval_json("document.documentElement.outerHTML", false)
         .await?;
-
-    let body = match value {
+    Ok(match value {
         serde_json::Value::String(s) => s,
         other => other.to_string(),
-    };
-
-    let has_array = extractors.values().any(|e| is_array_extractor(&e.kind));
-    run_extractors(extractors, &body, "", None, has_array, context)
+    })
 }
 
 async fn execute_script(
@@ -529,36 +1365,6 @@ async fn execute_script(
     Ok(())
 }
 
-async fn execute_automation(
-    actions: &[AutomationAction],
-    tab: &ChromeBrowserTab,
-    context: &InterpolationContext,
-) -> Result<()> {
-    for action in actions {
-        match action {
-            AutomationAction::Click { selector, wait_for } => {
-                let selector = context.interpolate(selector)?;
-                println!("[executor] Clicking element: {}", selector);
-                let element = tab.wait_for_selector(&selector).await?;
-                element.click().await?;
-                if let Some(wait_condition) = wait_for {
-                    apply_wait_condition(wait_condition, tab, context).await?;
-                }
-            }
-            AutomationAction::ClickIframe { selector, wait_for } => {
-                let selector = context.interpolate(selector)?;
-                println!("[executor] Clicking iframe: {}", selector);
-                let element = tab.wait_for_selector(&selector).await?;
-                element.click().await?;
-                if let Some(wait_condition) = wait_for {
-                    apply_wait_condition(wait_condition, tab, context).await?;
-                }
-            }
-        }
-    }
-    Ok(())
-}
-
 // ── Shared helpers ───────────────────────────────────────────────────────────
 
 /**
@@ -570,6 +1376,7 @@ fn run_extractors(
     body: &str,
     url: &str,
     headers: Option<&axum::http::HeaderMap>,
+    status: Option<u16>,
     has_array: bool,
     context: &InterpolationContext,
 ) -> Result<StepResult> {
@@ -577,7 +1384,7 @@ fn run_extractors(
         for (output_name, extractor) in extractors {
             if is_array_extractor(&extractor.kind) {
                 let extractor = interpolate_extractor(extractor, context)?;
-                let items = extract_array(&extractor, body)?;
+                let items = extract_array(&extractor, body, url)?;
                 println!(
                     "[executor] Extracted {} items from {}",
                     items.len(),
@@ -594,7 +1401,7 @@ fn run_extractors(
     let mut extracted = HashMap::new();
     for (output_name, extractor) in extractors {
         let extractor = interpolate_extractor(extractor, context)?;
-        match extract(&extractor, body, url, headers) {
+        match extract(&extractor, body, url, headers, status) {
             Ok(value) => {
                 extracted.insert(output_name.clone(), value);
             }
@@ -615,19 +1422,182 @@ fn run_extractors(
     Ok(StepResult::Single(extracted))
 }
 
-async fn apply_wait_condition(
+/**
+    Add the reason the last matching request was rejected to a sniffing
+    error, so a timeout after a near miss says what was missing.
+*/
+fn with_last_error(error: anyhow::Error, last_error: Option<anyhow::Error>) -> anyhow::Error {
+    match last_error {
+        Some(last) => anyhow!("{} (last match: {})", error, last),
+        None => error,
+    }
+}
+
+/**
+    What each of a step's extractors produced, for its trace span. Steps
+    with an array extractor only run that one; a failed step produced
+    nothing.
+*/
+fn extractor_outcomes(step: &Step, result: &Result<StepResult>) -> Vec<ExtractorOutcome> {
+    let Some(extractors) = step.extractors() else {
+        return Vec::new();
+    };
+    let mut names: Vec<&String> = extractors.keys().collect();
+    names.sort();
+
+    match result {
+        Ok(StepResult::Single(values)) => names
+            .into_iter()
+            .map(|name| match values.get(name) {
+                Some(value) => ExtractorOutcome::hit(name, value),
+                None => ExtractorOutcome::miss(name),
+            })
+            .collect(),
+        Ok(StepResult::Array { name, items }) => vec![ExtractorOutcome::items(name, items.len())],
+        Ok(StepResult::Empty) => Vec::new(),
+        Err(_) => names
+            .into_iter()
+            .map(|name| ExtractorOutcome::miss(name))
+            .collect(),
+    }
+}
+
+/**
+    Whether any extractor reads the response status, in which case non-2xx
+    responses are passed to the extractors instead of failing the step.
+*/
+fn reads_status(extractors: &HashMap<String, Extractor>) -> bool {
+    extractors.values().any(|e| e.kind == ExtractorKind::Status)
+}
+
+/**
+    Evaluate an `If` step condition against the phase output and current page.
+
+    Context and array checks run first so the browser is only queried when
+    they all pass.
+*/
+async fn evaluate_condition(
+    condition: &Condition,
+    step_name: &str,
+    env: &StepEnv<'_>,
+    output: &PhaseOutput,
+) -> Result<bool> {
+    let array_len = |name: &str| output.arrays.get(name).map_or(0, Vec::len);
+    if !check_condition_values(condition, &output.context, array_len)? {
+        return Ok(condition.not);
+    }
+
+    if condition.selector.is_none() && condition.function.is_none() {
+        return Ok(!condition.not);
+    }
+
+    let matched = match env.replaying() {
+        Some(fixtures) => fixtures.condition(step_name)?,
+        None => {
+            let matched = check_condition_page(condition, env.tab()?, &output.context).await?;
+            if let Some(fixtures) = env.fixtures {
+                fixtures.push(Event::Condition {
+                    step: step_name.to_string(),
+                    matched,
+                });
+            }
+            matched
+        }
+    };
+
+    Ok(matched != condition.not)
+}
+
+fn check_condition_values(
+    condition: &Condition,
+    context: &InterpolationContext,
+    array_len: impl Fn(&str) -> usize,
+) -> Result<bool> {
+    condition.check()?;
+
+    if let Some(template) = &condition.value {
+        // Only an undefined reference is false; typos and filter errors fail
+        let Some(value) = context.interpolate_optional(template)? else {
+            return Ok(false);
+        };
+
+        if let Some(expected) = &condition.equals {
+            if value != context.interpolate(expected)? {
+                return Ok(false);
+            }
+        } else if condition.matches.is_none() && value.trim().is_empty() {
+            return Ok(false);
+        }
+
+        if let Some(pattern) = &condition.matches {
+            let re =
+                Regex::new(pattern).map_err(|e| anyhow!("Invalid regex '{}': {}", pattern, e))?;
+            if !re.is_match(&value) {
+                return Ok(false);
+            }
+        }
+    }
+
+    if let Some(name) = &condition.array
+        && array_len(name) == 0
+    {
+        return Ok(false);
+    }
+
+    Ok(true)
+}
+
+async fn check_condition_page(
+    condition: &Condition,
+    tab: &ChromeBrowserTab,
+    context: &InterpolationContext,
+) -> Result<bool> {
+    if let Some(selector_template) = &condition.selector {
+        let selector = context.interpolate(selector_template)?;
+        println!("[executor] Checking for selector: {}", redact(&selector));
+        let script = format!("!!document.querySelector({})", js_string(&selector));
+        if !is_truthy(&tab.eval_json(script, false).await?) {
+            return Ok(false);
+        }
+    }
+
+    if let Some(expr_template) = &condition.function {
+        let expr = context.interpolate(expr_template)?;
+        println!("[executor] Checking function: {}", redact(&expr));
+        if !is_truthy(&tab.eval_json(expr, true).await?) {
+            return Ok(false);
+        }
+    }
+
+    Ok(true)
+}
+
+/**
+    JavaScript-style truthiness for values returned from the page.
+*/
+fn is_truthy(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::Null => false,
+        serde_json::Value::Bool(b) => *b,
+        serde_json::Value::Number(n) => n.as_f64().is_some_and(|n| n != 0.0),
+        serde_json::Value::String(s) => !s.is_empty(),
+        serde_json::Value::Array(_) | serde_json::Value::Object(_) => true,
+    }
+}
+
+pub(super) async fn apply_wait_condition(
     wait_for: &WaitCondition,
     tab: &ChromeBrowserTab,
     context: &InterpolationContext,
 ) -> Result<()> {
     if let Some(selector_template) = &wait_for.selector {
         let selector = context.interpolate(selector_template)?;
-        println!("[executor] Waiting for selector: {}", selector);
+        println!("[executor] Waiting for selector: {}", redact(&selector));
         tab.wait_for_selector(&selector).await?;
     }
     if let Some(expr_template) = &wait_for.function {
         let expr = context.interpolate(expr_template)?;
-        println!("[executor] Waiting for function: {}", expr);
+        println!("[executor] Waiting for function: {}", redact(&expr));
         tab.wait_for_function(&expr).await?;
     }
     if let Some(delay) = wait_for.delay {
@@ -655,6 +1625,14 @@ fn interpolate_extractor(
         interpolated.default = Some(context.interpolate(default)?);
     }
 
+    if let Some(selector) = &extractor.selector {
+        interpolated.selector = Some(context.interpolate(selector)?);
+    }
+
+    if let Some(variable) = &extractor.variable {
+        interpolated.variable = Some(context.interpolate(variable)?);
+    }
+
     if let Some(each) = &extractor.each {
         let mut next_each = HashMap::new();
         for (key, value) in each {
@@ -665,3 +1643,855 @@ fn interpolate_extractor(
 
     Ok(interpolated)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::engine::fixtures::Recording;
+    use crate::engine::trace::SpanStatus;
+
+    /**
+        Serve three pages of items linked by `next`, the last one without.
+        Pages past the last repeat it. `/endless` always links a next page and
+        `/sparse` has no items on pages 2, 3 and 6. `/detail/{id}` describes
+        an item, except `2b` which is missing.
+    */
+    async fn serve_pages() -> String {
+        use axum::{Json, Router, extract::Query, routing::get};
+
+        async fn items(Query(query): Query<HashMap<String, String>>) -> Json<serde_json::Value> {
+            let page: u32 = query.get("p").and_then(|p| p.parse().ok()).unwrap_or(1);
+            let page = page.min(3);
+            let next = (page < 3).then(|| format!("/items?p={}", page + 1));
+            Json(serde_json::json!({
+                "items": [{ "id": format!("{}a", page) }, { "id": format!("{}b", page) }],
+                "next": next,
+            }))
+        }
+
+        async fn endless(Query(query): Query<HashMap<String, String>>) -> Json<serde_json::Value> {
+            let page: u32 = query.get("p").and_then(|p| p.parse().ok()).unwrap_or(1);
+            Json(serde_json::json!({
+                "items": [{ "id": page.to_string() }],
+                "next": format!("/endless?p={}", page + 1),
+            }))
+        }
+
+        async fn sparse(Query(query): Query<HashMap<String, String>>) -> Json<serde_json::Value> {
+            let page: u32 = query.get("p").and_then(|p| p.parse().ok()).unwrap_or(1);
+            let items = match page {
+                2 | 3 | 6 => vec![],
+                _ => vec![serde_json::json!({ "id": page.to_string() })],
+            };
+            Json(serde_json::json!({ "items": items }))
+        }
+
+        async fn detail(
+            axum::extract::Path(id): axum::extract::Path<String>,
+        ) -> Result<Json<serde_json::Value>, axum::http::StatusCode> {
+            if id == "2b" {
+                return Err(axum::http::StatusCode::NOT_FOUND);
+            }
+            Ok(Json(serde_json::json!({ "title": format!("Item {}", id) })))
+        }
+
+        async fn guide() -> axum::response::Html<&'static str> {
+            axum::response::Html(r#"<html><body><h1 class="title">Guide</h1></body></html>"#)
+        }
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = Router::new()
+            .route("/items", get(items))
+            .route("/endless", get(endless))
+            .route("/sparse", get(sparse))
+            .route("/detail/{id}", get(detail))
+            .route("/guide", get(guide));
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    fn fetch_step(fields: &str) -> Step {
+        fetch_step_at("items", fields)
+    }
+
+    fn fetch_step_at(path: &str, fields: &str) -> Step {
+        let yaml = format!(
+            r#"
+name: "list"
+kind: Fetch
+url: "${{{{ server.base }}}}/{path}"
+{fields}
+extract:
+  items:
+    kind: jsonpath_array
+    path: "$.items[*]"
+    each:
+      id: "$.id"
+"#
+        );
+        serde_yaml::from_str(&yaml).expect("Failed to parse step")
+    }
+
+    async fn run_paginated(step: &Step, base: &str) -> Vec<String> {
+        try_paginated(step, base).await.unwrap()
+    }
+
+    async fn try_paginated(step: &Step, base: &str) -> Result<Vec<String>> {
+        let Step::Fetch {
+            url: Some(url),
+            method,
+            query,
+            headers,
+            body,
+            paginate: Some(paginate),
+            extract,
+            ..
+        } = step
+        else {
+            panic!("Expected paginated Fetch step");
+        };
+
+        let mut output = PhaseOutput {
+            context: InterpolationContext::new(),
+            arrays: HashMap::new(),
+        };
+        output.context.set("server", "base", base.to_string());
+
+        let template = FetchTemplate {
+            url,
+            method: method.as_deref(),
+            query,
+            headers,
+            body: body.as_ref(),
+        };
+        let result = execute_paginated_fetch(
+            &template,
+            extract,
+            paginate,
+            &output,
+            &HttpClient {
+                client: Client::new(),
+                fixtures: None,
+                trace: None,
+            },
+            &mut None,
+        )
+        .await?;
+
+        let StepResult::Array { items, .. } = result else {
+            panic!("Expected array result");
+        };
+        Ok(items
+            .iter()
+            .map(|item| item.get("id").cloned().flatten().unwrap_or_default())
+            .collect())
+    }
+
+    #[tokio::test]
+    async fn test_paginate_next_links() {
+        let base = serve_pages().await;
+
+        let step = fetch_step(
+            r#"
+paginate:
+  next:
+    kind: jsonpath
+    path: "$.next"
+    default: ""
+"#,
+        );
+        let ids = run_paginated(&step, &base).await;
+        assert_eq!(ids, ["1a", "1b", "2a", "2b", "3a", "3b"]);
+
+        let step = fetch_step(
+            r#"
+paginate:
+  next:
+    kind: jsonpath
+    path: "$.next"
+  max_pages: 2
+"#,
+        );
+        assert_eq!(run_paginated(&step, &base).await.len(), 4);
+
+        // Without a default, a missing link is an extraction error
+        let step = fetch_step(
+            r#"
+paginate:
+  next:
+    kind: jsonpath
+    path: "$.next"
+"#,
+        );
+        let error = try_paginated(&step, &base).await.unwrap_err().to_string();
+        assert!(error.contains("'next' failed on page 3"), "{}", error);
+
+        let step = fetch_step_at(
+            "endless",
+            r#"
+paginate:
+  next:
+    kind: jsonpath
+    path: "$.next"
+"#,
+        );
+        let ids = run_paginated(&step, &base).await;
+        assert_eq!(ids.len(), DEFAULT_MAX_PAGES as usize);
+    }
+
+    #[tokio::test]
+    async fn test_paginate_page_numbers() {
+        let base = serve_pages().await;
+
+        // Stops once a page has no `next` link
+        let step = fetch_step(
+            r#"
+query:
+  p: "${{ page.number }}"
+paginate:
+  start: 2
+  extract:
+    next:
+      kind: jsonpath
+      path: "$.next"
+      default: ""
+  stop_when:
+    value: "${{ page.next }}"
+    not: true
+"#,
+        );
+        let ids = run_paginated(&step, &base).await;
+        assert_eq!(ids, ["2a", "2b", "3a", "3b"]);
+
+        // Array conditions see the page just fetched
+        let step = fetch_step(
+            r#"
+query:
+  p: "${{ page.number }}"
+paginate:
+  stop_when:
+    array: items
+"#,
+        );
+        assert_eq!(run_paginated(&step, &base).await, ["1a", "1b"]);
+
+        // With `skip_empty`, empty pages up to `max_pages` are passed over
+        let step = fetch_step_at(
+            "sparse",
+            r#"
+query:
+  p: "${{ page.number }}"
+paginate:
+  skip_empty: true
+  max_pages: 6
+"#,
+        );
+        assert_eq!(run_paginated(&step, &base).await, ["1", "4", "5"]);
+
+        // Without a stop condition, the repeated last page ends paging
+        let step = fetch_step(
+            r#"
+query:
+  p: "${{ page.number }}"
+paginate: {}
+"#,
+        );
+        assert_eq!(run_paginated(&step, &base).await.len(), 6);
+    }
+
+    #[test]
+    fn test_condition_values() {
+        let mut context = InterpolationContext::new();
+        context.set("page", "title", "Guide".to_string());
+        let check = |yaml: &str| {
+            let condition: Condition = serde_yaml::from_str(yaml).unwrap();
+            check_condition_values(&condition, &context, |_| 0)
+        };
+
+        assert!(check(r#"{ value: "${{ page.title }}", equals: "Guide" }"#).unwrap());
+        assert!(!check(r#"{ value: "${{ page.title }}", matches: "^guide$" }"#).unwrap());
+
+        // An undefined output is false, other interpolation errors fail
+        assert!(!check(r#"{ value: "${{ page.missing }}" }"#).unwrap());
+        let error = check(r#"{ value: "${{ page.title | shout }}" }"#).unwrap_err();
+        assert!(
+            error.to_string().contains("Unknown filter 'shout'"),
+            "{}",
+            error
+        );
+
+        let error = check(r#"{ equals: "Guide" }"#).unwrap_err();
+        assert!(
+            error.to_string().contains("'equals' requires a 'value'"),
+            "{}",
+            error
+        );
+        let error = check("{ not: true }").unwrap_err();
+        assert!(
+            error.to_string().contains("needs at least one of"),
+            "{}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn test_steps_without_browser() {
+        let base = serve_pages().await;
+        let steps: Vec<Step> = serde_yaml::from_str(
+            r#"
+- name: "guide"
+  kind: Fetch
+  url: "${{ server.base }}/guide"
+  extract:
+    status:
+      kind: status
+- name: "page"
+  kind: Document
+  extract:
+    title:
+      kind: css
+      path: "h1.title"
+"#,
+        )
+        .unwrap();
+
+        let mut context = InterpolationContext::new();
+        context.set("server", "base", base);
+        let output = execute_steps(&steps, None, context, None, None, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            output.context.interpolate("${{ page.title }}").unwrap(),
+            "Guide"
+        );
+
+        let navigate: Vec<Step> =
+            serde_yaml::from_str("[{ name: open, kind: Navigate, url: \"about:blank\" }]").unwrap();
+        let Err(error) = execute_steps(
+            &navigate,
+            None,
+            InterpolationContext::new(),
+            None,
+            None,
+            None,
+        )
+        .await
+        else {
+            panic!("Expected Navigate to fail without a browser");
+        };
+        assert!(error.to_string().contains("needs a browser"));
+    }
+
+    /**
+        Fetch the items of `pages` and run `nested` for each of them.
+    */
+    async fn run_for_each(base: &str, pages: u32, nested: &str) -> Result<PhaseOutput> {
+        let yaml = format!(
+            r#"
+- name: "list"
+  kind: Fetch
+  url: "${{{{ server.base }}}}/items"
+  query:
+    p: "${{{{ page.number }}}}"
+  paginate:
+    max_pages: {pages}
+  extract:
+    items:
+      kind: jsonpath_array
+      path: "$.items[*]"
+      each:
+        id: "$.id"
+- name: "details"
+  kind: ForEach
+  array: items
+  as: entry
+  steps:
+{nested}
+"#
+        );
+        let steps: Vec<Step> = serde_yaml::from_str(&yaml).expect("Failed to parse steps");
+
+        let mut context = InterpolationContext::new();
+        context.set("server", "base", base.to_string());
+        execute_steps(&steps, None, context, None, None, None).await
+    }
+
+    #[tokio::test]
+    async fn test_for_each() {
+        let base = serve_pages().await;
+        let detail = r#"
+    - name: "detail"
+      kind: Fetch
+      url: "${{ server.base }}/detail/${{ entry.id }}"
+      extract:
+        title:
+          kind: jsonpath
+          path: "$.title"
+"#;
+
+        let output = run_for_each(&base, 1, detail).await.unwrap();
+        let titles: Vec<_> = output.arrays["items"]
+            .iter()
+            .map(|item| (item["id"].clone().unwrap(), item["title"].clone().unwrap()))
+            .collect();
+        assert_eq!(
+            titles,
+            [
+                ("1a".to_string(), "Item 1a".to_string()),
+                ("1b".to_string(), "Item 1b".to_string()),
+            ]
+        );
+
+        // Templates over the producing step see the enriched items
+        let (_, _, items) = output
+            .context
+            .referenced_array("${{ list.items.title }}")
+            .unwrap()
+            .unwrap();
+        assert_eq!(items[1]["title"], "Item 1b");
+
+        // One failing item fails the step
+        let error = run_for_each(&base, 2, detail).await.err().unwrap();
+        assert!(error.to_string().contains("404"), "{}", error);
+
+        // Nested outputs with the same name are rejected, not overwritten
+        let nested = format!(
+            r#"{detail}
+    - name: "again"
+      kind: Fetch
+      url: "${{{{ server.base }}}}/detail/${{{{ entry.id }}}}"
+      extract:
+        title:
+          kind: jsonpath
+          path: "$.title"
+"#
+        );
+        let error = run_for_each(&base, 1, &nested).await.err().unwrap();
+        assert!(
+            error
+                .to_string()
+                .contains("nested steps 'detail' and 'again' both produce 'title'"),
+            "{}",
+            error
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retry_then_fallback() {
+        let steps: Vec<Step> = serde_yaml::from_str(
+            r#"
+- name: "stream"
+  kind: Sniff
+  request:
+    url: "\\.m3u8"
+  extract:
+    url:
+      kind: url
+  retry:
+    attempts: 3
+    backoff: 0
+  on_error:
+    fallback:
+      - name: "guide"
+        kind: Fetch
+        url: "https://example.com/guide"
+        extract:
+          title:
+            kind: css
+            path: "h1.title"
+"#,
+        )
+        .unwrap();
+
+        // No requests were recorded for the Sniff, so every attempt fails
+        let recording: Recording = serde_json::from_value(serde_json::json!({
+            "events": [
+                { "kind": "response", "method": "GET", "url": "https://example.com/guide",
+                  "response_url": "https://example.com/guide", "status": 200, "headers": [],
+                  "body": "<h1 class=\"title\">Guide</h1>" }
+            ]
+        }))
+        .unwrap();
+
+        let fixtures = Fixtures::replay(recording);
+        let tracer = Tracer::new("content");
+        let output = execute_steps(
+            &steps,
+            None,
+            InterpolationContext::new(),
+            None,
+            Some(&fixtures),
+            Some(&tracer),
+        )
+        .await
+        .unwrap();
+
+        let stream = &tracer.trace().steps[0];
+        assert_eq!(stream.name, "stream");
+        assert_eq!(stream.attempts, 3);
+        assert_eq!(stream.status, SpanStatus::Failed);
+        assert_eq!(
+            output.context.interpolate("${{ guide.title }}").unwrap(),
+            "Guide"
+        );
+        assert!(output.context.interpolate("${{ stream.url }}").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_artifacts_only_for_phase_failures() {
+        let steps: Vec<Step> = serde_yaml::from_str(
+            r##"
+- name: "license"
+  kind: Sniff
+  on_error: continue
+  request:
+    url: "/license"
+  extract:
+    license_url:
+      kind: url
+- name: "check"
+  kind: If
+  condition:
+    value: "yes"
+  on_error:
+    fallback: []
+  then:
+    - name: "stream"
+      kind: Sniff
+      request:
+        url: "\\.m3u8"
+      extract:
+        url:
+          kind: url
+"##,
+        )
+        .unwrap();
+
+        // Nothing was recorded, so every Sniff fails
+        let fixtures = Fixtures::replay(Recording::default());
+        let mut env = StepEnv::new(None, None, Some(&fixtures), None).unwrap();
+        let mut output = PhaseOutput {
+            context: InterpolationContext::new(),
+            arrays: HashMap::new(),
+        };
+        run_steps(&steps, &mut env, &mut output).await.unwrap();
+        assert_eq!(env.failed_step, None);
+
+        // Without the fallback, the nested step fails the phase and is captured
+        let mut env = StepEnv::new(None, None, Some(&fixtures), None).unwrap();
+        let mut check = steps[1].clone();
+        let Step::If { on_error, .. } = &mut check else {
+            panic!("Expected If step");
+        };
+        *on_error = OnError::default();
+        let error = run_steps(std::slice::from_ref(&check), &mut env, &mut output)
+            .await
+            .unwrap_err();
+        assert_eq!(env.failed_step.as_deref(), Some("stream"));
+        assert!(
+            error
+                .to_string()
+                .starts_with("Step 'check' failed: Step 'stream' failed")
+        );
+
+        // A nested step that failed before a successful retry isn't blamed
+        // for a later failure
+        let base = serve_flaky().await;
+        let steps: Vec<Step> = serde_yaml::from_str(&format!(
+            r##"
+- name: "check"
+  kind: If
+  condition:
+    value: "yes"
+  retry:
+    attempts: 2
+    backoff: 0
+  then:
+    - name: "flaky"
+      kind: Fetch
+      url: "{base}/flaky"
+      extract:
+        url:
+          kind: url
+- name: "final"
+  kind: Fetch
+  url: "{base}/missing"
+  extract:
+    url:
+      kind: url
+"##
+        ))
+        .unwrap();
+        let mut env = StepEnv::new(None, None, None, None).unwrap();
+        let error = run_steps(&steps, &mut env, &mut output).await.unwrap_err();
+        assert_eq!(env.failed_step.as_deref(), Some("final"));
+        assert!(error.to_string().starts_with("Step 'final' failed"));
+    }
+
+    #[test]
+    fn test_fetch_in_browser_retry() {
+        let context = InterpolationContext::new();
+        let script = |method: &str| {
+            let request =
+                HttpRequest::resolve(Some(method), &HashMap::new(), None, &context).unwrap();
+            fetch_in_browser_script("https://api.example.com/epg", &request, false)
+        };
+
+        // Only network and CORS errors of GET and HEAD are retried
+        for method in ["get", "HEAD"] {
+            assert!(
+                script(method).contains("if (!true || !(err instanceof TypeError)) throw err;")
+            );
+        }
+        for method in ["post", "PUT", "delete"] {
+            assert!(
+                script(method).contains("if (!false || !(err instanceof TypeError)) throw err;")
+            );
+        }
+    }
+
+    /**
+        Serve `/flaky`, which fails its first request and succeeds after.
+    */
+    async fn serve_flaky() -> String {
+        use axum::{Router, http::StatusCode, routing::get};
+        use std::sync::atomic::{AtomicUsize, Ordering};
+
+        let hits = Arc::new(AtomicUsize::new(0));
+        let flaky = move || {
+            let hits = Arc::clone(&hits);
+            async move {
+                match hits.fetch_add(1, Ordering::SeqCst) {
+                    0 => StatusCode::SERVICE_UNAVAILABLE,
+                    _ => StatusCode::OK,
+                }
+            }
+        };
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let router = Router::new().route("/flaky", get(flaky));
+        tokio::spawn(async move {
+            axum::serve(listener, router).await.unwrap();
+        });
+        format!("http://{}", addr)
+    }
+
+    #[tokio::test]
+    async fn test_record_and_replay() {
+        let base = serve_pages().await;
+        let steps: Vec<Step> = serde_yaml::from_str(
+            r#"
+- name: "list"
+  kind: Fetch
+  url: "${{ server.base }}/items"
+  paginate:
+    next:
+      kind: jsonpath
+      path: "$.next"
+      default: ""
+  extract:
+    items:
+      kind: jsonpath_array
+      path: "$.items[*]"
+      each:
+        id: "$.id"
+- name: "guide"
+  kind: Fetch
+  url: "${{ server.base }}/guide"
+  extract:
+    status:
+      kind: status
+- name: "page"
+  kind: Document
+  extract:
+    title:
+      kind: css
+      path: "h1.title"
+"#,
+        )
+        .unwrap();
+        let mut context = InterpolationContext::new();
+        context.set("server", "base", base);
+
+        let recording = Fixtures::record();
+        let live = execute_steps(&steps, None, context.clone(), None, Some(&recording), None)
+            .await
+            .unwrap();
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("discovery.json");
+        recording.save(&path).unwrap();
+
+        let replay = Fixtures::load(&path).unwrap();
+        let replayed = execute_steps(&steps, None, context, None, Some(&replay), None)
+            .await
+            .unwrap();
+
+        assert_eq!(replayed.arrays["items"].len(), 6);
+        assert_eq!(replayed.arrays["items"], live.arrays["items"]);
+        for template in ["${{ guide.status }}", "${{ page.title }}"] {
+            assert_eq!(
+                replayed.context.interpolate(template).unwrap(),
+                live.context.interpolate(template).unwrap()
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_replay_page_steps() {
+        let steps: Vec<Step> = serde_yaml::from_str(
+            r##"
+- name: "open"
+  kind: Navigate
+  url: "https://example.com/live"
+- name: "check"
+  kind: If
+  condition:
+    selector: "#player"
+  then:
+    - name: "stream"
+      kind: Sniff
+      request:
+        url: "\\.m3u8"
+      extract:
+        url:
+          kind: url
+        token:
+          kind: header
+          path: "x-token"
+- name: "schedule"
+  kind: SniffMany
+  request:
+    url: "/epg"
+  extract:
+    programmes:
+      kind: jsonpath_array
+      path: "$[*]"
+      each:
+        title: "$.title"
+"##,
+        )
+        .unwrap();
+
+        let recording: Recording = serde_json::from_value(serde_json::json!({
+            "events": [
+                { "kind": "condition", "step": "check", "matched": true },
+                { "kind": "sniffed", "step": "stream", "requests": [
+                    { "url": "https://cdn.example.com/ad.mp4", "method": "GET", "headers": [], "body": null },
+                    { "url": "https://cdn.example.com/live.m3u8", "method": "GET",
+                      "headers": [["x-token", "abc"]], "body": "#EXTM3U" }
+                ]},
+                { "kind": "sniffed", "step": "schedule", "requests": [
+                    { "url": "https://example.com/epg?day=1", "method": "GET", "headers": [],
+                      "body": "[{\"title\": \"News\"}]" },
+                    { "url": "https://example.com/epg?day=2", "method": "GET", "headers": [],
+                      "body": "[{\"title\": \"Film\"}]" }
+                ]}
+            ]
+        }))
+        .unwrap();
+
+        let fixtures = Fixtures::replay(recording);
+        let output = execute_steps(
+            &steps,
+            None,
+            InterpolationContext::new(),
+            None,
+            Some(&fixtures),
+            None,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(
+            output.context.interpolate("${{ stream.url }}").unwrap(),
+            "https://cdn.example.com/live.m3u8"
+        );
+        assert_eq!(
+            output.context.interpolate("${{ stream.token }}").unwrap(),
+            "abc"
+        );
+        let titles: Vec<_> = output.arrays["programmes"]
+            .iter()
+            .map(|item| item.get("title").cloned().flatten().unwrap_or_default())
+            .collect();
+        assert_eq!(titles, ["News", "Film"]);
+    }
+
+    #[tokio::test]
+    async fn test_trace_spans() {
+        let steps: Vec<Step> = serde_yaml::from_str(
+            r##"
+- name: "check"
+  kind: If
+  condition:
+    value: "${{ channel.id }}"
+    equals: "one"
+  then:
+    - name: "stream"
+      kind: Sniff
+      request:
+        url: "\\.m3u8"
+      extract:
+        url:
+          kind: url
+- name: "license"
+  kind: Sniff
+  on_error: continue
+  request:
+    url: "/license"
+  extract:
+    license_url:
+      kind: url
+"##,
+        )
+        .unwrap();
+
+        let recording: Recording = serde_json::from_value(serde_json::json!({
+            "events": [
+                { "kind": "sniffed", "step": "stream", "requests": [
+                    { "url": "https://cdn.example.com/live.m3u8", "method": "GET",
+                      "headers": [], "body": "#EXTM3U" }
+                ]}
+            ]
+        }))
+        .unwrap();
+
+        let mut context = InterpolationContext::new();
+        context.set("channel", "id", "one".to_string());
+        let fixtures = Fixtures::replay(recording);
+        let tracer = Tracer::new("content");
+        execute_steps(&steps, None, context, None, Some(&fixtures), Some(&tracer))
+            .await
+            .unwrap();
+
+        let trace = tracer.trace();
+        let spans: Vec<(&str, usize, SpanStatus)> = trace
+            .steps
+            .iter()
+            .map(|span| (span.name.as_str(), span.depth, span.status))
+            .collect();
+        assert_eq!(
+            spans,
+            [
+                ("check", 0, SpanStatus::Ok),
+                ("stream", 1, SpanStatus::Ok),
+                ("license", 0, SpanStatus::Failed),
+            ]
+        );
+
+        let stream = &trace.steps[1];
+        assert_eq!(stream.requests, ["https://cdn.example.com/live.m3u8"]);
+        assert_eq!(stream.attempts, 1);
+        assert!(stream.extractors[0].hit);
+
+        let license = &trace.steps[2];
+        assert!(license.error.is_some());
+        assert_eq!(license.extractors[0].name, "license_url");
+        assert!(!license.extractors[0].hit);
+    }
+}
@@ -0,0 +1,579 @@
+use anyhow::{Result, anyhow};
+use base64::Engine;
+use base64::engine::general_purpose::{STANDARD, STANDARD_NO_PAD, URL_SAFE, URL_SAFE_NO_PAD};
+
+/**
+    A parsed `${{ ... }}` expression.
+
+    Grammar:
+
+    ```text
+    expr    := operand ('+' operand)*
+    operand := primary ('|' filter)*
+    primary := path | string | '(' expr ')'
+    path    := ident ('.' ident | '[' (number | string) ']')*
+    filter  := ident ('(' string (',' string)* ')')?
+    ```
+
+    Filters bind tighter than `+`, so `a.b | lower + "x"` lowercases only `a.b`.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum Expr {
+    Variable(Vec<PathSegment>),
+    Literal(String),
+    Concat(Vec<Expr>),
+    Filtered {
+        expr: Box<Expr>,
+        filters: Vec<Filter>,
+    },
+}
+
+/**
+    A segment of a variable path: `.name`/`["name"]` or `[index]`.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum PathSegment {
+    Key(String),
+    Index(usize),
+}
+
+/**
+    Format a variable path the way it is written in a template.
+*/
+pub fn display_path(path: &[PathSegment]) -> String {
+    let mut out = String::new();
+    for segment in path {
+        match segment {
+            PathSegment::Key(key) => {
+                if !out.is_empty() {
+                    out.push('.');
+                }
+                out.push_str(key);
+            }
+            PathSegment::Index(index) => out.push_str(&format!("[{}]", index)),
+        }
+    }
+    out
+}
+
+/**
+    A filter applied to a value, with its (string) arguments.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    pub name: String,
+    pub args: Vec<String>,
+}
+
+/**
+    Result of evaluating an expression.
+
+    Undefined values are kept (with a description of what was missing) rather
+    than failing immediately, so a later `default(...)` filter can replace them.
+*/
+#[derive(Debug, Clone, PartialEq)]
+pub enum Evaluated {
+    Value(String),
+    Undefined(String),
+}
+
+/**
+    Parse the source of a `${{ ... }}` placeholder.
+*/
+pub fn parse(source: &str) -> Result<Expr> {
+    let tokens = tokenize(source)?;
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.expr()?;
+
+    if let Some(token) = parser.peek() {
+        return Err(anyhow!("Unexpected {} in expression '{}'", token, source));
+    }
+
+    Ok(expr)
+}
+
+/**
+    Collect the variable paths referenced by an expression, in order.
+*/
+pub fn variables(expr: &Expr) -> Vec<&[PathSegment]> {
+    match expr {
+        Expr::Variable(path) => vec![path.as_slice()],
+        Expr::Literal(_) => Vec::new(),
+        Expr::Concat(parts) => parts.iter().flat_map(variables).collect(),
+        Expr::Filtered { expr, .. } => variables(expr),
+    }
+}
+
+/**
+    Check that every filter in an expression exists and has the right arity.
+*/
+pub fn check_filters(expr: &Expr) -> Result<()> {
+    match expr {
+        Expr::Variable(_) | Expr::Literal(_) => Ok(()),
+        Expr::Concat(parts) => parts.iter().try_for_each(check_filters),
+        Expr::Filtered { expr, filters } => {
+            check_filters(expr)?;
+            filters.iter().try_for_each(check_filter)
+        }
+    }
+}
+
+/**
+    Evaluate an expression, resolving variable paths through `resolve`.
+*/
+pub fn evaluate(
+    expr: &Expr,
+    resolve: &dyn Fn(&[PathSegment]) -> Option<String>,
+) -> Result<Evaluated> {
+    match expr {
+        Expr::Variable(path) => Ok(match resolve(path) {
+            Some(value) => Evaluated::Value(value),
+            None => Evaluated::Undefined(format!("Undefined variable '{}'", display_path(path))),
+        }),
+        Expr::Literal(value) => Ok(Evaluated::Value(value.clone())),
+        Expr::Concat(parts) => {
+            let mut joined = String::new();
+            for part in parts {
+                match evaluate(part, resolve)? {
+                    Evaluated::Value(value) => joined.push_str(&value),
+                    undefined => return Ok(undefined),
+                }
+            }
+            Ok(Evaluated::Value(joined))
+        }
+        Expr::Filtered { expr, filters } => {
+            let mut value = evaluate(expr, resolve)?;
+            for filter in filters {
+                value = apply_filter(filter, value)?;
+            }
+            Ok(value)
+        }
+    }
+}
+
+// ── Filters ──────────────────────────────────────────────────────────────────
+
+fn apply_filter(filter: &Filter, value: Evaluated) -> Result<Evaluated> {
+    let name = filter.name.as_str();
+
+    if name == "default" {
+        let [fallback] = filter_args::<1>(filter)?;
+        return Ok(match value {
+            Evaluated::Value(v) if !v.is_empty() => Evaluated::Value(v),
+            _ => Evaluated::Value(fallback.clone()),
+        });
+    }
+
+    let Evaluated::Value(value) = value else {
+        // Validate the filter even when there is nothing to apply it to
+        check_filter(filter)?;
+        return Ok(value);
+    };
+
+    let result = match name {
+        "lower" => {
+            filter_args::<0>(filter)?;
+            value.to_lowercase()
+        }
+        "upper" => {
+            filter_args::<0>(filter)?;
+            value.to_uppercase()
+        }
+        "trim" => {
+            filter_args::<0>(filter)?;
+            value.trim().to_string()
+        }
+        "replace" => {
+            let [from, to] = filter_args::<2>(filter)?;
+            value.replace(from.as_str(), to)
+        }
+        "url_decode" => {
+            filter_args::<0>(filter)?;
+            url_decode(&value)?
+        }
+        "url_encode" => {
+            filter_args::<0>(filter)?;
+            url_encode(&value)
+        }
+        "base64_decode" => {
+            filter_args::<0>(filter)?;
+            base64_decode(&value)?
+        }
+        "base64_encode" => {
+            filter_args::<0>(filter)?;
+            STANDARD.encode(value.as_bytes())
+        }
+        "json_get" => {
+            let [path] = filter_args::<1>(filter)?;
+            return json_get(&value, path);
+        }
+        _ => return Err(anyhow!("Unknown filter '{}'", name)),
+    };
+
+    Ok(Evaluated::Value(result))
+}
+
+fn check_filter(filter: &Filter) -> Result<()> {
+    let expected = match filter.name.as_str() {
+        "lower" | "upper" | "trim" | "url_decode" | "url_encode" | "base64_decode"
+        | "base64_encode" => 0,
+        "default" | "json_get" => 1,
+        "replace" => 2,
+        other => return Err(anyhow!("Unknown filter '{}'", other)),
+    };
+
+    if filter.args.len() != expected {
+        return Err(anyhow!(
+            "Filter '{}' expects {} argument(s), got {}",
+            filter.name,
+            expected,
+            filter.args.len()
+        ));
+    }
+
+    Ok(())
+}
+
+fn filter_args<const N: usize>(filter: &Filter) -> Result<&[String; N]> {
+    filter.args.as_slice().try_into().map_err(|_| {
+        anyhow!(
+            "Filter '{}' expects {} argument(s), got {}",
+            filter.name,
+            N,
+            filter.args.len()
+        )
+    })
+}
+
+fn url_decode(value: &str) -> Result<String> {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+
+    while i < bytes.len() {
+        if bytes[i] == b'%'
+            && let Some(hex) = value.get(i + 1..i + 3)
+            && let Ok(byte) = u8::from_str_radix(hex, 16)
+        {
+            decoded.push(byte);
+            i += 3;
+        } else {
+            decoded.push(bytes[i]);
+            i += 1;
+        }
+    }
+
+    String::from_utf8(decoded).map_err(|_| anyhow!("url_decode produced invalid UTF-8"))
+}
+
+pub(crate) fn url_encode(value: &str) -> String {
+    let mut encoded = String::with_capacity(value.len());
+    for byte in value.bytes() {
+        if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'_' | b'.' | b'~') {
+            encoded.push(byte as char);
+        } else {
+            encoded.push_str(&format!("%{:02X}", byte));
+        }
+    }
+    encoded
+}
+
+fn base64_decode(value: &str) -> Result<String> {
+    let trimmed = value.trim();
+    let bytes = [STANDARD, URL_SAFE, STANDARD_NO_PAD, URL_SAFE_NO_PAD]
+        .iter()
+        .find_map(|engine| engine.decode(trimmed).ok())
+        .ok_or_else(|| anyhow!("base64_decode: value is not valid base64"))?;
+
+    String::from_utf8(bytes).map_err(|_| anyhow!("base64_decode produced invalid UTF-8"))
+}
+
+fn json_get(value: &str, path: &str) -> Result<Evaluated> {
+    let json: serde_json::Value = serde_json::from_str(value)
+        .map_err(|e| anyhow!("json_get: value is not valid JSON: {}", e))?;
+
+    let mut current = &json;
+    for segment in path.split('.').filter(|s| !s.is_empty()) {
+        let next = match current {
+            serde_json::Value::Array(items) => {
+                segment.parse::<usize>().ok().and_then(|i| items.get(i))
+            }
+            serde_json::Value::Object(map) => map.get(segment),
+            _ => None,
+        };
+        match next {
+            Some(next) => current = next,
+            None => {
+                return Ok(Evaluated::Undefined(format!(
+                    "JSON path '{}' not found",
+                    path
+                )));
+            }
+        }
+    }
+
+    Ok(match current {
+        serde_json::Value::Null => Evaluated::Undefined(format!("JSON path '{}' is null", path)),
+        serde_json::Value::String(s) => Evaluated::Value(s.clone()),
+        other => Evaluated::Value(other.to_string()),
+    })
+}
+
+// ── Parser ───────────────────────────────────────────────────────────────────
+
+#[derive(Debug, Clone, PartialEq)]
+enum Token {
+    Ident(String),
+    Str(String),
+    Dot,
+    Pipe,
+    Plus,
+    Comma,
+    LParen,
+    RParen,
+    LBracket,
+    RBracket,
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::Ident(name) => write!(f, "'{}'", name),
+            Token::Str(value) => write!(f, "string {:?}", value),
+            Token::Dot => write!(f, "'.'"),
+            Token::Pipe => write!(f, "'|'"),
+            Token::Plus => write!(f, "'+'"),
+            Token::Comma => write!(f, "','"),
+            Token::LParen => write!(f, "'('"),
+            Token::RParen => write!(f, "')'"),
+            Token::LBracket => write!(f, "'['"),
+            Token::RBracket => write!(f, "']'"),
+        }
+    }
+}
+
+fn tokenize(source: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut chars = source.chars().peekable();
+
+    while let Some(&ch) = chars.peek() {
+        match ch {
+            c if c.is_whitespace() => {
+                chars.next();
+            }
+            '.' => {
+                chars.next();
+                tokens.push(Token::Dot);
+            }
+            '|' => {
+                chars.next();
+                tokens.push(Token::Pipe);
+            }
+            '+' => {
+                chars.next();
+                tokens.push(Token::Plus);
+            }
+            ',' => {
+                chars.next();
+                tokens.push(Token::Comma);
+            }
+            '(' => {
+                chars.next();
+                tokens.push(Token::LParen);
+            }
+            ')' => {
+                chars.next();
+                tokens.push(Token::RParen);
+            }
+            '[' => {
+                chars.next();
+                tokens.push(Token::LBracket);
+            }
+            ']' => {
+                chars.next();
+                tokens.push(Token::RBracket);
+            }
+            '"' | '\'' => {
+                let quote = ch;
+                chars.next();
+                let mut value = String::new();
+                loop {
+                    match chars.next() {
+                        Some('\\') => match chars.next() {
+                            Some('n') => value.push('\n'),
+                            Some('t') => value.push('\t'),
+                            Some(escaped) => value.push(escaped),
+                            None => break,
+                        },
+                        Some(c) if c == quote => break,
+                        Some(c) => value.push(c),
+                        None => {
+                            return Err(anyhow!("Unterminated string in expression '{}'", source));
+                        }
+                    }
+                }
+                tokens.push(Token::Str(value));
+            }
+            c if c.is_ascii_alphanumeric() || c == '_' || c == '-' => {
+                let mut ident = String::new();
+                while let Some(&c) = chars.peek() {
+                    if c.is_ascii_alphanumeric() || c == '_' || c == '-' {
+                        ident.push(c);
+                        chars.next();
+                    } else {
+                        break;
+                    }
+                }
+                tokens.push(Token::Ident(ident));
+            }
+            other => {
+                return Err(anyhow!(
+                    "Unexpected character '{}' in expression '{}'",
+                    other,
+                    source
+                ));
+            }
+        }
+    }
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.pos).cloned();
+        self.pos += 1;
+        token
+    }
+
+    fn eat(&mut self, token: &Token) -> bool {
+        if self.peek() == Some(token) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn expect(&mut self, token: &Token) -> Result<()> {
+        match self.next() {
+            Some(ref t) if t == token => Ok(()),
+            Some(t) => Err(anyhow!("Expected {}, found {}", token, t)),
+            None => Err(anyhow!("Expected {}, found end of expression", token)),
+        }
+    }
+
+    fn expr(&mut self) -> Result<Expr> {
+        let mut parts = vec![self.operand()?];
+        while self.eat(&Token::Plus) {
+            parts.push(self.operand()?);
+        }
+
+        if parts.len() == 1 {
+            Ok(parts.remove(0))
+        } else {
+            Ok(Expr::Concat(parts))
+        }
+    }
+
+    fn operand(&mut self) -> Result<Expr> {
+        let primary = self.primary()?;
+
+        let mut filters = Vec::new();
+        while self.eat(&Token::Pipe) {
+            filters.push(self.filter()?);
+        }
+
+        if filters.is_empty() {
+            Ok(primary)
+        } else {
+            Ok(Expr::Filtered {
+                expr: Box::new(primary),
+                filters,
+            })
+        }
+    }
+
+    fn primary(&mut self) -> Result<Expr> {
+        match self.next() {
+            Some(Token::Str(value)) => Ok(Expr::Literal(value)),
+            Some(Token::LParen) => {
+                let expr = self.expr()?;
+                self.expect(&Token::RParen)?;
+                Ok(expr)
+            }
+            Some(Token::Ident(first)) => {
+                let mut path = vec![PathSegment::Key(first)];
+                loop {
+                    if self.eat(&Token::Dot) {
+                        match self.next() {
+                            Some(Token::Ident(segment)) => path.push(PathSegment::Key(segment)),
+                            Some(t) => return Err(anyhow!("Expected name after '.', found {}", t)),
+                            None => return Err(anyhow!("Expected name after '.'")),
+                        }
+                    } else if self.eat(&Token::LBracket) {
+                        path.push(self.index()?);
+                        self.expect(&Token::RBracket)?;
+                    } else {
+                        break;
+                    }
+                }
+                Ok(Expr::Variable(path))
+            }
+            Some(t) => Err(anyhow!("Expected a variable or string, found {}", t)),
+            None => Err(anyhow!("Empty expression")),
+        }
+    }
+
+    fn index(&mut self) -> Result<PathSegment> {
+        match self.next() {
+            Some(Token::Str(key)) => Ok(PathSegment::Key(key)),
+            Some(Token::Ident(index)) => index
+                .parse()
+                .map(PathSegment::Index)
+                .map_err(|_| anyhow!("Expected an index inside '[]', found '{}'", index)),
+            Some(t) => Err(anyhow!("Expected an index inside '[]', found {}", t)),
+            None => Err(anyhow!("Expected an index after '['")),
+        }
+    }
+
+    fn filter(&mut self) -> Result<Filter> {
+        let name = match self.next() {
+            Some(Token::Ident(name)) => name,
+            Some(t) => return Err(anyhow!("Expected filter name after '|', found {}", t)),
+            None => return Err(anyhow!("Expected filter name after '|'")),
+        };
+
+        let mut args = Vec::new();
+        if self.eat(&Token::LParen) && !self.eat(&Token::RParen) {
+            loop {
+                match self.next() {
+                    Some(Token::Str(value)) | Some(Token::Ident(value)) => args.push(value),
+                    Some(t) => {
+                        return Err(anyhow!(
+                            "Expected argument for filter '{}', found {}",
+                            name,
+                            t
+                        ));
+                    }
+                    None => return Err(anyhow!("Unterminated arguments for filter '{}'", name)),
+                }
+                if self.eat(&Token::RParen) {
+                    break;
+                }
+                self.expect(&Token::Comma)?;
+            }
+        }
+
+        Ok(Filter { name, args })
+    }
+}
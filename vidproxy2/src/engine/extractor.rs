@@ -1,4 +1,4 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::sync::OnceLock;
 
 use anyhow::{Result, anyhow};
@@ -7,7 +7,8 @@ use regex::Regex;
 use scraper::{ElementRef, Html, Selector};
 use sxd_xpath::nodeset::Node;
 
-use super::step::{Extractor, ExtractorKind};
+use super::step::{Extractor, ExtractorKind, Transforms};
+use super::transform::{apply_transforms, check_transforms, transform_items};
 
 /**
     Result of extracting from an array - a list of objects with string fields.
@@ -22,9 +23,13 @@ pub fn extract(
     content: &str,
     url: &str,
     headers: Option<&HeaderMap>,
+    status: Option<u16>,
 ) -> Result<String> {
     let value = match extractor.kind {
         ExtractorKind::Url => Ok(url.to_string()),
+        ExtractorKind::Status => status.map(|s| s.to_string()).ok_or_else(|| {
+            anyhow!("status extractor can only be used with Fetch and FetchInBrowser steps")
+        }),
         ExtractorKind::UrlRegex => extract_regex(extractor, url),
         ExtractorKind::Header => extract_header(extractor, headers),
         ExtractorKind::JsonPath => extract_jsonpath(extractor, content),
@@ -40,21 +45,33 @@ pub fn extract(
         ExtractorKind::RegexArray => Err(anyhow!("Use extract_array() for regex_array extractors")),
         ExtractorKind::Line => extract_line(content),
         ExtractorKind::Pssh => extract_pssh(content, url),
+        ExtractorKind::ScriptJson => extract_script_json(extractor, content),
+        ExtractorKind::ScriptJsonArray => Err(anyhow!(
+            "Use extract_array() for script_json_array extractors"
+        )),
+        ExtractorKind::HlsVariants | ExtractorKind::DashRepresentations => {
+            Err(anyhow!("Use extract_array() for playlist extractors"))
+        }
     }?;
 
-    if extractor.unescape {
-        Ok(unescape_json_string(&value))
+    let value = if extractor.unescape {
+        unescape_json_string(&value)
     } else {
-        Ok(value)
+        value
+    };
+
+    match &extractor.transform {
+        Some(Transforms::Value(transforms)) => apply_transforms(transforms, &value, url),
+        _ => Ok(value),
     }
 }
 
 /**
-    Run an array extractor on the given content.
+    Run an array extractor on the given content, read from `url`.
     Returns raw items without any domain-specific filtering.
 */
-pub fn extract_array(extractor: &Extractor, content: &str) -> Result<ExtractedArray> {
-    match extractor.kind {
+pub fn extract_array(extractor: &Extractor, content: &str, url: &str) -> Result<ExtractedArray> {
+    let mut items = match extractor.kind {
         ExtractorKind::JsonPathArray => {
             let path = extractor
                 .path
@@ -69,10 +86,30 @@ pub fn extract_array(extractor: &Extractor, content: &str) -> Result<ExtractedAr
         ExtractorKind::RegexArray => extract_regex_array(extractor, content),
         ExtractorKind::XPathArray => extract_xpath_array(extractor, content),
         ExtractorKind::CssArray => extract_css_array(extractor, content),
+        ExtractorKind::ScriptJsonArray => {
+            let path = extractor
+                .path
+                .as_ref()
+                .ok_or_else(|| anyhow!("script_json_array extractor requires 'path'"))?;
+            let each = extractor
+                .each
+                .as_ref()
+                .ok_or_else(|| anyhow!("script_json_array extractor requires 'each'"))?;
+            extract_jsonpath_array(&find_script_json(extractor, content)?, path, each)
+        }
+        ExtractorKind::HlsVariants => {
+            filter_renditions(extractor, extract_hls_variants(content, url)?)
+        }
+        ExtractorKind::DashRepresentations => {
+            filter_renditions(extractor, extract_dash_representations(content, url)?)
+        }
         _ => Err(anyhow!(
             "extract_array() only works with array extractor kinds"
         )),
-    }
+    }?;
+
+    transform_items(extractor.transform.as_ref(), &mut items, url);
+    Ok(items)
 }
 
 // ── Header ───────────────────────────────────────────────────────────────────
@@ -85,7 +122,7 @@ fn extract_header(extractor: &Extractor, headers: Option<&HeaderMap>) -> Result<
 
     let Some(headers) = headers else {
         return Err(anyhow!(
-            "header extractor can only be used with Sniff steps"
+            "header extractor can only be used with Sniff, Fetch and FetchInBrowser steps"
         ));
     };
 
@@ -101,7 +138,7 @@ fn extract_header(extractor: &Extractor, headers: Option<&HeaderMap>) -> Result<
     }
 
     Err(anyhow!(
-        "Request header '{}' not found or invalid UTF-8",
+        "Header '{}' not found or invalid UTF-8",
         header_name
     ))
 }
@@ -214,15 +251,14 @@ fn extract_jsonpath_simple(
     let jsonpath =
         JsonPath::from_str(path).map_err(|e| anyhow!("Invalid JSONPath '{}': {}", path, e))?;
 
-    let results = jsonpath.find_slice(json);
-
-    if results.is_empty() {
-        return Err(anyhow!("JSONPath '{}' returned no results", path));
-    }
-
     let mut extracted = Vec::new();
 
-    for result in results {
+    // An empty array matches nothing and yields no items
+    for result in jsonpath
+        .find_slice(json)
+        .into_iter()
+        .filter(|r| r.has_value())
+    {
         let obj = result.clone().to_data();
         let mut fields: HashMap<String, Option<String>> = HashMap::new();
 
@@ -253,22 +289,14 @@ fn extract_jsonpath_with_parent(
     let child_jsonpath = JsonPath::from_str(&child_path)
         .map_err(|e| anyhow!("Invalid child JSONPath '{}': {}", child_path, e))?;
 
-    let parent_results = parent_jsonpath.find_slice(json);
-
-    if parent_results.is_empty() {
-        return Err(anyhow!(
-            "Parent JSONPath '{}' returned no results",
-            parent_path
-        ));
-    }
-
     let mut extracted = Vec::new();
 
-    for parent_result in parent_results {
+    let parent_results = parent_jsonpath.find_slice(json);
+    for parent_result in parent_results.into_iter().filter(|r| r.has_value()) {
         let parent_obj = parent_result.clone().to_data();
         let child_results = child_jsonpath.find_slice(&parent_obj);
 
-        for child_result in child_results {
+        for child_result in child_results.into_iter().filter(|r| r.has_value()) {
             let child_obj = child_result.clone().to_data();
             let mut fields: HashMap<String, Option<String>> = HashMap::new();
 
@@ -893,6 +921,696 @@ fn extract_pssh(content: &str, url: &str) -> Result<String> {
     Ok(pssh)
 }
 
+// ── Script JSON ──────────────────────────────────────────────────────────────
+
+/**
+    Extract from a JSON document embedded in an HTML page. With a `path`, the
+    document is queried like a `jsonpath` extractor; without, it is returned
+    whole.
+*/
+fn extract_script_json(extractor: &Extractor, content: &str) -> Result<String> {
+    let json = find_script_json(extractor, content)?;
+    match &extractor.path {
+        Some(_) => extract_jsonpath(extractor, &json),
+        None => Ok(json),
+    }
+}
+
+/**
+    The JSON document held by the `<script>` element matching `selector`, or
+    assigned to `variable` (`name = {...}` or `name = JSON.parse("...")`).
+
+    Only the JSON value itself is read, so code after it in the same script
+    is ignored and nested braces inside strings are handled.
+*/
+fn find_script_json(extractor: &Extractor, content: &str) -> Result<String> {
+    match (&extractor.selector, &extractor.variable) {
+        (Some(selector), None) => {
+            let parsed = Selector::parse(selector)
+                .map_err(|e| anyhow!("Invalid CSS selector '{}': {:?}", selector, e))?;
+            let document = Html::parse_document(content);
+            let text: String = document
+                .select(&parsed)
+                .next()
+                .ok_or_else(|| anyhow!("CSS selector '{}' returned no results", selector))?
+                .text()
+                .collect();
+
+            text.find(['{', '['])
+                .and_then(|start| json_value_at(&text[start..]))
+                .map(str::to_string)
+                .ok_or_else(|| anyhow!("Script '{}' contains no JSON", selector))
+        }
+        (None, Some(variable)) => {
+            let pattern = format!(r"(?:^|[^\w$.]){}\s*=\s*", regex::escape(variable));
+            let re = Regex::new(&pattern)
+                .map_err(|e| anyhow!("Invalid variable name '{}': {}", variable, e))?;
+
+            re.find_iter(content)
+                .find_map(|m| assigned_json(&content[m.end()..]))
+                .ok_or_else(|| anyhow!("No JSON assigned to '{}'", variable))
+        }
+        _ => Err(anyhow!(
+            "script_json extractor requires exactly one of 'selector' or 'variable'"
+        )),
+    }
+}
+
+/**
+    The JSON value at the start of `text`, without anything following it.
+*/
+fn json_value_at(text: &str) -> Option<&str> {
+    let mut values = serde_json::Deserializer::from_str(text).into_iter::<serde::de::IgnoredAny>();
+    match values.next() {
+        Some(Ok(_)) => Some(text[..values.byte_offset()].trim()),
+        _ => None,
+    }
+}
+
+fn assigned_json(value: &str) -> Option<String> {
+    match value.strip_prefix("JSON.parse(") {
+        Some(argument) => {
+            let literal = json_value_at(argument)?;
+            let decoded: String = serde_json::from_str(literal).ok()?;
+            json_value_at(&decoded).map(str::to_string)
+        }
+        None => json_value_at(value).map(str::to_string),
+    }
+}
+
+// ── Playlists ────────────────────────────────────────────────────────────────
+
+const HLS_FIELDS: &[&str] = &[
+    "type",
+    "uri",
+    "bandwidth",
+    "average_bandwidth",
+    "resolution",
+    "width",
+    "height",
+    "codecs",
+    "frame_rate",
+    "audio",
+    "subtitles",
+    "group",
+    "language",
+    "name",
+    "default",
+];
+
+const DASH_FIELDS: &[&str] = &[
+    "type",
+    "id",
+    "uri",
+    "bandwidth",
+    "resolution",
+    "width",
+    "height",
+    "codecs",
+    "frame_rate",
+    "language",
+    "mime_type",
+];
+
+/**
+    Parse an HLS master playlist into one item per variant stream, followed
+    by one item per alternative rendition (`EXT-X-MEDIA`) with its own URI.
+
+    Fields: `type` (`variant`, `audio`, `subtitles`), `uri` (absolute),
+    `bandwidth`, `average_bandwidth`, `resolution`, `width`, `height`,
+    `codecs`, `frame_rate`, `audio` and `subtitles` (group IDs), `group`,
+    `language`, `name` and `default`. A variant's `language` is that of the
+    default rendition in its audio group.
+*/
+fn extract_hls_variants(content: &str, url: &str) -> Result<ExtractedArray> {
+    if !content.trim_start().starts_with("#EXTM3U") {
+        return Err(anyhow!("Content is not an M3U8 playlist"));
+    }
+
+    let base = url::Url::parse(url).ok();
+    let mut variants = Vec::new();
+    let mut media = Vec::new();
+    let mut stream_inf: Option<HashMap<String, String>> = None;
+
+    for line in content.lines().map(str::trim) {
+        if let Some(attributes) = line.strip_prefix("#EXT-X-STREAM-INF:") {
+            stream_inf = Some(parse_attribute_list(attributes));
+        } else if let Some(attributes) = line.strip_prefix("#EXT-X-MEDIA:") {
+            media.push(parse_attribute_list(attributes));
+        } else if !line.is_empty()
+            && !line.starts_with('#')
+            && let Some(attributes) = stream_inf.take()
+        {
+            variants.push((attributes, line));
+        }
+    }
+
+    let group_language = |group: &str| {
+        let renditions: Vec<_> = media
+            .iter()
+            .filter(|m| m.get("TYPE").is_some_and(|t| t == "AUDIO"))
+            .filter(|m| m.get("GROUP-ID").is_some_and(|g| g == group))
+            .collect();
+        renditions
+            .iter()
+            .find(|m| m.get("DEFAULT").is_some_and(|d| d == "YES"))
+            .or(renditions.first())
+            .and_then(|m| m.get("LANGUAGE").cloned())
+    };
+
+    let mut items: ExtractedArray = Vec::new();
+    for (attributes, uri) in &variants {
+        let get = |name: &str| attributes.get(name).cloned();
+        let resolution = get("RESOLUTION");
+        let (width, height) = split_resolution(resolution.as_deref());
+        let audio = get("AUDIO");
+        items.push(HashMap::from([
+            ("type".to_string(), Some("variant".to_string())),
+            ("uri".to_string(), Some(resolve_uri(base.as_ref(), uri))),
+            ("bandwidth".to_string(), get("BANDWIDTH")),
+            ("average_bandwidth".to_string(), get("AVERAGE-BANDWIDTH")),
+            ("resolution".to_string(), resolution),
+            ("width".to_string(), width),
+            ("height".to_string(), height),
+            ("codecs".to_string(), get("CODECS")),
+            ("frame_rate".to_string(), get("FRAME-RATE")),
+            (
+                "language".to_string(),
+                audio.as_deref().and_then(group_language),
+            ),
+            ("audio".to_string(), audio),
+            ("subtitles".to_string(), get("SUBTITLES")),
+            ("group".to_string(), None),
+            ("name".to_string(), None),
+            ("default".to_string(), None),
+        ]));
+    }
+    sort_by_bandwidth(&mut items);
+
+    for attributes in &media {
+        let Some(uri) = attributes.get("URI") else {
+            continue;
+        };
+        let get = |name: &str| attributes.get(name).cloned();
+        let kind = get("TYPE").map(|t| t.to_ascii_lowercase());
+        let group = get("GROUP-ID");
+        items.push(HashMap::from([
+            ("uri".to_string(), Some(resolve_uri(base.as_ref(), uri))),
+            ("bandwidth".to_string(), None),
+            ("average_bandwidth".to_string(), None),
+            ("resolution".to_string(), None),
+            ("width".to_string(), None),
+            ("height".to_string(), None),
+            ("codecs".to_string(), None),
+            ("frame_rate".to_string(), None),
+            ("language".to_string(), get("LANGUAGE")),
+            (
+                "audio".to_string(),
+                group.clone().filter(|_| kind.as_deref() == Some("audio")),
+            ),
+            (
+                "subtitles".to_string(),
+                group
+                    .clone()
+                    .filter(|_| kind.as_deref() == Some("subtitles")),
+            ),
+            ("type".to_string(), kind),
+            ("group".to_string(), group),
+            ("name".to_string(), get("NAME")),
+            ("default".to_string(), get("DEFAULT")),
+        ]));
+    }
+
+    if items.is_empty() {
+        return Err(anyhow!("Playlist has no variants (not a master playlist?)"));
+    }
+    Ok(items)
+}
+
+/**
+    Parse an HLS attribute list (`KEY=value,KEY="quoted, value"`).
+*/
+fn parse_attribute_list(input: &str) -> HashMap<String, String> {
+    let mut attributes = HashMap::new();
+    let mut rest = input;
+
+    while let Some((key, after)) = rest.split_once('=') {
+        let key = key.trim().trim_start_matches(',').trim().to_string();
+        let (value, after) = match after.strip_prefix('"') {
+            Some(quoted) => match quoted.split_once('"') {
+                Some((value, after)) => (value, after),
+                None => (quoted, ""),
+            },
+            None => after.split_once(',').unwrap_or((after, "")),
+        };
+        attributes.insert(key, value.to_string());
+        rest = after.trim_start_matches(',');
+    }
+
+    attributes
+}
+
+/**
+    Parse a DASH MPD into one item per `Representation`.
+
+    Fields: `type` (`video`, `audio`, `text`), `id`, `uri` (the absolute
+    `BaseURL` of the representation, or the MPD URL), `bandwidth`,
+    `resolution`, `width`, `height`, `codecs`, `frame_rate`, `language` and
+    `mime_type`. Attributes missing on a representation are taken from its
+    `AdaptationSet`.
+*/
+fn extract_dash_representations(content: &str, url: &str) -> Result<ExtractedArray> {
+    let package = sxd_document::parser::parse(content)
+        .map_err(|e| anyhow!("Failed to parse MPD: {:?}", e))?;
+    let document = package.as_document();
+
+    let mpd = document
+        .root()
+        .children()
+        .into_iter()
+        .find_map(|child| match child {
+            sxd_document::dom::ChildOfRoot::Element(e) if e.name().local_part() == "MPD" => Some(e),
+            _ => None,
+        })
+        .ok_or_else(|| anyhow!("Content is not an MPD"))?;
+
+    let mpd_base = dash_base_url(mpd, &url::Url::parse(url).ok());
+    let mut items: ExtractedArray = Vec::new();
+
+    for period in child_elements(mpd, "Period") {
+        let period_base = dash_base_url(period, &mpd_base);
+        for set in child_elements(period, "AdaptationSet") {
+            let set_base = dash_base_url(set, &period_base);
+            for representation in child_elements(set, "Representation") {
+                let uri = dash_base_url(representation, &set_base)
+                    .map(|u| u.to_string())
+                    .unwrap_or_else(|| url.to_string());
+                let get = |name: &str| {
+                    representation
+                        .attribute_value(name)
+                        .or_else(|| set.attribute_value(name))
+                        .map(str::to_string)
+                };
+                let mime_type = get("mimeType");
+                let kind = set
+                    .attribute_value("contentType")
+                    .map(str::to_string)
+                    .or_else(|| {
+                        mime_type
+                            .as_deref()
+                            .and_then(|m| m.split('/').next())
+                            .map(|m| if m == "application" { "text" } else { m }.to_string())
+                    });
+                let (width, height) = (get("width"), get("height"));
+                let resolution = width
+                    .as_ref()
+                    .zip(height.as_ref())
+                    .map(|(w, h)| format!("{}x{}", w, h));
+
+                items.push(HashMap::from([
+                    ("type".to_string(), kind),
+                    ("id".to_string(), get("id")),
+                    ("uri".to_string(), Some(uri)),
+                    ("bandwidth".to_string(), get("bandwidth")),
+                    ("resolution".to_string(), resolution),
+                    ("width".to_string(), width),
+                    ("height".to_string(), height),
+                    ("codecs".to_string(), get("codecs")),
+                    ("frame_rate".to_string(), get("frameRate")),
+                    ("language".to_string(), get("lang")),
+                    ("mime_type".to_string(), mime_type),
+                ]));
+            }
+        }
+    }
+
+    if items.is_empty() {
+        return Err(anyhow!("MPD has no representations"));
+    }
+    sort_by_bandwidth(&mut items);
+    Ok(items)
+}
+
+fn child_elements<'d>(
+    element: sxd_document::dom::Element<'d>,
+    name: &str,
+) -> Vec<sxd_document::dom::Element<'d>> {
+    element
+        .children()
+        .into_iter()
+        .filter_map(|child| match child {
+            sxd_document::dom::ChildOfElement::Element(e) if e.name().local_part() == name => {
+                Some(e)
+            }
+            _ => None,
+        })
+        .collect()
+}
+
+/**
+    The base URL of an MPD element: its `BaseURL` resolved against the
+    parent's, or the parent's if it has none.
+*/
+fn dash_base_url(
+    element: sxd_document::dom::Element<'_>,
+    parent: &Option<url::Url>,
+) -> Option<url::Url> {
+    let Some(base) = child_elements(element, "BaseURL").into_iter().next() else {
+        return parent.clone();
+    };
+    let text: String = base
+        .children()
+        .into_iter()
+        .filter_map(|child| match child {
+            sxd_document::dom::ChildOfElement::Text(t) => Some(t.text().to_string()),
+            _ => None,
+        })
+        .collect();
+    match parent {
+        Some(parent) => parent.join(text.trim()).ok(),
+        None => url::Url::parse(text.trim()).ok(),
+    }
+}
+
+/**
+    Keep the renditions matching every `field=value` condition of the
+    extractor's `path` (comma-separated, compared case-insensitively).
+*/
+fn filter_renditions(extractor: &Extractor, items: ExtractedArray) -> Result<ExtractedArray> {
+    let Some(path) = extractor.path.as_deref().filter(|p| !p.trim().is_empty()) else {
+        return Ok(items);
+    };
+    let conditions = parse_rendition_filter(path)?;
+
+    let items: ExtractedArray = items
+        .into_iter()
+        .filter(|item| {
+            conditions.iter().all(|(field, expected)| {
+                item.get(*field)
+                    .and_then(|v| v.as_deref())
+                    .is_some_and(|v| v.eq_ignore_ascii_case(expected))
+            })
+        })
+        .collect();
+
+    if items.is_empty() {
+        return Err(anyhow!("No renditions matching '{}'", path));
+    }
+    Ok(items)
+}
+
+fn parse_rendition_filter(path: &str) -> Result<Vec<(&str, &str)>> {
+    path.split(',')
+        .map(|condition| {
+            condition
+                .split_once('=')
+                .map(|(field, value)| (field.trim(), value.trim()))
+                .filter(|(field, _)| !field.is_empty())
+                .ok_or_else(|| {
+                    anyhow!(
+                        "Invalid rendition filter '{}': expected field=value",
+                        condition.trim()
+                    )
+                })
+        })
+        .collect()
+}
+
+/**
+    Order items by bandwidth, highest first, keeping document order for ties
+    and items without one.
+*/
+fn sort_by_bandwidth(items: &mut ExtractedArray) {
+    items.sort_by_key(|item| {
+        let bandwidth = item
+            .get("bandwidth")
+            .and_then(|b| b.as_deref())
+            .and_then(|b| b.parse::<u64>().ok())
+            .unwrap_or(0);
+        std::cmp::Reverse(bandwidth)
+    });
+}
+
+fn split_resolution(resolution: Option<&str>) -> (Option<String>, Option<String>) {
+    match resolution.and_then(|r| r.split_once(['x', 'X'])) {
+        Some((width, height)) => (Some(width.to_string()), Some(height.to_string())),
+        None => (None, None),
+    }
+}
+
+fn resolve_uri(base: Option<&url::Url>, uri: &str) -> String {
+    base.and_then(|base| base.join(uri).ok())
+        .map(|url| url.to_string())
+        .unwrap_or_else(|| uri.to_string())
+}
+
+// ── Validation ───────────────────────────────────────────────────────────────
+
+/**
+    Statically check an extractor without running it.
+
+    Reports missing required fields and selectors/patterns that don't compile.
+    Fields containing `${{...}}` placeholders are interpolated at runtime, so
+    they are not compiled here.
+*/
+pub fn check_extractor(extractor: &Extractor) -> Vec<String> {
+    let mut errors = Vec::new();
+    let kind = extractor_kind_name(&extractor.kind);
+    let is_template = |value: &str| value.contains("${{");
+
+    let needs_path = !matches!(
+        extractor.kind,
+        ExtractorKind::Url
+            | ExtractorKind::Status
+            | ExtractorKind::Line
+            | ExtractorKind::Pssh
+            | ExtractorKind::ScriptJson
+            | ExtractorKind::HlsVariants
+            | ExtractorKind::DashRepresentations
+    );
+    let path = extractor.path.as_deref();
+    if needs_path && path.is_none() {
+        errors.push(format!("{} extractor requires 'path'", kind));
+    }
+
+    if let Some(path) = path.filter(|p| !is_template(p)) {
+        let result = match extractor.kind {
+            ExtractorKind::UrlRegex | ExtractorKind::Regex | ExtractorKind::RegexArray => {
+                check_regex(path)
+            }
+            ExtractorKind::JsonPath | ExtractorKind::JsonPathRegex | ExtractorKind::ScriptJson => {
+                check_jsonpath(path)
+            }
+            ExtractorKind::JsonPathArray | ExtractorKind::ScriptJsonArray => {
+                let needs_parent = extractor
+                    .each
+                    .as_ref()
+                    .is_some_and(|each| each.values().any(|p| p.contains("$parent")));
+                if needs_parent {
+                    split_nested_path(path).and_then(|(parent, child)| {
+                        check_jsonpath(&parent)?;
+                        check_jsonpath(&child)
+                    })
+                } else {
+                    check_jsonpath(path)
+                }
+            }
+            ExtractorKind::Css => check_css_path(path),
+            ExtractorKind::CssArray => {
+                if path.contains("::") {
+                    Err(anyhow!(
+                        "css_array path '{}' should be a selector without ::text/::attr",
+                        path
+                    ))
+                } else {
+                    check_css_selector(path)
+                }
+            }
+            ExtractorKind::XPath | ExtractorKind::XPathArray => check_xpath(path),
+            ExtractorKind::HlsVariants | ExtractorKind::DashRepresentations => {
+                parse_rendition_filter(path).map(|_| ())
+            }
+            _ => Ok(()),
+        };
+        if let Err(e) = result {
+            errors.push(e.to_string());
+        }
+    }
+
+    if extractor.kind == ExtractorKind::JsonPathRegex {
+        match extractor.regex.as_deref() {
+            None => errors.push(format!("{} extractor requires 'regex'", kind)),
+            Some(pattern) if !is_template(pattern) => {
+                if let Err(e) = check_regex(pattern) {
+                    errors.push(e.to_string());
+                }
+            }
+            Some(_) => {}
+        }
+    }
+
+    let script_json = matches!(
+        extractor.kind,
+        ExtractorKind::ScriptJson | ExtractorKind::ScriptJsonArray
+    );
+    match (&extractor.selector, &extractor.variable) {
+        _ if !script_json => {
+            if extractor.selector.is_some() || extractor.variable.is_some() {
+                errors.push(format!(
+                    "{} extractor does not use 'selector' or 'variable'",
+                    kind
+                ));
+            }
+        }
+        (Some(selector), None) => {
+            if !is_template(selector)
+                && let Err(e) = check_css_selector(selector)
+            {
+                errors.push(e.to_string());
+            }
+        }
+        (None, Some(_)) => {}
+        _ => errors.push(format!(
+            "{} extractor requires exactly one of 'selector' or 'variable'",
+            kind
+        )),
+    }
+
+    if let Some(transforms) = &extractor.transform {
+        let fields: Option<HashSet<String>> = match extractor.kind {
+            ExtractorKind::HlsVariants => Some(HLS_FIELDS.iter().map(|f| f.to_string()).collect()),
+            ExtractorKind::DashRepresentations => {
+                Some(DASH_FIELDS.iter().map(|f| f.to_string()).collect())
+            }
+            _ if super::step::is_array_extractor(&extractor.kind) => Some(
+                extractor
+                    .each
+                    .iter()
+                    .flatten()
+                    .map(|(k, _)| k.clone())
+                    .collect(),
+            ),
+            _ => None,
+        };
+        errors.extend(check_transforms(transforms, fields.as_ref()));
+    }
+
+    if super::step::is_playlist_extractor(&extractor.kind) {
+        if extractor.each.is_some() {
+            errors.push(format!(
+                "{} extractor has fixed fields and takes no 'each'",
+                kind
+            ));
+        }
+        return errors;
+    }
+
+    if super::step::is_array_extractor(&extractor.kind) {
+        let Some(each) = &extractor.each else {
+            errors.push(format!("{} extractor requires 'each'", kind));
+            return errors;
+        };
+
+        let regex = match extractor.kind {
+            ExtractorKind::RegexArray => path
+                .filter(|p| !is_template(p))
+                .and_then(|p| Regex::new(p).ok()),
+            _ => None,
+        };
+
+        let mut fields: Vec<_> = each.iter().collect();
+        fields.sort();
+
+        for (field, spec) in fields {
+            let candidates = spec
+                .split('|')
+                .map(|c| c.trim())
+                .filter(|c| !c.is_empty() && !c.starts_with("const:") && !is_template(c));
+
+            for candidate in candidates {
+                let result = match extractor.kind {
+                    ExtractorKind::JsonPathArray | ExtractorKind::ScriptJsonArray => {
+                        check_jsonpath(&candidate.replacen("$parent", "$", 1))
+                    }
+                    ExtractorKind::CssArray => check_css_path(candidate),
+                    ExtractorKind::XPathArray => check_xpath(candidate),
+                    ExtractorKind::RegexArray => match &regex {
+                        Some(re) => check_regex_group(re, candidate),
+                        None => Ok(()),
+                    },
+                    _ => Ok(()),
+                };
+                if let Err(e) = result {
+                    errors.push(format!("Field '{}': {}", field, e));
+                }
+            }
+        }
+    }
+
+    errors
+}
+
+fn extractor_kind_name(kind: &ExtractorKind) -> String {
+    serde_json::to_value(kind)
+        .ok()
+        .and_then(|v| v.as_str().map(str::to_string))
+        .unwrap_or_else(|| format!("{:?}", kind))
+}
+
+fn check_regex(pattern: &str) -> Result<()> {
+    Regex::new(pattern)
+        .map(|_| ())
+        .map_err(|e| anyhow!("Invalid regex '{}': {}", pattern, e))
+}
+
+fn check_regex_group(re: &Regex, group: &str) -> Result<()> {
+    let exists = match group.parse::<usize>() {
+        Ok(index) => index < re.captures_len(),
+        Err(_) => re.capture_names().flatten().any(|name| name == group),
+    };
+    if exists {
+        Ok(())
+    } else {
+        Err(anyhow!(
+            "Regex '{}' has no capture group '{}'",
+            re.as_str(),
+            group
+        ))
+    }
+}
+
+fn check_jsonpath(path: &str) -> Result<()> {
+    use jsonpath_rust::JsonPath;
+    use std::str::FromStr;
+
+    JsonPath::<serde_json::Value>::from_str(path)
+        .map(|_| ())
+        .map_err(|e| anyhow!("Invalid JSONPath '{}': {}", path, e))
+}
+
+fn check_css_selector(selector: &str) -> Result<()> {
+    Selector::parse(selector)
+        .map(|_| ())
+        .map_err(|e| anyhow!("Invalid CSS selector '{}': {:?}", selector, e))
+}
+
+fn check_css_path(path: &str) -> Result<()> {
+    let (selector, _target) = parse_css_path(path)?;
+    if selector.is_empty() {
+        Ok(())
+    } else {
+        check_css_selector(&selector)
+    }
+}
+
+fn check_xpath(path: &str) -> Result<()> {
+    sxd_xpath::Factory::new()
+        .build(path)
+        .map_err(|e| anyhow!("Invalid XPath '{}': {:?}", path, e))?
+        .map(|_| ())
+        .ok_or_else(|| anyhow!("XPath '{}' is empty", path))
+}
+
 // ── String helpers ───────────────────────────────────────────────────────────
 
 fn unescape_json_string(s: &str) -> String {
@@ -975,6 +1693,24 @@ mod tests {
     use super::*;
     use axum::http::HeaderValue;
 
+    /**
+        An extractor of `kind` with only `path` set; other fields can be set
+        with struct update syntax.
+    */
+    fn new_extractor(kind: ExtractorKind, path: Option<&str>) -> Extractor {
+        Extractor {
+            kind,
+            path: path.map(str::to_string),
+            default: None,
+            regex: None,
+            selector: None,
+            variable: None,
+            each: None,
+            unescape: false,
+            transform: None,
+        }
+    }
+
     #[test]
     fn test_extract_url() {
         let extractor = Extractor {
@@ -982,14 +1718,18 @@ mod tests {
             path: None,
             default: None,
             regex: None,
+            selector: None,
+            variable: None,
             each: None,
             unescape: false,
+            transform: None,
         };
         let result = extract(
             &extractor,
             "body content",
             "https://example.com/test.mpd",
             None,
+            None,
         )
         .unwrap();
         assert_eq!(result, "https://example.com/test.mpd");
@@ -1002,11 +1742,14 @@ mod tests {
             path: None,
             default: None,
             regex: None,
+            selector: None,
+            variable: None,
             each: None,
             unescape: false,
+            transform: None,
         };
         let content = "some header\nabc123:def456\nmore stuff";
-        let result = extract(&extractor, content, "", None).unwrap();
+        let result = extract(&extractor, content, "", None, None).unwrap();
         assert_eq!(result, "abc123:def456");
     }
 
@@ -1017,10 +1760,13 @@ mod tests {
             path: Some(r"id=(\d+)".to_string()),
             default: None,
             regex: None,
+            selector: None,
+            variable: None,
             each: None,
             unescape: false,
+            transform: None,
         };
-        let result = extract(&extractor, "content?id=12345&other=value", "", None).unwrap();
+        let result = extract(&extractor, "content?id=12345&other=value", "", None, None).unwrap();
         assert_eq!(result, "12345");
     }
 
@@ -1055,11 +1801,14 @@ mod tests {
             path: Some(r"url=(https://[^\s]+)".to_string()),
             default: None,
             regex: None,
+            selector: None,
+            variable: None,
             each: None,
             unescape: true,
+            transform: None,
         };
         let content = r"url=https://example.com?a=1\u0026b=2";
-        let result = extract(&extractor, content, "", None).unwrap();
+        let result = extract(&extractor, content, "", None, None).unwrap();
         assert_eq!(result, "https://example.com?a=1&b=2");
     }
 
@@ -1070,8 +1819,11 @@ mod tests {
             path: Some("referer".to_string()),
             default: None,
             regex: None,
+            selector: None,
+            variable: None,
             each: None,
             unescape: false,
+            transform: None,
         };
 
         let mut headers = HeaderMap::new();
@@ -1080,10 +1832,29 @@ mod tests {
             HeaderValue::from_static("https://mdstrm.com/live-stream/abc"),
         );
 
-        let result = extract(&extractor, "", "", Some(&headers)).unwrap();
+        let result = extract(&extractor, "", "", Some(&headers), None).unwrap();
         assert_eq!(result, "https://mdstrm.com/live-stream/abc");
     }
 
+    #[test]
+    fn test_extract_status() {
+        let extractor = Extractor {
+            kind: ExtractorKind::Status,
+            path: None,
+            default: None,
+            regex: None,
+            selector: None,
+            variable: None,
+            each: None,
+            unescape: false,
+            transform: None,
+        };
+
+        assert_eq!(extract(&extractor, "", "", None, Some(404)).unwrap(), "404");
+        assert!(extract(&extractor, "", "", None, None).is_err());
+        assert!(check_extractor(&extractor).is_empty());
+    }
+
     #[test]
     fn test_extract_jsonpath_array() {
         let mut each = HashMap::new();
@@ -1099,8 +1870,11 @@ mod tests {
             path: Some("$.items[*]".to_string()),
             default: None,
             regex: None,
+            selector: None,
+            variable: None,
             each: Some(each),
             unescape: false,
+            transform: None,
         };
 
         let content = r#"{
@@ -1111,7 +1885,7 @@ mod tests {
             ]
         }"#;
 
-        let result = extract_array(&extractor, content).unwrap();
+        let result = extract_array(&extractor, content, "").unwrap();
 
         // Engine returns ALL items - no domain filtering
         assert_eq!(result.len(), 3);
@@ -1142,6 +1916,10 @@ mod tests {
             result[2].get("name").unwrap(),
             &Some("No ID Channel".to_string())
         );
+
+        // An empty array yields no items
+        let result = extract_array(&extractor, r#"{"items": []}"#, "").unwrap();
+        assert!(result.is_empty());
     }
 
     #[test]
@@ -1156,8 +1934,11 @@ mod tests {
             path: Some("$.result[*].content.epg[*]".to_string()),
             default: None,
             regex: None,
+            selector: None,
+            variable: None,
             each: Some(each),
             unescape: false,
+            transform: None,
         };
 
         let content = r#"{
@@ -1182,7 +1963,7 @@ mod tests {
             ]
         }"#;
 
-        let result = extract_array(&extractor, content).unwrap();
+        let result = extract_array(&extractor, content, "").unwrap();
 
         assert_eq!(result.len(), 3);
 
@@ -1218,8 +1999,11 @@ mod tests {
             path: Some("$.schedule[*]".to_string()),
             default: None,
             regex: None,
+            selector: None,
+            variable: None,
             each: Some(each),
             unescape: false,
+            transform: None,
         };
 
         let content = r#"{
@@ -1229,7 +2013,7 @@ mod tests {
             ]
         }"#;
 
-        let result = extract_array(&extractor, content).unwrap();
+        let result = extract_array(&extractor, content, "").unwrap();
         assert_eq!(result.len(), 2);
         assert_eq!(
             result[0].get("channel_id").unwrap(),
@@ -1247,4 +2031,155 @@ mod tests {
         assert_eq!(parent, "$.result[*]");
         assert_eq!(child, "$.content.epg[*]");
     }
+
+    #[test]
+    fn test_extract_hls_variants() {
+        let content = r#"#EXTM3U
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="aac",LANGUAGE="en",NAME="English",DEFAULT=YES,URI="audio/en.m3u8"
+#EXT-X-MEDIA:TYPE=AUDIO,GROUP-ID="aac",LANGUAGE="de",NAME="Deutsch",DEFAULT=NO,URI="audio/de.m3u8"
+#EXT-X-STREAM-INF:BANDWIDTH=800000,RESOLUTION=640x360,CODECS="avc1.4d401e,mp4a.40.2",AUDIO="aac"
+low/index.m3u8
+#EXT-X-STREAM-INF:BANDWIDTH=5000000,RESOLUTION=1920x1080,CODECS="avc1.640028,mp4a.40.2",FRAME-RATE=25.000,AUDIO="aac"
+https://cdn.example.com/high/index.m3u8
+"#;
+        let url = "https://example.com/live/master.m3u8?token=abc";
+
+        let extractor = new_extractor(ExtractorKind::HlsVariants, None);
+        let items = extract_array(&extractor, content, url).unwrap();
+        assert_eq!(items.len(), 4);
+
+        let best = &items[0];
+        assert_eq!(
+            best["uri"].as_deref(),
+            Some("https://cdn.example.com/high/index.m3u8")
+        );
+        assert_eq!(best["height"].as_deref(), Some("1080"));
+        assert_eq!(best["codecs"].as_deref(), Some("avc1.640028,mp4a.40.2"));
+        assert_eq!(best["language"].as_deref(), Some("en"));
+        assert_eq!(
+            items[1]["uri"].as_deref(),
+            Some("https://example.com/live/low/index.m3u8")
+        );
+
+        let extractor = new_extractor(ExtractorKind::HlsVariants, Some("type=audio, language=DE"));
+        let items = extract_array(&extractor, content, url).unwrap();
+        assert_eq!(items.len(), 1);
+        assert_eq!(
+            items[0]["uri"].as_deref(),
+            Some("https://example.com/live/audio/de.m3u8")
+        );
+        assert_eq!(items[0]["audio"].as_deref(), Some("aac"));
+
+        let extractor = new_extractor(ExtractorKind::HlsVariants, Some("height=720"));
+        assert!(extract_array(&extractor, content, url).is_err());
+    }
+
+    #[test]
+    fn test_extract_dash_representations() {
+        let content = r#"<?xml version="1.0"?>
+<MPD xmlns="urn:mpeg:dash:schema:mpd:2011" type="static">
+  <BaseURL>media/</BaseURL>
+  <Period>
+    <AdaptationSet mimeType="video/mp4" codecs="avc1.64001f">
+      <Representation id="v1" bandwidth="1500000" width="1280" height="720" frameRate="25">
+        <BaseURL>video-720.mp4</BaseURL>
+      </Representation>
+      <Representation id="v2" bandwidth="4500000" width="1920" height="1080" codecs="avc1.640028"/>
+    </AdaptationSet>
+    <AdaptationSet contentType="audio" mimeType="audio/mp4" lang="fr">
+      <Representation id="a1" bandwidth="128000" codecs="mp4a.40.2"/>
+    </AdaptationSet>
+  </Period>
+</MPD>"#;
+        let url = "https://example.com/vod/manifest.mpd";
+
+        let extractor = new_extractor(ExtractorKind::DashRepresentations, None);
+        let items = extract_array(&extractor, content, url).unwrap();
+        assert_eq!(items.len(), 3);
+        assert_eq!(items[0]["id"].as_deref(), Some("v2"));
+        assert_eq!(items[0]["codecs"].as_deref(), Some("avc1.640028"));
+        assert_eq!(
+            items[0]["uri"].as_deref(),
+            Some("https://example.com/vod/media/")
+        );
+        assert_eq!(items[1]["resolution"].as_deref(), Some("1280x720"));
+        assert_eq!(items[1]["codecs"].as_deref(), Some("avc1.64001f"));
+        assert_eq!(
+            items[1]["uri"].as_deref(),
+            Some("https://example.com/vod/media/video-720.mp4")
+        );
+
+        let extractor = new_extractor(ExtractorKind::DashRepresentations, Some("type=audio"));
+        let items = extract_array(&extractor, content, url).unwrap();
+        assert_eq!(items[0]["language"].as_deref(), Some("fr"));
+        assert_eq!(items[0]["type"].as_deref(), Some("audio"));
+        let invalid = new_extractor(ExtractorKind::DashRepresentations, Some("audio"));
+        assert_eq!(check_extractor(&invalid).len(), 1);
+    }
+
+    #[test]
+    fn test_extract_script_json() {
+        let content = r#"<html><head>
+<script id="__NEXT_DATA__" type="application/json">{"props":{"pageProps":{"channels":[{"id":"one","name":"One {HD}"},{"id":"two","name":"Two"}]}}}</script>
+<script>
+  window.__INITIAL_STATE__ = {"player":{"src":"https://cdn.example.com/live.m3u8","note":"}; not the end"}};
+  window.__CONFIG__ = JSON.parse("{\"region\":\"eu\"}");
+  init(window.__INITIAL_STATE__);
+</script>
+</head></html>"#;
+
+        let extractor = Extractor {
+            selector: Some("script#__NEXT_DATA__".to_string()),
+            ..new_extractor(
+                ExtractorKind::ScriptJson,
+                Some("$.props.pageProps.channels[1].name"),
+            )
+        };
+        assert_eq!(extract(&extractor, content, "", None, None).unwrap(), "Two");
+
+        let extractor = Extractor {
+            variable: Some("window.__INITIAL_STATE__".to_string()),
+            ..new_extractor(ExtractorKind::ScriptJson, Some("$.player.src"))
+        };
+        assert_eq!(
+            extract(&extractor, content, "", None, None).unwrap(),
+            "https://cdn.example.com/live.m3u8"
+        );
+
+        let extractor = Extractor {
+            variable: Some("window.__CONFIG__".to_string()),
+            ..new_extractor(ExtractorKind::ScriptJson, None)
+        };
+        assert_eq!(
+            extract(&extractor, content, "", None, None).unwrap(),
+            r#"{"region":"eu"}"#
+        );
+
+        let extractor = Extractor {
+            selector: Some("#__NEXT_DATA__".to_string()),
+            each: Some(HashMap::from([
+                ("id".to_string(), "$.id".to_string()),
+                ("name".to_string(), "$.name".to_string()),
+            ])),
+            ..new_extractor(
+                ExtractorKind::ScriptJsonArray,
+                Some("$.props.pageProps.channels[*]"),
+            )
+        };
+        let items = extract_array(&extractor, content, "").unwrap();
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[0]["name"].as_deref(), Some("One {HD}"));
+
+        let missing = Extractor {
+            variable: Some("__MISSING__".to_string()),
+            ..new_extractor(ExtractorKind::ScriptJson, None)
+        };
+        assert!(extract(&missing, content, "", None, None).is_err());
+        let ambiguous = Extractor {
+            selector: Some("script".to_string()),
+            variable: Some("x".to_string()),
+            ..new_extractor(ExtractorKind::ScriptJson, None)
+        };
+        assert_eq!(check_extractor(&ambiguous).len(), 1);
+    }
 }
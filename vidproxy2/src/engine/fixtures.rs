@@ -0,0 +1,570 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::path::Path;
+use std::sync::Mutex;
+
+use anyhow::{Result, anyhow};
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use chrome_browser::{NetworkRequest, NetworkRequestStream};
+use reqwest::Method;
+use serde::{Deserialize, Serialize};
+
+use super::executor::FetchedResponse;
+use super::secrets::{REDACTED, redact, redact_json};
+
+/// Headers carrying credentials, whose values are left out of saved recordings
+const CREDENTIAL_HEADERS: [&str; 4] = [
+    "authorization",
+    "cookie",
+    "proxy-authorization",
+    "set-cookie",
+];
+
+/**
+    Everything a phase read from the network and the page, in the order it
+    was read.
+*/
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct Recording {
+    pub events: Vec<Event>,
+}
+
+/**
+    A single input to a phase, as seen by its extractors.
+*/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Event {
+    /// The requests a `Sniff` or `SniffMany` step read responses of
+    Sniffed {
+        step: String,
+        requests: Vec<RecordedRequest>,
+    },
+    /// The response to a `Fetch` or `FetchInBrowser` request
+    Response {
+        method: String,
+        url: String,
+        response_url: String,
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: String,
+    },
+    /// The page HTML read by a `Document` step
+    Document { step: String, html: String },
+    /// Whether the page part of an `If` condition matched
+    Condition { step: String, matched: bool },
+    /// The cookies visible to a step
+    Cookies {
+        step: String,
+        values: BTreeMap<String, String>,
+    },
+}
+
+impl Event {
+    fn without_credentials(self) -> Self {
+        match self {
+            Event::Sniffed { step, requests } => Event::Sniffed {
+                step,
+                requests: requests
+                    .into_iter()
+                    .map(|request| RecordedRequest {
+                        headers: redact_credential_headers(request.headers),
+                        ..request
+                    })
+                    .collect(),
+            },
+            Event::Response {
+                method,
+                url,
+                response_url,
+                status,
+                headers,
+                body,
+            } => Event::Response {
+                method,
+                url,
+                response_url,
+                status,
+                headers: redact_credential_headers(headers),
+                body,
+            },
+            Event::Cookies { step, values } => Event::Cookies {
+                step,
+                values: values
+                    .into_keys()
+                    .map(|name| (name, REDACTED.to_string()))
+                    .collect(),
+            },
+            event => event,
+        }
+    }
+}
+
+fn redact_credential_headers(headers: Vec<(String, String)>) -> Vec<(String, String)> {
+    headers
+        .into_iter()
+        .map(|(name, value)| {
+            let credential = CREDENTIAL_HEADERS
+                .iter()
+                .any(|header| name.eq_ignore_ascii_case(header));
+            if credential {
+                (name, REDACTED.to_string())
+            } else {
+                (name, value)
+            }
+        })
+        .collect()
+}
+
+/**
+    A sniffed request, with its response body if it had one.
+*/
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RecordedRequest {
+    pub url: String,
+    pub method: String,
+    pub headers: Vec<(String, String)>,
+    pub body: Option<String>,
+}
+
+/**
+    Record a phase's inputs, or replay a phase from a recording.
+
+    When replaying there is no browser and no network: `Fetch` and
+    `FetchInBrowser` responses are looked up by method and URL, everything
+    else is taken in order per step. Steps that only drive the page
+    (`Navigate`, `Script`, `Automation`, waits) do nothing. The steps and
+    extractors themselves run exactly as they do live.
+*/
+pub struct Fixtures {
+    replay: bool,
+    events: Mutex<VecDeque<Event>>,
+}
+
+impl Fixtures {
+    /**
+        Start an empty recording.
+    */
+    pub fn record() -> Self {
+        Self {
+            replay: false,
+            events: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /**
+        Replay the events of a recording.
+    */
+    pub fn replay(recording: Recording) -> Self {
+        Self {
+            replay: true,
+            events: Mutex::new(recording.events.into()),
+        }
+    }
+
+    /**
+        Replay a recording saved with `save`.
+    */
+    pub fn load(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .map_err(|e| anyhow!("Failed to read recording {}: {}", path.display(), e))?;
+        let recording: Recording = serde_json::from_str(&content)
+            .map_err(|e| anyhow!("Invalid recording {}: {}", path.display(), e))?;
+        Ok(Self::replay(recording))
+    }
+
+    /**
+        Write the recorded events as JSON.
+
+        Recordings are meant to be committed as test fixtures, so the values
+        of credential headers and cookies are replaced with `[REDACTED]`, as
+        are secret values wherever they appear. Header and cookie names are
+        kept, so extractors reading them still match on replay, and replayed
+        requests are matched against their redacted URL too.
+    */
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let recording = Recording {
+            events: self
+                .events
+                .lock()
+                .unwrap()
+                .iter()
+                .cloned()
+                .map(Event::without_credentials)
+                .collect(),
+        };
+        let mut json = serde_json::to_value(&recording)?;
+        redact_json(&mut json);
+
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        std::fs::write(path, serde_json::to_string_pretty(&json)?)
+            .map_err(|e| anyhow!("Failed to write recording {}: {}", path.display(), e))
+    }
+
+    pub fn is_replay(&self) -> bool {
+        self.replay
+    }
+
+    /**
+        Add an event to the recording. Does nothing when replaying.
+    */
+    pub(super) fn push(&self, event: Event) {
+        if !self.replay {
+            self.events.lock().unwrap().push_back(event);
+        }
+    }
+
+    /**
+        Remove and return the first event matching `predicate`.
+    */
+    fn take(&self, predicate: impl Fn(&Event) -> bool) -> Option<Event> {
+        let mut events = self.events.lock().unwrap();
+        let index = events.iter().position(predicate)?;
+        events.remove(index)
+    }
+
+    pub(super) fn record_response(&self, method: &Method, url: &str, response: &FetchedResponse) {
+        self.push(Event::Response {
+            method: method.to_string(),
+            url: url.to_string(),
+            response_url: response.url.clone(),
+            status: response.status,
+            headers: header_pairs(&response.headers),
+            body: response.body.clone(),
+        });
+    }
+
+    pub(super) fn response(&self, method: &Method, url: &str) -> Result<FetchedResponse> {
+        // Saved recordings have secrets in URLs redacted
+        let redacted = redact(url);
+        let event = self.take(|event| {
+            matches!(event, Event::Response { method: m, url: u, .. }
+                if m == method.as_str() && (u == url || *u == redacted))
+        });
+        match event {
+            Some(Event::Response {
+                response_url,
+                status,
+                headers,
+                body,
+                ..
+            }) => Ok(FetchedResponse {
+                url: response_url,
+                status,
+                headers: header_map(&headers),
+                body,
+            }),
+            _ => Err(anyhow!("No recorded response for {} {}", method, url)),
+        }
+    }
+
+    pub(super) fn document(&self, step: &str) -> Option<String> {
+        match self.take(|event| matches!(event, Event::Document { step: s, .. } if s == step)) {
+            Some(Event::Document { html, .. }) => Some(html),
+            _ => None,
+        }
+    }
+
+    pub(super) fn condition(&self, step: &str) -> Result<bool> {
+        match self.take(|event| matches!(event, Event::Condition { step: s, .. } if s == step)) {
+            Some(Event::Condition { matched, .. }) => Ok(matched),
+            _ => Err(anyhow!("No recorded page condition for step '{}'", step)),
+        }
+    }
+
+    pub(super) fn cookies(&self, step: &str) -> Option<BTreeMap<String, String>> {
+        match self.take(|event| matches!(event, Event::Cookies { step: s, .. } if s == step)) {
+            Some(Event::Cookies { values, .. }) => Some(values),
+            _ => None,
+        }
+    }
+
+    /**
+        The requests recorded for one run of a sniffing step, as a feed that
+        ends after the last one.
+    */
+    fn sniffed(&self, step: &str) -> VecDeque<SniffedRequest> {
+        let requests =
+            match self.take(|event| matches!(event, Event::Sniffed { step: s, .. } if s == step)) {
+                Some(Event::Sniffed { requests, .. }) => requests,
+                _ => Vec::new(),
+            };
+
+        requests
+            .into_iter()
+            .map(|request| SniffedRequest {
+                url: request.url,
+                method: request.method,
+                headers: header_map(&request.headers),
+                response: SniffedResponse::Recorded(request.body),
+            })
+            .collect()
+    }
+
+    /**
+        Start recording a run of a sniffing step, returning the index of its
+        event. Requests are added to it as their responses are read.
+    */
+    fn start_sniffed(&self, step: &str) -> Option<usize> {
+        if self.replay {
+            return None;
+        }
+        let mut events = self.events.lock().unwrap();
+        events.push_back(Event::Sniffed {
+            step: step.to_string(),
+            requests: Vec::new(),
+        });
+        Some(events.len() - 1)
+    }
+
+    fn push_sniffed(&self, index: usize, request: RecordedRequest) {
+        if let Some(Event::Sniffed { requests, .. }) = self.events.lock().unwrap().get_mut(index) {
+            requests.push(request);
+        }
+    }
+}
+
+/**
+    Network requests seen by a `Sniff` or `SniffMany` step: live from the
+    tab, or the ones recorded for the step.
+*/
+pub(super) enum RequestFeed<'a> {
+    Live {
+        stream: &'a mut NetworkRequestStream,
+        /// Recording and index of the step's `Sniffed` event
+        recorder: Option<(&'a Fixtures, usize)>,
+    },
+    Replay(VecDeque<SniffedRequest>),
+}
+
+pub(super) struct SniffedRequest {
+    pub url: String,
+    pub method: String,
+    pub headers: HeaderMap,
+    response: SniffedResponse,
+}
+
+enum SniffedResponse {
+    Live(NetworkRequest),
+    Recorded(Option<String>),
+}
+
+impl<'a> RequestFeed<'a> {
+    pub fn live(
+        stream: &'a mut NetworkRequestStream,
+        fixtures: Option<&'a Fixtures>,
+        step: &str,
+    ) -> Self {
+        let recorder =
+            fixtures.and_then(|fixtures| Some((fixtures, fixtures.start_sniffed(step)?)));
+        Self::Live { stream, recorder }
+    }
+
+    pub fn replay(fixtures: &Fixtures, step: &str) -> Self {
+        Self::Replay(fixtures.sniffed(step))
+    }
+
+    /**
+        The next request, or `None` once the stream (or recording) ends.
+    */
+    pub async fn next(&mut self) -> Option<SniffedRequest> {
+        match self {
+            Self::Live { stream, .. } => {
+                let request = stream.next().await?;
+                Some(SniffedRequest {
+                    url: request.url().to_string(),
+                    method: request.method().to_string(),
+                    headers: request.headers().clone(),
+                    response: SniffedResponse::Live(request),
+                })
+            }
+            Self::Replay(requests) => requests.pop_front(),
+        }
+    }
+
+    /**
+        The response body of a request, or `None` if it has no response.
+    */
+    pub async fn body(&self, request: SniffedRequest) -> Option<String> {
+        let body = match request.response {
+            SniffedResponse::Recorded(body) => return body,
+            SniffedResponse::Live(ref live) => match live.response().await {
+                Ok(response) => Some(response.text().await.unwrap_or_default()),
+                Err(_) => None,
+            },
+        };
+
+        if let Self::Live {
+            recorder: Some((fixtures, index)),
+            ..
+        } = self
+        {
+            fixtures.push_sniffed(
+                *index,
+                RecordedRequest {
+                    url: request.url,
+                    method: request.method,
+                    headers: header_pairs(&request.headers),
+                    body: body.clone(),
+                },
+            );
+        }
+        body
+    }
+}
+
+fn header_pairs(headers: &HeaderMap) -> Vec<(String, String)> {
+    headers
+        .iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.to_str().ok()?.to_string())))
+        .collect()
+}
+
+fn header_map(pairs: &[(String, String)]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for (name, value) in pairs {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(name.as_bytes()),
+            HeaderValue::from_str(value),
+        ) {
+            headers.append(name, value);
+        }
+    }
+    headers
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sniffed(url: &str, body: &str) -> RecordedRequest {
+        RecordedRequest {
+            url: url.to_string(),
+            method: "GET".to_string(),
+            headers: Vec::new(),
+            body: Some(body.to_string()),
+        }
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let dir = tempfile::tempdir().unwrap();
+        let secrets = dir.path().join("secrets.yaml");
+        std::fs::write(&secrets, "FIXTURE_TOKEN: tok-5f1e9a77\n").unwrap();
+        super::super::secrets::load_file(&secrets).unwrap();
+
+        let recording = Fixtures::record();
+        recording.push(Event::Sniffed {
+            step: "stream".to_string(),
+            requests: vec![RecordedRequest {
+                headers: vec![
+                    ("Cookie".to_string(), "session=abc".to_string()),
+                    ("x-token".to_string(), "tok-5f1e9a77".to_string()),
+                    ("accept".to_string(), "*/*".to_string()),
+                ],
+                ..sniffed(
+                    "https://cdn.example.com/live.m3u8?t=tok-5f1e9a77",
+                    "#EXTM3U",
+                )
+            }],
+        });
+        recording.push(Event::Response {
+            method: "GET".to_string(),
+            url: "https://example.com/epg".to_string(),
+            response_url: "https://example.com/epg".to_string(),
+            status: 200,
+            headers: vec![
+                (
+                    "set-cookie".to_string(),
+                    "session=abc; HttpOnly".to_string(),
+                ),
+                ("content-type".to_string(), "application/json".to_string()),
+            ],
+            body: "[]".to_string(),
+        });
+        recording.push(Event::Response {
+            method: "GET".to_string(),
+            url: "https://example.com/api?key=tok-5f1e9a77".to_string(),
+            response_url: "https://example.com/api?key=tok-5f1e9a77".to_string(),
+            status: 200,
+            headers: Vec::new(),
+            body: "{}".to_string(),
+        });
+        recording.push(Event::Cookies {
+            step: "stream".to_string(),
+            values: BTreeMap::from([("session".to_string(), "abc".to_string())]),
+        });
+
+        let path = dir.path().join("fixtures/content.json");
+        recording.save(&path).unwrap();
+        let saved = std::fs::read_to_string(&path).unwrap();
+        for leaked in ["tok-5f1e9a77", "session=abc", "\"abc\""] {
+            assert!(!saved.contains(leaked), "{} in {}", leaked, saved);
+        }
+
+        let replay = Fixtures::load(&path).unwrap();
+        assert!(replay.is_replay());
+        let response = replay
+            .response(&Method::GET, "https://example.com/epg")
+            .unwrap();
+        assert_eq!((response.status, response.body.as_str()), (200, "[]"));
+        assert_eq!(response.headers["set-cookie"], REDACTED);
+        assert_eq!(response.headers["content-type"], "application/json");
+        assert!(
+            replay
+                .response(&Method::GET, "https://example.com/epg")
+                .is_err()
+        );
+
+        // A request with a secret in its URL finds its redacted recording
+        let response = replay
+            .response(&Method::GET, "https://example.com/api?key=tok-5f1e9a77")
+            .unwrap();
+        assert_eq!(response.url, "https://example.com/api?key=[REDACTED]");
+
+        let requests = replay.sniffed("stream");
+        assert_eq!(
+            requests[0].url,
+            "https://cdn.example.com/live.m3u8?t=[REDACTED]"
+        );
+        assert_eq!(requests[0].headers["x-token"], REDACTED);
+        assert_eq!(requests[0].headers["cookie"], REDACTED);
+        assert_eq!(replay.cookies("stream").unwrap()["session"], REDACTED);
+    }
+
+    #[tokio::test]
+    async fn test_request_feed_replay() {
+        let recording = Fixtures::record();
+        for (step, url) in [
+            ("schedule", "day=1"),
+            ("stream", "live"),
+            ("schedule", "day=2"),
+        ] {
+            let index = recording.start_sniffed(step).unwrap();
+            recording.push_sniffed(index, sniffed(&format!("https://example.com/{}", url), url));
+        }
+        let events = recording.events.lock().unwrap().drain(..).collect();
+        let fixtures = Fixtures::replay(Recording { events });
+
+        // Each run of a step gets the requests recorded for that run, in order
+        for expected in ["day=1", "day=2"] {
+            let mut feed = RequestFeed::replay(&fixtures, "schedule");
+            let request = feed.next().await.unwrap();
+            assert_eq!(request.url, format!("https://example.com/{}", expected));
+            assert_eq!(feed.body(request).await.as_deref(), Some(expected));
+            assert!(feed.next().await.is_none());
+        }
+
+        let mut feed = RequestFeed::replay(&fixtures, "stream");
+        assert_eq!(feed.next().await.unwrap().url, "https://example.com/live");
+
+        // Nothing left for a step that ran out of recorded runs
+        assert!(
+            RequestFeed::replay(&fixtures, "schedule")
+                .next()
+                .await
+                .is_none()
+        );
+    }
+}
@@ -0,0 +1,580 @@
+use std::borrow::Cow;
+use std::path::Path;
+use std::sync::OnceLock;
+
+use anyhow::{Result, anyhow};
+use regex::{Captures, Regex};
+use serde_yaml::{Mapping, Value};
+
+const EXTENDS_KEY: &str = "extends";
+const INCLUDE_KEY: &str = "include";
+const PARAMS_KEY: &str = "params";
+const ITEMS_KEY: &str = "items";
+
+/**
+    Where the manifests and fragments named by `extends:` and `include:`
+    are looked up, by path relative to the sources directory
+    (e.g. `templates/mediastream.yaml`).
+*/
+pub struct Fragments<'a> {
+    dir: Option<&'a Path>,
+}
+
+impl<'a> Fragments<'a> {
+    /**
+        Only the fragments embedded in the binary.
+    */
+    pub fn embedded() -> Self {
+        Self { dir: None }
+    }
+
+    /**
+        Fragments in `dir`, falling back to the embedded ones.
+    */
+    pub fn in_dir(dir: &'a Path) -> Self {
+        Self { dir: Some(dir) }
+    }
+
+    /**
+        The text of a fragment.
+    */
+    pub fn read(&self, name: &str) -> Result<Cow<'static, str>> {
+        let on_disk = self.dir.map(|dir| dir.join(name)).filter(|p| p.is_file());
+        Ok(match on_disk {
+            Some(path) => Cow::Owned(
+                std::fs::read_to_string(&path)
+                    .map_err(|e| anyhow!("Failed to read {}: {}", path.display(), e))?,
+            ),
+            None => Cow::Borrowed(
+                super::manifest::embedded_fragment(name)
+                    .ok_or_else(|| anyhow!("Fragment '{}' not found", name))?,
+            ),
+        })
+    }
+
+    fn load(&self, name: &str) -> Result<Value> {
+        let content = self.read(name)?;
+        serde_yaml::from_str(&content).map_err(|e| anyhow!("Failed to parse '{}': {}", name, e))
+    }
+}
+
+/**
+    Expand a manifest's `extends:` and `include:` references into a plain
+    manifest, before it is deserialized.
+
+    - `extends: NAME` merges the manifest over the base manifest `NAME`:
+      mappings are merged key by key, anything else in the manifest
+      replaces the base's value. Bases may extend other bases.
+    - A list item `{ include: NAME }` is replaced by the items of the
+      fragment `NAME`, which is either a list or a mapping with `items` and
+      default `params`. Fragments may include other fragments.
+    - `${{ param.NAME }}` is replaced by the parameter `NAME`: for a base,
+      from the `params` of the manifest extending it; for a fragment, from
+      the `params` next to its `include`. A placeholder that is the whole
+      string keeps the parameter's type.
+
+    Content without any of these is returned unchanged, so parse errors
+    keep their positions.
+*/
+pub fn expand<'c>(content: &'c str, fragments: &Fragments) -> Result<Cow<'c, str>> {
+    // Invalid YAML is left for the manifest parser to report
+    let Ok(value) = serde_yaml::from_str::<Value>(content) else {
+        return Ok(Cow::Borrowed(content));
+    };
+    if !uses_fragments(&value) {
+        return Ok(Cow::Borrowed(content));
+    }
+
+    let expanded = expand_document(value, &Mapping::new(), fragments, &mut Vec::new())?;
+    serde_yaml::to_string(&expanded)
+        .map(Cow::Owned)
+        .map_err(|e| anyhow!("Failed to serialize expanded manifest: {}", e))
+}
+
+/**
+    The bases and fragments a manifest uses, directly or through other
+    fragments, as (name, content) in order of first use. Ones that can't be
+    read are left out.
+*/
+pub fn used_fragments(content: &str, fragments: &Fragments) -> Vec<(String, String)> {
+    let mut used = Vec::new();
+    collect_used(content, fragments, &mut used);
+    used
+}
+
+fn collect_used(content: &str, fragments: &Fragments, used: &mut Vec<(String, String)>) {
+    let Ok(value) = serde_yaml::from_str::<Value>(content) else {
+        return;
+    };
+    let mut names = Vec::new();
+    if let Some(name) = value.get(EXTENDS_KEY).and_then(Value::as_str) {
+        names.push(name.to_string());
+    }
+    include_names(&value, &mut names);
+
+    for name in names {
+        if used.iter().any(|(used_name, _)| *used_name == name) {
+            continue;
+        }
+        let Ok(text) = fragments.read(&name) else {
+            continue;
+        };
+        used.push((name, text.to_string()));
+        collect_used(&text, fragments, used);
+    }
+}
+
+fn include_names(value: &Value, names: &mut Vec<String>) {
+    match value {
+        Value::Sequence(items) => {
+            for item in items {
+                match item.get(INCLUDE_KEY).and_then(Value::as_str) {
+                    Some(name) => names.push(name.to_string()),
+                    None => include_names(item, names),
+                }
+            }
+        }
+        Value::Mapping(map) => map.values().for_each(|item| include_names(item, names)),
+        Value::Tagged(tagged) => include_names(&tagged.value, names),
+        _ => {}
+    }
+}
+
+/**
+    Whether a file is a fragment or base manifest rather than a manifest of
+    its own: a list, a mapping with `items` or without `source`, or a
+    mapping using a `${{ param.NAME }}` its own `params` don't define.
+*/
+pub fn is_fragment(content: &str) -> bool {
+    let Ok(value) = serde_yaml::from_str::<Value>(content) else {
+        return false;
+    };
+    let Some(map) = value.as_mapping() else {
+        return value.is_sequence();
+    };
+    if map.contains_key(ITEMS_KEY) || !map.contains_key("source") {
+        return true;
+    }
+
+    let own = map.get(PARAMS_KEY).and_then(Value::as_mapping);
+    let mut values = map.clone();
+    values.remove(PARAMS_KEY);
+    let text = serde_yaml::to_string(&values).unwrap_or_default();
+    param_regex()
+        .captures_iter(&text)
+        .any(|caps| own.is_none_or(|own| !own.contains_key(&caps[1])))
+}
+
+/**
+    Check the shape of a fragment or base manifest on its own, without the
+    parameters it is used with.
+*/
+pub fn check_fragment(value: &Value) -> Result<()> {
+    let Some(map) = value.as_mapping() else {
+        return match value {
+            Value::Sequence(_) => check_includes(value),
+            _ => Err(anyhow!("A fragment must be a list or a mapping")),
+        };
+    };
+    if !matches!(
+        map.get(PARAMS_KEY),
+        None | Some(Value::Null | Value::Mapping(_))
+    ) {
+        return Err(anyhow!("'params' must be a mapping"));
+    }
+    if !matches!(map.get(EXTENDS_KEY), None | Some(Value::String(_))) {
+        return Err(anyhow!("'extends' must be a file name"));
+    }
+    if !matches!(map.get(ITEMS_KEY), None | Some(Value::Sequence(_))) {
+        return Err(anyhow!("'items' of a fragment must be a list"));
+    }
+    check_includes(value)
+}
+
+fn check_includes(value: &Value) -> Result<()> {
+    match value {
+        Value::Sequence(items) => {
+            for item in items {
+                include_of(item)?;
+                check_includes(item)?;
+            }
+            Ok(())
+        }
+        Value::Mapping(map) => map.values().try_for_each(check_includes),
+        Value::Tagged(tagged) => check_includes(&tagged.value),
+        _ => Ok(()),
+    }
+}
+
+fn uses_fragments(value: &Value) -> bool {
+    let top_level = value
+        .as_mapping()
+        .is_some_and(|map| map.contains_key(EXTENDS_KEY) || map.contains_key(PARAMS_KEY));
+    top_level || has_includes(value)
+}
+
+fn has_includes(value: &Value) -> bool {
+    match value {
+        Value::Sequence(items) => items.iter().any(|item| {
+            item.as_mapping()
+                .is_some_and(|map| map.contains_key(INCLUDE_KEY))
+                || has_includes(item)
+        }),
+        Value::Mapping(map) => map.values().any(has_includes),
+        Value::Tagged(tagged) => has_includes(&tagged.value),
+        _ => false,
+    }
+}
+
+/**
+    Expand a manifest or base manifest. `passed` are the parameters given
+    by the manifest extending it, which override its own defaults.
+    `chain` holds the names being expanded, to detect cycles.
+*/
+fn expand_document(
+    mut value: Value,
+    passed: &Mapping,
+    fragments: &Fragments,
+    chain: &mut Vec<String>,
+) -> Result<Value> {
+    let map = value
+        .as_mapping_mut()
+        .ok_or_else(|| anyhow!("A manifest must be a mapping"))?;
+
+    let mut params = take_params(map)?;
+    params.extend(passed.clone());
+
+    if let Some(name) = map.remove(EXTENDS_KEY) {
+        let name = name
+            .as_str()
+            .ok_or_else(|| anyhow!("'extends' must be a file name"))?
+            .to_string();
+        enter(chain, &name)?;
+        let base = fragments
+            .load(&name)
+            .and_then(|base| expand_document(base, &params, fragments, chain))
+            .map_err(|e| anyhow!("In base manifest '{}': {}", name, e))?;
+        chain.pop();
+        value = merge(base, value);
+    }
+
+    substitute(&mut value, &params)?;
+    expand_includes(&mut value, fragments, chain)?;
+    Ok(value)
+}
+
+/**
+    Replace every `include` item in the lists inside `value`.
+*/
+fn expand_includes(
+    value: &mut Value,
+    fragments: &Fragments,
+    chain: &mut Vec<String>,
+) -> Result<()> {
+    match value {
+        Value::Sequence(items) => {
+            let mut expanded = Vec::with_capacity(items.len());
+            for mut item in std::mem::take(items) {
+                let Some((name, params)) = include_of(&item)? else {
+                    expand_includes(&mut item, fragments, chain)?;
+                    expanded.push(item);
+                    continue;
+                };
+                enter(chain, &name)?;
+                let included = fragments
+                    .load(&name)
+                    .and_then(|fragment| expand_fragment(fragment, &params, fragments, chain))
+                    .map_err(|e| anyhow!("In fragment '{}': {}", name, e))?;
+                chain.pop();
+                expanded.extend(included);
+            }
+            *items = expanded;
+        }
+        Value::Mapping(map) => {
+            for item in map.values_mut() {
+                expand_includes(item, fragments, chain)?;
+            }
+        }
+        Value::Tagged(tagged) => expand_includes(&mut tagged.value, fragments, chain)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+/**
+    The fragment name and parameters of an `include` item, if it is one.
+*/
+fn include_of(item: &Value) -> Result<Option<(String, Mapping)>> {
+    let Some(map) = item.as_mapping() else {
+        return Ok(None);
+    };
+    let Some(name) = map.get(INCLUDE_KEY) else {
+        return Ok(None);
+    };
+    let name = name
+        .as_str()
+        .ok_or_else(|| anyhow!("'include' must be a file name"))?;
+
+    if let Some(key) = map
+        .keys()
+        .find(|key| !matches!(key.as_str(), Some(INCLUDE_KEY | PARAMS_KEY)))
+    {
+        return Err(anyhow!(
+            "Unexpected key {} next to include of '{}'",
+            serde_yaml::to_string(key).unwrap_or_default().trim(),
+            name
+        ));
+    }
+
+    let params = match map.get(PARAMS_KEY) {
+        Some(Value::Mapping(params)) => params.clone(),
+        Some(_) => return Err(anyhow!("'params' of include '{}' must be a mapping", name)),
+        None => Mapping::new(),
+    };
+    Ok(Some((name.to_string(), params)))
+}
+
+fn expand_fragment(
+    fragment: Value,
+    passed: &Mapping,
+    fragments: &Fragments,
+    chain: &mut Vec<String>,
+) -> Result<Vec<Value>> {
+    let (items, mut params) = match fragment {
+        Value::Sequence(items) => (items, Mapping::new()),
+        Value::Mapping(mut map) => {
+            let params = take_params(&mut map)?;
+            match map.remove(ITEMS_KEY) {
+                Some(Value::Sequence(items)) => (items, params),
+                _ => return Err(anyhow!("A fragment must be a list or have an 'items' list")),
+            }
+        }
+        _ => return Err(anyhow!("A fragment must be a list or have an 'items' list")),
+    };
+    params.extend(passed.clone());
+
+    let mut items = Value::Sequence(items);
+    substitute(&mut items, &params)?;
+    expand_includes(&mut items, fragments, chain)?;
+    match items {
+        Value::Sequence(items) => Ok(items),
+        _ => unreachable!(),
+    }
+}
+
+fn take_params(map: &mut Mapping) -> Result<Mapping> {
+    match map.remove(PARAMS_KEY) {
+        Some(Value::Mapping(params)) => Ok(params),
+        Some(Value::Null) | None => Ok(Mapping::new()),
+        Some(_) => Err(anyhow!("'params' must be a mapping")),
+    }
+}
+
+fn enter(chain: &mut Vec<String>, name: &str) -> Result<()> {
+    if chain.iter().any(|entered| entered == name) {
+        return Err(anyhow!(
+            "Circular reference: {} -> {}",
+            chain.join(" -> "),
+            name
+        ));
+    }
+    chain.push(name.to_string());
+    Ok(())
+}
+
+/**
+    Merge `overlay` over `base`.
+*/
+fn merge(base: Value, overlay: Value) -> Value {
+    match (base, overlay) {
+        (Value::Mapping(mut base), Value::Mapping(overlay)) => {
+            for (key, value) in overlay {
+                let merged = match base.remove(&key) {
+                    Some(existing) => merge(existing, value),
+                    None => value,
+                };
+                base.insert(key, merged);
+            }
+            Value::Mapping(base)
+        }
+        (_, overlay) => overlay,
+    }
+}
+
+fn param_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| {
+        Regex::new(r"\$\{\{\s*param\.([A-Za-z0-9_]+)\s*\}\}").expect("param regex should compile")
+    })
+}
+
+/**
+    Replace `${{ param.NAME }}` placeholders in every string inside `value`.
+*/
+fn substitute(value: &mut Value, params: &Mapping) -> Result<()> {
+    match value {
+        Value::String(s) => {
+            if let Some(replaced) = substitute_str(s, params)? {
+                *value = replaced;
+            }
+        }
+        Value::Sequence(items) => {
+            for item in items {
+                substitute(item, params)?;
+            }
+        }
+        Value::Mapping(map) => {
+            for item in map.values_mut() {
+                substitute(item, params)?;
+            }
+        }
+        Value::Tagged(tagged) => substitute(&mut tagged.value, params)?,
+        _ => {}
+    }
+    Ok(())
+}
+
+fn substitute_str(s: &str, params: &Mapping) -> Result<Option<Value>> {
+    let re = param_regex();
+    let Some(caps) = re.captures(s) else {
+        return Ok(None);
+    };
+    if caps[0].len() == s.len() {
+        return param(params, &caps[1]).cloned().map(Some);
+    }
+
+    let mut error = None;
+    let replaced = re.replace_all(s, |caps: &Captures| {
+        match param(params, &caps[1]).and_then(|value| scalar_string(&caps[1], value)) {
+            Ok(value) => value,
+            Err(e) => {
+                error.get_or_insert(e);
+                String::new()
+            }
+        }
+    });
+    match error {
+        Some(e) => Err(e),
+        None => Ok(Some(Value::String(replaced.into_owned()))),
+    }
+}
+
+fn param<'p>(params: &'p Mapping, name: &str) -> Result<&'p Value> {
+    params
+        .get(name)
+        .ok_or_else(|| anyhow!("Missing parameter '{}'", name))
+}
+
+fn scalar_string(name: &str, value: &Value) -> Result<String> {
+    match value {
+        Value::String(s) => Ok(s.clone()),
+        Value::Number(n) => Ok(n.to_string()),
+        Value::Bool(b) => Ok(b.to_string()),
+        _ => Err(anyhow!(
+            "Parameter '{}' is not a string or number, so it can only be used as a whole value",
+            name
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn write(dir: &Path, name: &str, content: &str) {
+        let path = dir.join(name);
+        std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+        std::fs::write(path, content).unwrap();
+    }
+
+    #[test]
+    fn test_extends_and_include() {
+        let dir = tempfile::tempdir().unwrap();
+        write(
+            dir.path(),
+            "templates/base.yaml",
+            r#"
+params:
+  timeout: 30.0
+source:
+  id: "base"
+  name: "${{ param.name }} TV"
+content:
+  headless: false
+  outputs:
+    manifest_url: "${{get_manifest.url}}"
+  steps:
+    - include: templates/sniff.yaml
+      params:
+        request: "${{ param.request }}"
+        timeout: "${{ param.timeout }}"
+"#,
+        );
+        write(
+            dir.path(),
+            "templates/sniff.yaml",
+            r#"
+- name: "get_manifest"
+  kind: Sniff
+  request:
+    url: "${{ param.request }}"
+    timeout: "${{ param.timeout }}"
+  extract:
+    url:
+      kind: url
+"#,
+        );
+
+        let content = r#"
+extends: templates/base.yaml
+params:
+  name: "Example"
+  request: "master\\.m3u8"
+source:
+  id: "example"
+"#;
+        let expanded = expand(content, &Fragments::in_dir(dir.path())).unwrap();
+        let value: Value = serde_yaml::from_str(&expanded).unwrap();
+
+        assert_eq!(value["source"]["id"], "example");
+        assert_eq!(value["source"]["name"], "Example TV");
+        assert_eq!(value["content"]["headless"], false);
+        let step = &value["content"]["steps"][0];
+        assert_eq!(step["name"], "get_manifest");
+        assert_eq!(step["request"]["url"], "master\\.m3u8");
+        assert_eq!(step["request"]["timeout"], 30.0);
+        assert_eq!(
+            value["content"]["outputs"]["manifest_url"],
+            "${{get_manifest.url}}"
+        );
+    }
+
+    #[test]
+    fn test_expand_errors() {
+        let dir = tempfile::tempdir().unwrap();
+        write(dir.path(), "a.yaml", "- include: b.yaml\n");
+        write(dir.path(), "b.yaml", "- include: a.yaml\n");
+        write(dir.path(), "c.yaml", "- url: \"${{ param.missing }}\"\n");
+        let fragments = Fragments::in_dir(dir.path());
+
+        let err = expand("steps:\n  - include: a.yaml\n", &fragments).unwrap_err();
+        assert!(
+            err.to_string().contains("a.yaml -> b.yaml -> a.yaml"),
+            "{}",
+            err
+        );
+
+        let err = expand("steps:\n  - include: c.yaml\n", &fragments).unwrap_err();
+        assert!(
+            err.to_string().contains("Missing parameter 'missing'"),
+            "{}",
+            err
+        );
+
+        let plain = "source:\n  id: \"x\"\n";
+        assert!(matches!(
+            expand(plain, &fragments).unwrap(),
+            Cow::Borrowed(_)
+        ));
+    }
+}
@@ -0,0 +1,432 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use anyhow::{Result, anyhow};
+use axum::http::{HeaderMap, HeaderName, HeaderValue};
+use base64::Engine;
+use regex::Regex;
+use serde::Deserialize;
+
+use super::extractor::{ExtractedArray, extract, extract_array};
+use super::interpolate::placeholder_regex;
+use super::step::{Extractor, Step, is_array_extractor};
+
+/**
+    A request and its response, as captured in a HAR file.
+*/
+pub struct HarEntry {
+    pub method: String,
+    pub url: String,
+    pub status: u16,
+    pub request_headers: HeaderMap,
+    pub response_headers: HeaderMap,
+    pub body: String,
+}
+
+#[derive(Deserialize)]
+struct HarFile {
+    log: HarLog,
+}
+
+#[derive(Deserialize)]
+struct HarLog {
+    #[serde(default)]
+    entries: Vec<RawEntry>,
+}
+
+#[derive(Deserialize)]
+struct RawEntry {
+    request: RawRequest,
+    response: RawResponse,
+}
+
+#[derive(Deserialize)]
+struct RawRequest {
+    method: String,
+    url: String,
+    #[serde(default)]
+    headers: Vec<RawHeader>,
+}
+
+#[derive(Deserialize)]
+struct RawResponse {
+    #[serde(default)]
+    status: u16,
+    #[serde(default)]
+    headers: Vec<RawHeader>,
+    #[serde(default)]
+    content: RawContent,
+}
+
+#[derive(Deserialize)]
+struct RawHeader {
+    name: String,
+    value: String,
+}
+
+#[derive(Deserialize, Default)]
+struct RawContent {
+    #[serde(default)]
+    text: Option<String>,
+    #[serde(default)]
+    encoding: Option<String>,
+}
+
+/**
+    Read the entries of a HAR file, as exported by browser dev tools.
+*/
+pub fn load(path: &Path) -> Result<Vec<HarEntry>> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read HAR file {}: {}", path.display(), e))?;
+    parse(&content).map_err(|e| anyhow!("Invalid HAR file {}: {}", path.display(), e))
+}
+
+/**
+    Parse the entries of a HAR document. Base64-encoded response bodies are
+    decoded; bodies that are not valid UTF-8 are read lossily.
+*/
+pub fn parse(content: &str) -> Result<Vec<HarEntry>> {
+    let har: HarFile = serde_json::from_str(content)?;
+
+    har.log
+        .entries
+        .into_iter()
+        .map(|entry| {
+            let body = match (entry.response.content.text, entry.response.content.encoding) {
+                (Some(text), Some(encoding)) if encoding == "base64" => {
+                    let bytes = base64::engine::general_purpose::STANDARD
+                        .decode(text.trim())
+                        .map_err(|e| {
+                            anyhow!("Invalid base64 body for {}: {}", entry.request.url, e)
+                        })?;
+                    String::from_utf8_lossy(&bytes).into_owned()
+                }
+                (text, _) => text.unwrap_or_default(),
+            };
+
+            Ok(HarEntry {
+                request_headers: header_map(&entry.request.headers),
+                response_headers: header_map(&entry.response.headers),
+                method: entry.request.method,
+                url: entry.request.url,
+                status: entry.response.status,
+                body,
+            })
+        })
+        .collect()
+}
+
+/**
+    Headers of a HAR request or response. Headers that are not valid HTTP
+    (e.g. HTTP/2 pseudo-headers like `:authority`) are skipped.
+*/
+fn header_map(raw: &[RawHeader]) -> HeaderMap {
+    let mut headers = HeaderMap::new();
+    for header in raw {
+        if let (Ok(name), Ok(value)) = (
+            HeaderName::from_bytes(header.name.as_bytes()),
+            HeaderValue::from_str(&header.value),
+        ) {
+            headers.append(name, value);
+        }
+    }
+    headers
+}
+
+/**
+    A step whose extractors can run against HAR entries, with the matcher
+    for the entries it would have read.
+*/
+pub struct HarStep<'a> {
+    pub name: &'a str,
+    pub kind: &'static str,
+    pub extractors: &'a HashMap<String, Extractor>,
+    url: Regex,
+    method: Option<String>,
+    /// Whether the step reads the response status and headers (`Fetch` and
+    /// `FetchInBrowser`); sniffing steps read the request headers instead
+    is_fetch: bool,
+}
+
+/**
+    The `Sniff`, `SniffMany`, `Fetch` and `FetchInBrowser` steps in a step
+    list, including nested ones.
+
+    Sniffing steps match entries by their `request` URL regex and method,
+    exactly as when running live. Fetch steps match entries whose URL starts
+    with the step's URL, with every `${{...}}` placeholder matching anything.
+*/
+pub fn har_steps(steps: &[Step]) -> Result<Vec<HarStep<'_>>> {
+    let mut found = Vec::new();
+    for step in steps {
+        collect_har_steps(step, &mut found)?;
+    }
+    Ok(found)
+}
+
+fn collect_har_steps<'a>(step: &'a Step, found: &mut Vec<HarStep<'a>>) -> Result<()> {
+    match step {
+        Step::Sniff {
+            name,
+            request,
+            extract,
+            ..
+        }
+        | Step::SniffMany {
+            name,
+            request,
+            extract,
+            ..
+        } => {
+            let url = Regex::new(&request.url)
+                .map_err(|e| anyhow!("Invalid URL regex '{}': {}", request.url, e))?;
+            found.push(HarStep {
+                name,
+                kind: if matches!(step, Step::Sniff { .. }) {
+                    "Sniff"
+                } else {
+                    "SniffMany"
+                },
+                extractors: extract,
+                url,
+                method: request.method.clone(),
+                is_fetch: false,
+            });
+        }
+        Step::Fetch {
+            name,
+            url,
+            urls,
+            method,
+            extract,
+            ..
+        } => {
+            let templates: Vec<&str> = match (url, urls) {
+                (_, Some(urls)) => urls.iter().map(String::as_str).collect(),
+                (Some(url), None) => vec![url.as_str()],
+                (None, None) => Vec::new(),
+            };
+            if !templates.is_empty() {
+                found.push(HarStep {
+                    name,
+                    kind: "Fetch",
+                    extractors: extract,
+                    url: template_regex(&templates)?,
+                    method: fetch_method(method.as_deref()),
+                    is_fetch: true,
+                });
+            }
+        }
+        Step::FetchInBrowser {
+            name,
+            url,
+            method,
+            extract,
+            ..
+        } => {
+            found.push(HarStep {
+                name,
+                kind: "FetchInBrowser",
+                extractors: extract,
+                url: template_regex(&[url.as_str()])?,
+                method: fetch_method(method.as_deref()),
+                is_fetch: true,
+            });
+        }
+        _ => {}
+    }
+
+    for nested in step.nested_steps() {
+        collect_har_steps(nested, found)?;
+    }
+    Ok(())
+}
+
+/**
+    A regex matching URLs that start with any of the templates.
+*/
+fn template_regex(templates: &[&str]) -> Result<Regex> {
+    let alternatives: Vec<String> = templates
+        .iter()
+        .map(|template| {
+            placeholder_regex()
+                .split(template)
+                .map(regex::escape)
+                .collect::<Vec<_>>()
+                .join(".*")
+        })
+        .collect();
+    let pattern = format!("^(?:{})", alternatives.join("|"));
+    Regex::new(&pattern).map_err(|e| anyhow!("Invalid URL pattern '{}': {}", pattern, e))
+}
+
+/**
+    The method a fetch step sends, or `None` if it is only known at runtime.
+*/
+fn fetch_method(method: Option<&str>) -> Option<String> {
+    match method {
+        Some(method) if placeholder_regex().is_match(method) => None,
+        Some(method) => Some(method.trim().to_ascii_uppercase()),
+        None => Some("GET".to_string()),
+    }
+}
+
+/**
+    What an extractor returned for one entry.
+*/
+pub enum ExtractorOutput {
+    Value(String),
+    Items(ExtractedArray),
+}
+
+impl HarStep<'_> {
+    pub fn matches(&self, entry: &HarEntry) -> bool {
+        self.url.is_match(&entry.url)
+            && self
+                .method
+                .as_ref()
+                .is_none_or(|method| *method == entry.method)
+    }
+
+    /**
+        Run one of the step's extractors against an entry, with the headers
+        the step would see live. Extractor templates are not interpolated,
+        as there is no phase context.
+    */
+    pub fn extract(&self, extractor: &Extractor, entry: &HarEntry) -> Result<ExtractorOutput> {
+        if is_array_extractor(&extractor.kind) {
+            return extract_array(extractor, &entry.body, &entry.url).map(ExtractorOutput::Items);
+        }
+
+        let (headers, status) = if self.is_fetch {
+            (&entry.response_headers, Some(entry.status))
+        } else {
+            (&entry.request_headers, None)
+        };
+        extract(extractor, &entry.body, &entry.url, Some(headers), status)
+            .map(ExtractorOutput::Value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const HAR: &str = r#"{
+        "log": {
+            "entries": [
+                {
+                    "request": {
+                        "method": "GET",
+                        "url": "https://api.example.com/channels?page=1",
+                        "headers": [
+                            { "name": ":authority", "value": "api.example.com" },
+                            { "name": "Referer", "value": "https://www.example.com/live" }
+                        ]
+                    },
+                    "response": {
+                        "status": 200,
+                        "headers": [{ "name": "Content-Type", "value": "application/json" }],
+                        "content": { "text": "eyJpdGVtcyI6W3siaWQiOiJvbmUifSx7ImlkIjoidHdvIn1dfQ==", "encoding": "base64" }
+                    }
+                },
+                {
+                    "request": { "method": "POST", "url": "https://api.example.com/epg/one/today" },
+                    "response": { "status": 404, "content": { "text": "{\"error\":\"missing\"}" } }
+                }
+            ]
+        }
+    }"#;
+
+    #[test]
+    fn test_parse_har() {
+        let entries = parse(HAR).unwrap();
+        assert_eq!(entries.len(), 2);
+        assert_eq!(entries[0].body, r#"{"items":[{"id":"one"},{"id":"two"}]}"#);
+        assert_eq!(
+            entries[0].response_headers["content-type"],
+            "application/json"
+        );
+        assert_eq!(
+            entries[0].request_headers["referer"],
+            "https://www.example.com/live"
+        );
+        assert!(entries[1].request_headers.is_empty());
+        assert_eq!(entries[1].status, 404);
+        assert_eq!(entries[1].body, r#"{"error":"missing"}"#);
+    }
+
+    #[test]
+    fn test_match_and_extract() {
+        let steps: Vec<Step> = serde_yaml::from_str(
+            r#"
+- kind: Sniff
+  name: channels
+  request:
+    url: "/channels"
+  extract:
+    channels:
+      kind: jsonpath_array
+      path: "$.items[*]"
+      each:
+        id: "$.id"
+    referer:
+      kind: header
+      path: referer
+    content_type:
+      kind: header
+      path: content-type
+      default: ""
+- kind: ForEach
+  name: each_channel
+  array: channels
+  steps:
+    - kind: Fetch
+      name: epg
+      url: "https://api.example.com/epg/${{item.id}}/today"
+      method: post
+      extract:
+        status:
+          kind: status
+"#,
+        )
+        .unwrap();
+        let entries = parse(HAR).unwrap();
+        let found = har_steps(&steps).unwrap();
+        assert_eq!(found.len(), 2);
+
+        let (sniff, fetch) = (&found[0], &found[1]);
+        assert!(sniff.matches(&entries[0]) && !sniff.matches(&entries[1]));
+        assert!(!fetch.matches(&entries[0]) && fetch.matches(&entries[1]));
+
+        let Ok(ExtractorOutput::Items(items)) =
+            sniff.extract(&sniff.extractors["channels"], &entries[0])
+        else {
+            panic!("expected items");
+        };
+        assert_eq!(items.len(), 2);
+        assert_eq!(items[1]["id"].as_deref(), Some("two"));
+
+        let Ok(ExtractorOutput::Value(referer)) =
+            sniff.extract(&sniff.extractors["referer"], &entries[0])
+        else {
+            panic!("expected a value");
+        };
+        assert_eq!(referer, "https://www.example.com/live");
+
+        // Response headers are not visible to a sniffed request
+        let Ok(ExtractorOutput::Value(content_type)) =
+            sniff.extract(&sniff.extractors["content_type"], &entries[0])
+        else {
+            panic!("expected a value");
+        };
+        assert_eq!(content_type, "");
+
+        let Ok(ExtractorOutput::Value(status)) =
+            fetch.extract(&fetch.extractors["status"], &entries[1])
+        else {
+            panic!("expected a value");
+        };
+        assert_eq!(status, "404");
+    }
+}
@@ -1,14 +1,24 @@
 use std::collections::HashMap;
+use std::sync::OnceLock;
 
 use anyhow::{Result, anyhow};
 use regex::Regex;
+use serde_json::Value;
+
+use super::expression::{self, Evaluated, Expr, PathSegment};
+use super::extractor::ExtractedArray;
+use super::secrets;
 
 /**
     Context for variable interpolation, storing outputs from each step.
+
+    Outputs are structured values: scalar extractors store strings, array
+    extractors store arrays of objects. Templates address them with dotted
+    and indexed paths, e.g. `${{step.items[0].url}}` or `${{step.items.length}}`.
 */
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 pub struct InterpolationContext {
-    steps: HashMap<String, HashMap<String, String>>,
+    steps: HashMap<String, HashMap<String, Value>>,
 }
 
 impl InterpolationContext {
@@ -17,54 +27,260 @@ impl InterpolationContext {
     }
 
     /**
-        Add an output value for a step.
+        Add a string output value for a step.
     */
     pub fn set(&mut self, step_name: &str, output_name: &str, value: String) {
+        self.set_value(step_name, output_name, Value::String(value));
+    }
+
+    /**
+        Add a structured output value for a step.
+    */
+    pub fn set_value(&mut self, step_name: &str, output_name: &str, value: Value) {
         self.steps
             .entry(step_name.to_string())
             .or_default()
             .insert(output_name.to_string(), value);
     }
 
+    /**
+        Append array items to a step's output, creating the array if needed.
+    */
+    pub fn extend_array(&mut self, step_name: &str, output_name: &str, items: &ExtractedArray) {
+        let outputs = self.steps.entry(step_name.to_string()).or_default();
+        let entry = outputs
+            .entry(output_name.to_string())
+            .or_insert_with(|| Value::Array(Vec::new()));
+
+        if !entry.is_array() {
+            *entry = Value::Array(Vec::new());
+        }
+        if let Value::Array(values) = entry {
+            values.extend(items.iter().map(item_to_value));
+        }
+    }
+
+    /**
+        Replace every array output named `output_name`, in any step, with `items`.
+
+        Used when a step rewrites an array by output name (e.g. `ForEach`), so
+        templates referencing the producing step see the updated items.
+    */
+    pub fn replace_arrays(&mut self, output_name: &str, items: &ExtractedArray) {
+        for outputs in self.steps.values_mut() {
+            if let Some(entry) = outputs.get_mut(output_name)
+                && entry.is_array()
+            {
+                *entry = Value::Array(items.iter().map(item_to_value).collect());
+            }
+        }
+    }
+
     /**
         Get an output value from a step.
     */
-    pub fn get(&self, step_name: &str, output_name: &str) -> Option<&String> {
+    pub fn get(&self, step_name: &str, output_name: &str) -> Option<&Value> {
         self.steps.get(step_name)?.get(output_name)
     }
 
     /**
-        Interpolate a string, replacing `${{step_name.output_name}}` with values.
+        Get all output values from a step.
     */
-    pub fn interpolate(&self, template: &str) -> Result<String> {
-        let re = Regex::new(r"\$\{\{([a-zA-Z_][a-zA-Z0-9_]*)\.([a-zA-Z_][a-zA-Z0-9_]*)\}\}")?;
+    pub fn outputs(&self, step_name: &str) -> Option<&HashMap<String, Value>> {
+        self.steps.get(step_name)
+    }
 
-        let mut result = template.to_string();
-        let mut last_err: Option<anyhow::Error> = None;
+    /**
+        Resolve a variable path to a value.
 
-        for cap in re.captures_iter(template) {
-            let full_match = cap.get(0).unwrap().as_str();
-            let step_name = &cap[1];
-            let output_name = &cap[2];
+        The first two segments name the step and output; the rest walk into
+        objects and arrays. `length` on an array (or string) yields its length.
+        The reserved `env` and `secret` namespaces resolve process environment
+        variables and entries of the secrets file instead of step outputs.
+    */
+    pub fn lookup(&self, path: &[PathSegment]) -> Option<Value> {
+        let [
+            PathSegment::Key(step_name),
+            PathSegment::Key(output_name),
+            rest @ ..,
+        ] = path
+        else {
+            return None;
+        };
 
-            match self.get(step_name, output_name) {
-                Some(value) => {
-                    result = result.replace(full_match, value);
+        let namespaced;
+        let mut current = match step_name.as_str() {
+            "env" => {
+                namespaced = Value::String(std::env::var(output_name).ok()?);
+                &namespaced
+            }
+            "secret" => {
+                namespaced = Value::String(secrets::get(output_name)?);
+                &namespaced
+            }
+            _ => self.get(step_name, output_name)?,
+        };
+        for (i, segment) in rest.iter().enumerate() {
+            current = match (current, segment) {
+                (Value::Object(map), PathSegment::Key(key)) => map.get(key)?,
+                (Value::Array(items), PathSegment::Index(index)) => items.get(*index)?,
+                (Value::Array(items), PathSegment::Key(key))
+                    if key == "length" && i + 1 == rest.len() =>
+                {
+                    return Some(Value::from(items.len()));
                 }
-                None => {
-                    last_err = Some(anyhow!("Undefined variable: {}.{}", step_name, output_name));
+                (Value::String(s), PathSegment::Key(key))
+                    if key == "length" && i + 1 == rest.len() =>
+                {
+                    return Some(Value::from(s.chars().count()));
+                }
+                _ => return None,
+            };
+        }
+
+        Some(current.clone())
+    }
+
+    /**
+        Resolve a template consisting of a single `${{ path }}` placeholder to
+        the structured value it references, without stringifying it.
+
+        Returns `Ok(None)` if the template is anything other than a bare path
+        reference, or if the path is undefined.
+    */
+    pub fn lookup_template(&self, template: &str) -> Result<Option<Value>> {
+        let Some(cap) = placeholder_regex().captures(template.trim()) else {
+            return Ok(None);
+        };
+        if cap[0].len() != template.trim().len() {
+            return Ok(None);
+        }
+
+        let expr =
+            expression::parse(&cap[1]).map_err(|e| anyhow!("{} in template '{}'", e, template))?;
+
+        Ok(match expr {
+            Expr::Variable(path) => self.lookup(&path),
+            _ => None,
+        })
+    }
+
+    /**
+        Find the first array output referenced by a template.
+
+        Returns the step and output names of the array along with its items,
+        so callers can rebind `step.output` to each item in turn and
+        interpolate per-item templates like `${{find_channels.channels.id}}`.
+    */
+    pub fn referenced_array(&self, template: &str) -> Result<Option<(String, String, Vec<Value>)>> {
+        for cap in placeholder_regex().captures_iter(template) {
+            let expr = expression::parse(&cap[1])
+                .map_err(|e| anyhow!("{} in template '{}'", e, template))?;
+
+            for path in expression::variables(&expr) {
+                if let [
+                    PathSegment::Key(step_name),
+                    PathSegment::Key(output_name),
+                    ..,
+                ] = path
+                    && let Some(Value::Array(items)) = self.get(step_name, output_name)
+                {
+                    return Ok(Some((
+                        step_name.clone(),
+                        output_name.clone(),
+                        items.clone(),
+                    )));
                 }
             }
         }
 
-        if let Some(err) = last_err {
-            return Err(err);
+        Ok(None)
+    }
+
+    /**
+        Interpolate a string, replacing `${{ expression }}` placeholders.
+
+        Expressions are `step_name.output_name` references, optionally with
+        further path segments (`${{ step.items[0].url }}`), filters
+        (`${{ step.url | url_decode }}`) and `+` concatenation; see
+        [`super::expression`] for the full syntax. Objects and arrays are
+        rendered as JSON.
+    */
+    pub fn interpolate(&self, template: &str) -> Result<String> {
+        self.render(template)?
+            .map_err(|reason| anyhow!("{} in template '{}'", reason, template))
+    }
+
+    /**
+        Interpolate a string, returning `None` instead of an error when the
+        template references an undefined value.
+
+        Syntax errors and filter failures are still reported as errors.
+    */
+    pub fn interpolate_optional(&self, template: &str) -> Result<Option<String>> {
+        Ok(self.render(template)?.ok())
+    }
+
+    fn render(&self, template: &str) -> Result<Result<String, String>> {
+        let re = placeholder_regex();
+
+        let mut result = String::with_capacity(template.len());
+        let mut last_end = 0;
+
+        for cap in re.captures_iter(template) {
+            let full_match = cap.get(0).unwrap();
+            result.push_str(&template[last_end..full_match.start()]);
+            last_end = full_match.end();
+
+            let expr = expression::parse(&cap[1])
+                .map_err(|e| anyhow!("{} in template '{}'", e, template))?;
+
+            let resolve =
+                |path: &[PathSegment]| self.lookup(path).as_ref().and_then(value_to_string);
+
+            match expression::evaluate(&expr, &resolve)
+                .map_err(|e| anyhow!("{} in template '{}'", e, template))?
+            {
+                Evaluated::Value(value) => result.push_str(&value),
+                Evaluated::Undefined(reason) => return Ok(Err(reason)),
+            }
         }
 
-        Ok(result)
+        result.push_str(&template[last_end..]);
+        Ok(Ok(result))
+    }
+}
+
+/**
+    Render a value as a string: strings as-is, null as undefined, and
+    everything else as JSON.
+*/
+pub fn value_to_string(value: &Value) -> Option<String> {
+    match value {
+        Value::Null => None,
+        Value::String(s) => Some(s.clone()),
+        other => Some(other.to_string()),
     }
 }
 
+fn item_to_value(item: &HashMap<String, Option<String>>) -> Value {
+    Value::Object(
+        item.iter()
+            .map(|(k, v)| {
+                (
+                    k.clone(),
+                    v.clone().map(Value::String).unwrap_or(Value::Null),
+                )
+            })
+            .collect(),
+    )
+}
+
+pub(super) fn placeholder_regex() -> &'static Regex {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    RE.get_or_init(|| Regex::new(r"(?s)\$\{\{(.*?)\}\}").expect("placeholder regex should compile"))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -101,4 +317,191 @@ mod tests {
         let result = ctx.interpolate("plain string").unwrap();
         assert_eq!(result, "plain string");
     }
+
+    #[test]
+    fn test_filters() {
+        let mut ctx = InterpolationContext::new();
+        ctx.set(
+            "get_manifest",
+            "url",
+            "https%3A%2F%2Fexample.com%2Fa%20b".to_string(),
+        );
+        ctx.set("page", "title", "  Canal RCN  ".to_string());
+        ctx.set("page", "token", "aGVsbG8gd29ybGQ=".to_string());
+        ctx.set(
+            "page",
+            "state",
+            r#"{"a": {"b": "deep", "n": [1, 2]}}"#.to_string(),
+        );
+
+        assert_eq!(
+            ctx.interpolate("${{ get_manifest.url | url_decode }}")
+                .unwrap(),
+            "https://example.com/a b"
+        );
+        assert_eq!(
+            ctx.interpolate("${{ page.title | trim | lower | replace(' ', '_') }}")
+                .unwrap(),
+            "canal_rcn"
+        );
+        assert_eq!(
+            ctx.interpolate("${{page.token | base64_decode}}").unwrap(),
+            "hello world"
+        );
+        assert_eq!(
+            ctx.interpolate("${{ page.state | json_get(\"a.b\") }}")
+                .unwrap(),
+            "deep"
+        );
+        assert_eq!(
+            ctx.interpolate("${{ page.state | json_get('a.n.1') }}")
+                .unwrap(),
+            "2"
+        );
+    }
+
+    #[test]
+    fn test_default_and_concatenation() {
+        let mut ctx = InterpolationContext::new();
+        ctx.set("channel", "id", "abc".to_string());
+        ctx.set("channel", "empty", String::new());
+
+        assert_eq!(
+            ctx.interpolate("${{ missing.value | default(\"x\") }}")
+                .unwrap(),
+            "x"
+        );
+        assert_eq!(
+            ctx.interpolate("${{ channel.empty | default('fallback') }}")
+                .unwrap(),
+            "fallback"
+        );
+        assert_eq!(
+            ctx.interpolate("${{ \"id-\" + channel.id | upper + '.json' }}")
+                .unwrap(),
+            "id-ABC.json"
+        );
+        assert_eq!(
+            ctx.interpolate("${{ (\"id-\" + channel.id) | upper }}")
+                .unwrap(),
+            "ID-ABC"
+        );
+    }
+
+    #[test]
+    fn test_structured_values() {
+        let mut ctx = InterpolationContext::new();
+        let items: ExtractedArray = vec![
+            HashMap::from([
+                ("url".to_string(), Some("https://a.example/1".to_string())),
+                ("title".to_string(), None),
+            ]),
+            HashMap::from([("url".to_string(), Some("https://a.example/2".to_string()))]),
+        ];
+        ctx.extend_array("list", "items", &items);
+        ctx.set_value(
+            "page",
+            "state",
+            serde_json::json!({"user": {"name": "ana", "tags": ["x", "y"]}}),
+        );
+
+        assert_eq!(
+            ctx.interpolate("${{list.items[1].url}}").unwrap(),
+            "https://a.example/2"
+        );
+        assert_eq!(ctx.interpolate("${{list.items.length}}").unwrap(), "2");
+        assert_eq!(
+            ctx.interpolate("${{page.state.user.name | upper}}")
+                .unwrap(),
+            "ANA"
+        );
+        assert_eq!(
+            ctx.interpolate("${{page.state.user[\"tags\"][0]}}")
+                .unwrap(),
+            "x"
+        );
+        assert_eq!(
+            ctx.interpolate("${{page.state.user.tags}}").unwrap(),
+            r#"["x","y"]"#
+        );
+
+        assert!(ctx.interpolate("${{list.items[0].title}}").is_err());
+        assert_eq!(
+            ctx.interpolate_optional("${{list.items[5].url}}").unwrap(),
+            None
+        );
+        assert!(ctx.interpolate_optional("${{list.items[x]}}").is_err());
+
+        let value = ctx.lookup_template("${{ list.items }}").unwrap().unwrap();
+        assert_eq!(value.as_array().unwrap().len(), 2);
+        assert!(
+            ctx.lookup_template("id-${{ list.items }}")
+                .unwrap()
+                .is_none()
+        );
+
+        let (step, output, found) = ctx
+            .referenced_array("${{ list.items.url | trim }}")
+            .unwrap()
+            .unwrap();
+        assert_eq!((step.as_str(), output.as_str()), ("list", "items"));
+        assert_eq!(found.len(), 2);
+    }
+
+    #[test]
+    fn test_env_namespace() {
+        let ctx = InterpolationContext::new();
+        let path = std::env::var("PATH").unwrap();
+
+        assert_eq!(ctx.interpolate("${{env.PATH}}").unwrap(), path);
+        assert!(ctx.interpolate("${{env.VIDPROXY_UNSET_VARIABLE}}").is_err());
+        assert!(
+            ctx.interpolate("${{secret.VIDPROXY_UNSET_SECRET}}")
+                .is_err()
+        );
+        assert_eq!(
+            ctx.interpolate("${{env.VIDPROXY_UNSET_VARIABLE | default('x')}}")
+                .unwrap(),
+            "x"
+        );
+    }
+
+    #[test]
+    fn test_expression_errors() {
+        let mut ctx = InterpolationContext::new();
+        ctx.set("channel", "id", "abc".to_string());
+
+        let err = ctx
+            .interpolate("https://example.com/${{ get_manifest.url }}")
+            .unwrap_err()
+            .to_string();
+        assert!(
+            err.contains("Undefined variable 'get_manifest.url'"),
+            "{}",
+            err
+        );
+        assert!(
+            err.contains("https://example.com/${{ get_manifest.url }}"),
+            "{}",
+            err
+        );
+
+        let err = ctx
+            .interpolate("${{ channel.id | shout }}")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Unknown filter 'shout'"), "{}", err);
+
+        let err = ctx
+            .interpolate("${{ missing.value | shout }}")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("Unknown filter 'shout'"), "{}", err);
+
+        let err = ctx
+            .interpolate("${{ channel.id | replace('a') }}")
+            .unwrap_err()
+            .to_string();
+        assert!(err.contains("expects 2 argument(s)"), "{}", err);
+    }
 }
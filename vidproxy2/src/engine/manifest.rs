@@ -1,18 +1,26 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
 use anyhow::{Result, anyhow};
 use include_dir::{Dir, include_dir};
+use schemars::JsonSchema;
 use serde::{Deserialize, Serialize};
 
+use super::expression::{self, Expr, PathSegment};
+use super::fragments::{Fragments, expand};
+use super::interpolate::{InterpolationContext, placeholder_regex};
 use super::step::Step;
 
 /**
-    Embedded source manifests directory.
+    Embedded source manifests directory. Only files directly inside it are
+    manifests; subdirectories hold fragments for `extends:` and `include:`.
 */
 static SOURCES_DIR: Dir = include_dir!("$CARGO_MANIFEST_DIR/sources");
 
 /**
     A source manifest defining how to discover channels and extract stream info.
 */
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct Manifest {
     pub source: Source,
     pub discovery: DiscoveryPhase,
@@ -23,10 +31,103 @@ pub struct Manifest {
     pub content: ContentPhase,
 }
 
+impl Manifest {
+    /**
+        Check that every `${{env.*}}` and `${{secret.*}}` variable the manifest
+        references is defined, then resolve them in the proxy settings.
+
+        References guarded by a `default(...)` filter are optional. Step
+        templates keep their placeholders and are resolved when they run.
+    */
+    pub fn resolve_variables(&mut self) -> Result<()> {
+        let yaml = serde_yaml::to_string(self)
+            .map_err(|e| anyhow!("Failed to serialize manifest '{}': {}", self.source.id, e))?;
+
+        let context = InterpolationContext::new();
+
+        let mut missing = Vec::new();
+        for cap in placeholder_regex().captures_iter(&yaml) {
+            let Ok(expr) = expression::parse(&cap[1]) else {
+                continue;
+            };
+            collect_required_variables(&expr, &mut |path| {
+                let name = expression::display_path(path);
+                if context.lookup(path).is_none() && !missing.contains(&name) {
+                    missing.push(name);
+                }
+            });
+        }
+
+        if !missing.is_empty() {
+            return Err(anyhow!(
+                "Source '{}' requires undefined variable(s): {}",
+                self.source.id,
+                missing.join(", ")
+            ));
+        }
+
+        let resolve = |proxy: &mut Option<String>| -> Result<()> {
+            if let Some(template) = proxy.take() {
+                let value = context.interpolate(&template)?;
+                *proxy = Some(value).filter(|v| !v.is_empty());
+            }
+            Ok(())
+        };
+
+        resolve(&mut self.source.proxy)?;
+        resolve(&mut self.discovery.browser.proxy)?;
+        if let Some(metadata) = &mut self.metadata {
+            resolve(&mut metadata.browser.proxy)?;
+        }
+        resolve(&mut self.content.browser.proxy)?;
+
+        Ok(())
+    }
+
+    /**
+        The resolved browser configs of the phases that can run in a browser.
+    */
+    pub fn browser_configs(&self) -> Vec<ResolvedBrowserConfig> {
+        let mut configs = vec![self.discovery.browser.resolve(&self.source)];
+        if let Some(metadata) = &self.metadata {
+            configs.push(metadata.browser.resolve(&self.source));
+        }
+        configs.push(self.content.browser.resolve(&self.source));
+        configs
+    }
+}
+
+/**
+    Visit the `env`/`secret` variable paths an expression needs to be defined,
+    skipping those under a `default(...)` filter.
+*/
+fn collect_required_variables(expr: &Expr, visit: &mut dyn FnMut(&[PathSegment])) {
+    match expr {
+        Expr::Variable(path) => {
+            if let Some(PathSegment::Key(namespace)) = path.first()
+                && (namespace == "env" || namespace == "secret")
+            {
+                visit(path);
+            }
+        }
+        Expr::Literal(_) => {}
+        Expr::Concat(parts) => {
+            for part in parts {
+                collect_required_variables(part, visit);
+            }
+        }
+        Expr::Filtered { expr, filters } => {
+            if !filters.iter().any(|f| f.name == "default") {
+                collect_required_variables(expr, visit);
+            }
+        }
+    }
+}
+
 /**
     Source metadata.
 */
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct Source {
     pub id: String,
     pub name: String,
@@ -38,19 +139,39 @@ pub struct Source {
     pub language: Option<String>,
     #[serde(default)]
     pub headless: Option<bool>,
+    /// IANA time zone of schedule times without an offset, e.g. `America/Bogota`
+    #[serde(default)]
+    pub timezone: Option<String>,
 }
 
 /**
     Browser configuration for a phase (proxy and headless settings).
 */
-#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
 pub struct BrowserConfig {
+    #[serde(default)]
+    pub browser: BrowserMode,
     #[serde(default)]
     pub proxy: Option<String>,
     #[serde(default)]
     pub headless: Option<bool>,
 }
 
+/**
+    Whether a phase launches a browser.
+
+    `auto` launches one only if a step needs a page, `none` runs the phase
+    with just the HTTP client.
+*/
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, Serialize, JsonSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum BrowserMode {
+    #[default]
+    Auto,
+    Chrome,
+    None,
+}
+
 impl BrowserConfig {
     /**
         Resolve with fallback to source-level defaults.
@@ -65,7 +186,10 @@ impl BrowserConfig {
 
 /**
     Resolved browser configuration with concrete values.
+
+    Browsers with equal configs are interchangeable, see `BrowserPool`.
 */
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct ResolvedBrowserConfig {
     pub proxy: Option<String>,
     pub headless: bool,
@@ -74,7 +198,7 @@ pub struct ResolvedBrowserConfig {
 /**
     Discovery phase - finds all channels from a source.
 */
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct DiscoveryPhase {
     #[serde(flatten)]
     pub browser: BrowserConfig,
@@ -85,7 +209,7 @@ pub struct DiscoveryPhase {
 /**
     Outputs from the discovery phase (per-channel).
 */
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct DiscoveryOutputs {
     pub id: String,
     #[serde(default)]
@@ -101,7 +225,7 @@ pub struct DiscoveryOutputs {
 /**
     Processing phase - filter and transform discovered channels.
 */
-#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
 pub struct ProcessPhase {
     #[serde(default)]
     pub filter: Option<ChannelFilter>,
@@ -112,7 +236,7 @@ pub struct ProcessPhase {
 /**
     Filter to apply to discovered channels.
 */
-#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
 pub struct ChannelFilter {
     #[serde(default)]
     pub name: Vec<String>,
@@ -123,7 +247,7 @@ pub struct ChannelFilter {
 /**
     A transform to apply to channels.
 */
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(tag = "kind")]
 pub enum Transform {
     AddCategory {
@@ -152,10 +276,13 @@ pub enum Transform {
 /**
     Metadata phase - extracts EPG data per channel.
 */
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct MetadataPhase {
     #[serde(flatten)]
     pub browser: BrowserConfig,
+    /// Overrides `source.timezone` for this phase
+    #[serde(default)]
+    pub timezone: Option<String>,
     pub steps: Vec<Step>,
     pub outputs: MetadataOutputs,
 }
@@ -163,7 +290,7 @@ pub struct MetadataPhase {
 /**
     Outputs from the metadata phase.
 */
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct MetadataOutputs {
     pub programmes: String,
     #[serde(default)]
@@ -173,7 +300,7 @@ pub struct MetadataOutputs {
 /**
     Content phase - fetches stream info for a single channel.
 */
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct ContentPhase {
     #[serde(flatten)]
     pub browser: BrowserConfig,
@@ -184,7 +311,7 @@ pub struct ContentPhase {
 /**
     Outputs from the content phase.
 */
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct ContentOutputs {
     pub manifest_url: String,
     #[serde(default)]
@@ -198,37 +325,243 @@ pub struct ContentOutputs {
 }
 
 /**
-    Load all available source manifests.
+    Manifests loaded from the embedded set and an optional sources directory.
+
+    Files that fail to read, parse or resolve are reported in `errors` rather
+    than aborting the whole load.
 */
-pub fn load_all() -> Result<Vec<Manifest>> {
-    let mut manifests = Vec::new();
-
-    for file in SOURCES_DIR.files() {
-        let path = file.path();
-        if path
-            .extension()
-            .map(|e| e == "yaml" || e == "yml")
-            .unwrap_or(false)
-        {
-            let content = file
-                .contents_utf8()
-                .ok_or_else(|| anyhow!("Failed to read {:?} as UTF-8", path))?;
+#[derive(Debug, Default)]
+pub struct LoadedManifests {
+    pub manifests: Vec<Manifest>,
+    pub errors: Vec<ManifestError>,
+    /// The source id defined by each file loaded from the sources directory
+    pub files: HashMap<PathBuf, String>,
+}
+
+/**
+    A manifest file that could not be loaded.
+*/
+#[derive(Debug, Clone)]
+pub struct ManifestError {
+    pub path: PathBuf,
+    pub message: String,
+    /// The source id the file declares, if it could still be read
+    pub source_id: Option<String>,
+}
+
+impl std::fmt::Display for ManifestError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.path.display(), self.message)
+    }
+}
+
+fn is_manifest_file(path: &Path) -> bool {
+    path.extension()
+        .map(|e| e == "yaml" || e == "yml")
+        .unwrap_or(false)
+}
+
+fn parse_manifest(content: &str, fragments: &Fragments) -> Result<Manifest> {
+    let content = expand(content, fragments)?;
+    let mut manifest: Manifest =
+        serde_yaml::from_str(&content).map_err(|e| anyhow!("Failed to parse: {}", e))?;
+    manifest.resolve_variables()?;
+    Ok(manifest)
+}
+
+/**
+    Load one manifest file, reporting a failure as a `ManifestError`.
+*/
+fn load_file(
+    path: PathBuf,
+    content: &str,
+    fragments: &Fragments,
+) -> Result<Manifest, ManifestError> {
+    parse_manifest(content, fragments).map_err(|e| ManifestError {
+        path,
+        message: e.to_string(),
+        source_id: declared_source_id(content, fragments),
+    })
+}
+
+/**
+    The `source.id` a manifest file declares, read without deserializing or
+    resolving the manifest, so a file that fails to load can still be
+    matched to its source.
+*/
+fn declared_source_id(content: &str, fragments: &Fragments) -> Option<String> {
+    let content = expand(content, fragments).ok()?;
+    let value: serde_yaml::Value = serde_yaml::from_str(&content).ok()?;
+    value.get("source")?.get("id")?.as_str().map(str::to_string)
+}
+
+/**
+    The embedded manifest files, as (path, contents).
+*/
+pub fn embedded_files() -> Vec<(PathBuf, String)> {
+    SOURCES_DIR
+        .files()
+        .filter(|file| is_manifest_file(file.path()))
+        .map(|file| {
+            (
+                file.path().to_path_buf(),
+                String::from_utf8_lossy(file.contents()).into_owned(),
+            )
+        })
+        .collect()
+}
 
-            let manifest: Manifest = serde_yaml::from_str(content)
-                .map_err(|e| anyhow!("Failed to parse {:?}: {}", path, e))?;
+/**
+    An embedded fragment by path, e.g. `templates/mediastream.yaml`.
+*/
+pub(super) fn embedded_fragment(name: &str) -> Option<&'static str> {
+    SOURCES_DIR
+        .get_file(name)
+        .and_then(|file| file.contents_utf8())
+}
+
+/**
+    Load all embedded source manifests.
 
-            manifests.push(manifest);
+    Files that fail to load are reported in `errors`, like `load_dir` does,
+    so one broken source doesn't keep the others from loading.
+*/
+pub fn load_all() -> LoadedManifests {
+    let mut loaded = LoadedManifests::default();
+    for (path, content) in embedded_files() {
+        match load_file(path, &content, &Fragments::embedded()) {
+            Ok(manifest) => loaded.manifests.push(manifest),
+            Err(error) => loaded.errors.push(error),
         }
     }
+    loaded
+}
+
+/**
+    Load manifests from a directory on disk.
 
-    Ok(manifests)
+    Only `.yaml`/`.yml` files directly inside `dir` are considered, in file
+    name order. Fragments are looked up in `dir` first, then among the
+    embedded ones. Returns an error only if the directory itself can't be
+    read.
+*/
+pub fn load_dir(dir: &Path) -> Result<LoadedManifests> {
+    let entries = std::fs::read_dir(dir)
+        .map_err(|e| anyhow!("Failed to read sources directory {:?}: {}", dir, e))?;
+
+    let mut paths: Vec<PathBuf> = entries
+        .filter_map(|entry| entry.ok().map(|e| e.path()))
+        .filter(|path| path.is_file() && is_manifest_file(path))
+        .collect();
+    paths.sort();
+
+    let mut loaded = LoadedManifests::default();
+    let mut seen: HashMap<String, PathBuf> = HashMap::new();
+
+    for path in paths {
+        let result = match std::fs::read_to_string(&path) {
+            Ok(content) => load_file(path.clone(), &content, &Fragments::in_dir(dir)),
+            Err(e) => Err(ManifestError {
+                path: path.clone(),
+                message: format!("Failed to read: {}", e),
+                source_id: None,
+            }),
+        };
+
+        match result {
+            Ok(manifest) => {
+                if let Some(previous) = seen.get(&manifest.source.id) {
+                    loaded.errors.push(ManifestError {
+                        path,
+                        message: format!(
+                            "Duplicate source id '{}' (already loaded from {})",
+                            manifest.source.id,
+                            previous.display()
+                        ),
+                        source_id: None,
+                    });
+                    continue;
+                }
+                seen.insert(manifest.source.id.clone(), path);
+                loaded.manifests.push(manifest);
+            }
+            Err(error) => loaded.errors.push(error),
+        }
+    }
+
+    loaded.files = seen.into_iter().map(|(id, path)| (path, id)).collect();
+
+    Ok(loaded)
+}
+
+/**
+    Load the embedded manifests, merged with those in `sources_dir` if given.
+
+    A manifest on disk replaces the embedded one with the same `source.id`;
+    any other disk manifest is added to the set. Embedded files that fail to
+    load are reported in `errors`, unless the directory overrides them.
+*/
+pub fn load_sources(sources_dir: Option<&Path>) -> Result<LoadedManifests> {
+    let embedded = load_all();
+    match sources_dir {
+        Some(dir) => Ok(merge_sources(embedded, load_dir(dir)?, dir)),
+        None => Ok(embedded),
+    }
+}
+
+fn merge_sources(
+    embedded: LoadedManifests,
+    loaded: LoadedManifests,
+    dir: &Path,
+) -> LoadedManifests {
+    let mut manifests = embedded.manifests;
+    for manifest in loaded.manifests {
+        match manifests
+            .iter_mut()
+            .find(|m| m.source.id == manifest.source.id)
+        {
+            Some(existing) => {
+                println!(
+                    "[manifest] Overriding embedded source '{}' from {}",
+                    manifest.source.id,
+                    dir.display()
+                );
+                *existing = manifest;
+            }
+            None => manifests.push(manifest),
+        }
+    }
+
+    // A broken embedded source doesn't matter once the directory replaces it
+    let mut errors: Vec<ManifestError> = embedded
+        .errors
+        .into_iter()
+        .filter(|error| {
+            error
+                .source_id
+                .as_ref()
+                .is_none_or(|id| !loaded.files.values().any(|loaded_id| loaded_id == id))
+        })
+        .collect();
+    errors.extend(loaded.errors);
+
+    LoadedManifests {
+        manifests,
+        errors,
+        files: loaded.files,
+    }
 }
 
 /**
     Find a source manifest by ID (case-insensitive, partial match).
 */
-pub fn find_by_id(id: &str) -> Result<Manifest> {
-    let manifests = load_all()?;
+pub fn find_by_id(id: &str, sources_dir: Option<&Path>) -> Result<Manifest> {
+    let loaded = load_sources(sources_dir)?;
+    for error in &loaded.errors {
+        eprintln!("[manifest] Skipping {}", error);
+    }
+
+    let manifests = loaded.manifests;
     let id_lower = id.to_lowercase();
 
     if let Some(manifest) = manifests
@@ -251,9 +584,12 @@ pub fn find_by_id(id: &str) -> Result<Manifest> {
 /**
     List all available source IDs.
 */
-pub fn list_sources() -> Result<Vec<String>> {
-    let manifests = load_all()?;
-    Ok(manifests.into_iter().map(|m| m.source.id).collect())
+pub fn list_sources(sources_dir: Option<&Path>) -> Result<Vec<String>> {
+    let loaded = load_sources(sources_dir)?;
+    for error in &loaded.errors {
+        eprintln!("[manifest] Skipping {}", error);
+    }
+    Ok(loaded.manifests.into_iter().map(|m| m.source.id).collect())
 }
 
 #[cfg(test)]
@@ -262,7 +598,9 @@ mod tests {
 
     #[test]
     fn test_load_all_manifests() {
-        let manifests = load_all().expect("Failed to load manifests");
+        let loaded = load_all();
+        assert!(loaded.errors.is_empty(), "{:?}", loaded.errors);
+        let manifests = loaded.manifests;
         assert!(!manifests.is_empty(), "No manifests found");
 
         for manifest in &manifests {
@@ -275,7 +613,7 @@ mod tests {
 
     #[test]
     fn test_list_sources() {
-        let sources = list_sources().expect("Failed to list sources");
+        let sources = list_sources(None).expect("Failed to list sources");
         assert!(sources.contains(&"caracol".to_string()));
         assert!(sources.contains(&"canal_rcn".to_string()));
         assert!(sources.contains(&"canal_1".to_string()));
@@ -283,8 +621,349 @@ mod tests {
 
     #[test]
     fn test_find_by_id() {
-        let manifest = find_by_id("caracol").expect("Failed to find caracol");
+        let manifest = find_by_id("caracol", None).expect("Failed to find caracol");
         assert_eq!(manifest.source.id, "caracol");
         assert_eq!(manifest.source.name, "Caracol TV");
     }
+
+    #[test]
+    fn test_load_sources_dir() {
+        let dir = tempfile::tempdir().unwrap();
+        let manifest = |id: &str, name: &str| {
+            format!(
+                r#"
+source:
+  id: "{id}"
+  name: "{name}"
+discovery:
+  outputs:
+    id: "${{{{ page.id }}}}"
+  steps: []
+content:
+  outputs:
+    manifest_url: "${{{{ page.url }}}}"
+  steps: []
+"#
+            )
+        };
+        std::fs::write(dir.path().join("a.yaml"), manifest("caracol", "Override")).unwrap();
+        std::fs::write(dir.path().join("b.yml"), manifest("private", "Private")).unwrap();
+        std::fs::write(dir.path().join("c.yaml"), "source: [not, a, manifest").unwrap();
+        std::fs::write(dir.path().join("notes.txt"), "ignored").unwrap();
+
+        let loaded = load_sources(Some(dir.path())).unwrap();
+        let embedded = load_all().manifests.len();
+        assert_eq!(loaded.manifests.len(), embedded + 1);
+
+        let caracol = loaded
+            .manifests
+            .iter()
+            .find(|m| m.source.id == "caracol")
+            .unwrap();
+        assert_eq!(caracol.source.name, "Override");
+        assert!(loaded.manifests.iter().any(|m| m.source.id == "private"));
+
+        assert_eq!(loaded.errors.len(), 1);
+        assert!(loaded.errors[0].path.ends_with("c.yaml"));
+    }
+
+    #[test]
+    fn test_broken_embedded_sources() {
+        let manifest = |id: &str, url: &str| {
+            format!(
+                r#"
+source:
+  id: "{id}"
+  name: "{id}"
+discovery:
+  outputs:
+    id: "${{{{ page.id }}}}"
+  steps:
+    - name: "page"
+      kind: Fetch
+      url: "{url}"
+      extract:
+        id:
+          kind: url
+content:
+  outputs:
+    manifest_url: "${{{{ page.url }}}}"
+  steps: []
+"#
+            )
+        };
+        let missing_secret = "https://example.com/?key=${{ secret.VIDPROXY_TEST_MISSING }}";
+
+        // Embedded files that fail on an undefined secret
+        let mut embedded = load_all();
+        for id in ["caracol", "embedded-broken"] {
+            let path = PathBuf::from(format!("{}.yaml", id));
+            let error =
+                load_file(path, &manifest(id, missing_secret), &Fragments::embedded()).unwrap_err();
+            assert_eq!(error.source_id.as_deref(), Some(id));
+            embedded.manifests.retain(|m| m.source.id != id);
+            embedded.errors.push(error);
+        }
+
+        let dir = tempfile::tempdir().unwrap();
+        std::fs::write(
+            dir.path().join("caracol.yaml"),
+            manifest("caracol", "https://example.com/"),
+        )
+        .unwrap();
+        let loaded = merge_sources(embedded, load_dir(dir.path()).unwrap(), dir.path());
+
+        // The overridden one is rescued, the other is reported without
+        // keeping the rest from loading
+        assert_eq!(loaded.errors.len(), 1, "{:?}", loaded.errors);
+        assert!(loaded.errors[0].path.ends_with("embedded-broken.yaml"));
+        assert!(
+            loaded.errors[0]
+                .message
+                .contains("secret.VIDPROXY_TEST_MISSING"),
+            "{}",
+            loaded.errors[0]
+        );
+        assert!(loaded.manifests.iter().any(|m| m.source.id == "caracol"));
+        assert_eq!(loaded.manifests.len(), load_all().manifests.len());
+    }
+
+    #[test]
+    fn test_required_variables() {
+        let yaml = r#"
+source:
+  id: "example"
+  name: "Example"
+  proxy: "${{ env.VIDPROXY_TEST_PROXY | default('socks5://127.0.0.1:1080') }}"
+discovery:
+  outputs:
+    id: "${{ page.id }}"
+  steps:
+    - name: "page"
+      kind: Navigate
+      url: "https://example.com/?key=${{ secret.VIDPROXY_TEST_KEY }}&u=${{ env.VIDPROXY_TEST_USER }}"
+content:
+  outputs:
+    manifest_url: "${{ page.url }}"
+  steps: []
+"#;
+        let mut manifest: Manifest = serde_yaml::from_str(yaml).unwrap();
+        let err = manifest.resolve_variables().unwrap_err().to_string();
+        assert!(err.contains("secret.VIDPROXY_TEST_KEY"), "{}", err);
+        assert!(err.contains("env.VIDPROXY_TEST_USER"), "{}", err);
+        assert!(!err.contains("VIDPROXY_TEST_PROXY"), "{}", err);
+
+        let yaml = yaml.replace("${{ secret.VIDPROXY_TEST_KEY }}", "k");
+        let yaml = yaml.replace("${{ env.VIDPROXY_TEST_USER }}", "u");
+        let mut manifest: Manifest = serde_yaml::from_str(&yaml).unwrap();
+        manifest.resolve_variables().unwrap();
+        assert_eq!(
+            manifest.source.proxy.as_deref(),
+            Some("socks5://127.0.0.1:1080")
+        );
+    }
+
+    #[test]
+    fn test_parse_if_step() {
+        let yaml = r##"
+- name: "dismiss_cookies"
+  kind: If
+  condition:
+    selector: "#cookie-banner button"
+  then:
+    - name: "accept_cookies"
+      kind: Automation
+      steps:
+        - kind: Click
+          selector: "#cookie-banner button"
+- name: "fallback_page"
+  kind: When
+  condition:
+    array: "channels"
+    not: true
+  then:
+    - name: "go_to_fallback"
+      kind: Navigate
+      url: "https://example.com/live"
+  else: []
+"##;
+
+        let steps: Vec<Step> = serde_yaml::from_str(yaml).expect("Failed to parse steps");
+        assert_eq!(steps.len(), 2);
+
+        match &steps[0] {
+            Step::If {
+                condition,
+                then,
+                otherwise,
+                ..
+            } => {
+                assert_eq!(condition.selector.as_deref(), Some("#cookie-banner button"));
+                assert_eq!(then.len(), 1);
+                assert_eq!(then[0].name(), "accept_cookies");
+                assert!(otherwise.is_empty());
+            }
+            other => panic!("Expected If step, got {:?}", other),
+        }
+
+        match &steps[1] {
+            Step::If { condition, .. } => {
+                assert_eq!(condition.array.as_deref(), Some("channels"));
+                assert!(condition.not);
+            }
+            other => panic!("Expected If step, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_parse_for_each_step() {
+        let yaml = r#"
+name: "enrich_channels"
+kind: ForEach
+array: "channels"
+as: "channel"
+steps:
+  - name: "get_detail"
+    kind: Fetch
+    url: "https://example.com/channels/${{channel.id}}.json"
+    extract:
+      logo:
+        kind: jsonpath
+        path: "$.logo"
+"#;
+
+        let step: Step = serde_yaml::from_str(yaml).expect("Failed to parse step");
+        match &step {
+            Step::ForEach {
+                array,
+                binding,
+                steps,
+                ..
+            } => {
+                assert_eq!(array, "channels");
+                assert_eq!(binding.as_deref(), Some("channel"));
+                assert_eq!(steps.len(), 1);
+            }
+            other => panic!("Expected ForEach step, got {:?}", other),
+        }
+
+        let nested: Vec<&str> = step.nested_steps().iter().map(|s| s.name()).collect();
+        assert_eq!(nested, vec!["get_detail"]);
+    }
+
+    #[test]
+    fn test_parse_retry_and_on_error() {
+        use crate::engine::step::{ErrorPolicy, OnError};
+
+        let yaml = r#"
+- name: "get_manifest"
+  kind: Sniff
+  request:
+    url: "master\\.m3u8"
+  extract:
+    manifest_url:
+      kind: url
+  retry:
+    attempts: 3
+    backoff: 0.5
+  on_error:
+    fallback:
+      - name: "reload"
+        kind: Navigate
+        url: "https://example.com/live"
+- name: "accept_cookies"
+  kind: Automation
+  steps: []
+  on_error: continue
+"#;
+
+        let steps: Vec<Step> = serde_yaml::from_str(yaml).expect("Failed to parse steps");
+
+        let retry = steps[0].retry().expect("Expected retry policy");
+        assert_eq!(retry.attempts, 3);
+        assert_eq!(retry.delay(1).as_secs_f64(), 0.5);
+        assert_eq!(retry.delay(3).as_secs_f64(), 2.0);
+        assert_eq!(retry.delay(u32::MAX), crate::engine::step::MAX_RETRY_DELAY);
+        assert!(
+            matches!(steps[0].on_error(), OnError::Fallback { fallback } if fallback.len() == 1)
+        );
+        assert_eq!(steps[0].nested_steps()[0].name(), "reload");
+
+        assert!(steps[1].retry().is_none());
+        assert!(matches!(
+            steps[1].on_error(),
+            OnError::Policy(ErrorPolicy::Continue)
+        ));
+
+        for backoff in ["-1", ".nan", ".inf"] {
+            let yaml = format!(
+                "[{{ name: open, kind: Navigate, url: \"about:blank\", retry: {{ attempts: 2, backoff: {} }} }}]",
+                backoff
+            );
+            assert!(
+                serde_yaml::from_str::<Vec<Step>>(&yaml).is_err(),
+                "backoff {} should be rejected",
+                backoff
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_automation_actions() {
+        use crate::engine::step::AutomationAction;
+
+        let yaml = r#"
+- name: "login"
+  kind: Automation
+  steps:
+    - kind: SwitchFrame
+      selector: "iframe#login"
+    - kind: Type
+      selector: "input[name=email]"
+      text: "${{ secret.EMAIL }}"
+    - kind: PressKey
+      key: "Enter"
+      wait_for:
+        delay: 1.0
+    - kind: SwitchFrame
+    - kind: Scroll
+      y: 600
+    - kind: SelectOption
+      selector: "select#region"
+      label: "Colombia"
+    - kind: WaitFor
+      selector: "div.player"
+"#;
+
+        let steps: Vec<Step> = serde_yaml::from_str(yaml).expect("Failed to parse steps");
+        let Step::Automation { steps: actions, .. } = &steps[0] else {
+            panic!("Expected Automation step");
+        };
+
+        assert_eq!(actions.len(), 7);
+        assert!(
+            matches!(&actions[0], AutomationAction::SwitchFrame { selector: Some(s), .. } if s == "iframe#login")
+        );
+        assert!(matches!(
+            &actions[1],
+            AutomationAction::Type { append: false, .. }
+        ));
+        assert!(
+            matches!(&actions[2], AutomationAction::PressKey { selector: None, wait_for: Some(w), .. } if w.delay == Some(1.0))
+        );
+        assert!(matches!(
+            &actions[3],
+            AutomationAction::SwitchFrame { selector: None, .. }
+        ));
+        assert!(matches!(
+            &actions[4],
+            AutomationAction::Scroll { selector: None, x: None, y: Some(y), .. } if *y == 600.0
+        ));
+        assert!(
+            matches!(&actions[5], AutomationAction::SelectOption { value: None, label: Some(l), .. } if l == "Colombia")
+        );
+        assert!(
+            matches!(&actions[6], AutomationAction::WaitFor { condition } if condition.selector.as_deref() == Some("div.player"))
+        );
+    }
 }
@@ -1,13 +1,25 @@
+pub mod artifacts;
+mod automation;
 pub mod browser;
+mod cookies;
 pub mod executor;
+pub mod expression;
 pub mod extractor;
+pub mod fixtures;
+pub mod fragments;
+pub mod har;
 pub mod interpolate;
 pub mod manifest;
+mod request;
+pub mod schema;
+pub mod secrets;
 pub mod step;
+pub mod trace;
+pub mod transform;
+pub mod validate;
 
 pub use executor::PhaseOutput;
-pub use extractor::ExtractedArray;
 pub use interpolate::InterpolationContext;
 pub use manifest::{
-    ChannelFilter, ProcessPhase, Source, Transform, find_by_id, list_sources, load_all,
+    ChannelFilter, ProcessPhase, Source, Transform, find_by_id, list_sources, load_sources,
 };
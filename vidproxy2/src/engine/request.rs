@@ -0,0 +1,212 @@
+use std::collections::HashMap;
+
+use anyhow::{Result, anyhow};
+use reqwest::{Client, Method, Url};
+use serde_json::Value;
+
+use super::fixtures::Fixtures;
+use super::interpolate::InterpolationContext;
+use super::step::RequestBody;
+use super::trace::Tracer;
+
+/**
+    Method, query and body of a `Fetch`/`FetchInBrowser` request, with every
+    template resolved.
+*/
+pub(super) struct HttpRequest {
+    pub method: Method,
+    pub query: Vec<(String, String)>,
+    pub body: Option<HttpBody>,
+}
+
+/**
+    The HTTP client of a phase, the recording its responses are written
+    to or replayed from, and the trace its requests are added to, if any.
+*/
+pub(super) struct HttpClient<'a> {
+    pub client: Client,
+    pub fixtures: Option<&'a Fixtures>,
+    pub trace: Option<&'a Tracer>,
+}
+
+/**
+    An encoded request body and the content type to send it with.
+*/
+pub(super) struct HttpBody {
+    pub content_type: Option<String>,
+    pub content: String,
+}
+
+impl HttpRequest {
+    pub fn resolve(
+        method: Option<&str>,
+        query: &HashMap<String, String>,
+        body: Option<&RequestBody>,
+        context: &InterpolationContext,
+    ) -> Result<Self> {
+        let method = match method {
+            Some(template) => {
+                let method = context.interpolate(template)?.trim().to_ascii_uppercase();
+                Method::from_bytes(method.as_bytes())
+                    .map_err(|_| anyhow!("Invalid HTTP method '{}'", method))?
+            }
+            None => Method::GET,
+        };
+
+        Ok(Self {
+            method,
+            query: interpolate_pairs(query, context)?,
+            body: body.map(|body| resolve_body(body, context)).transpose()?,
+        })
+    }
+
+    /**
+        Append the query parameters to a URL.
+    */
+    pub fn url(&self, url: &str) -> Result<String> {
+        if self.query.is_empty() {
+            return Ok(url.to_string());
+        }
+
+        let mut parsed = Url::parse(url).map_err(|e| anyhow!("Invalid URL '{}': {}", url, e))?;
+        parsed.query_pairs_mut().extend_pairs(&self.query);
+        Ok(parsed.into())
+    }
+}
+
+fn resolve_body(body: &RequestBody, context: &InterpolationContext) -> Result<HttpBody> {
+    Ok(match body {
+        RequestBody::Raw { raw, content_type } => HttpBody {
+            content_type: content_type
+                .as_deref()
+                .map(|t| context.interpolate(t))
+                .transpose()?,
+            content: context.interpolate(raw)?,
+        },
+        RequestBody::Json { json } => HttpBody {
+            content_type: Some("application/json".to_string()),
+            content: serde_json::to_string(&interpolate_json(json, context)?)?,
+        },
+        RequestBody::Form { form } => HttpBody {
+            content_type: Some("application/x-www-form-urlencoded".to_string()),
+            content: url::form_urlencoded::Serializer::new(String::new())
+                .extend_pairs(interpolate_pairs(form, context)?)
+                .finish(),
+        },
+    })
+}
+
+/**
+    Interpolate the values of a map, sorted by key so requests are stable.
+*/
+fn interpolate_pairs(
+    pairs: &HashMap<String, String>,
+    context: &InterpolationContext,
+) -> Result<Vec<(String, String)>> {
+    let mut resolved = pairs
+        .iter()
+        .map(|(key, value)| Ok((key.clone(), context.interpolate(value)?)))
+        .collect::<Result<Vec<_>>>()?;
+    resolved.sort();
+    Ok(resolved)
+}
+
+/**
+    Interpolate every string in a JSON value.
+
+    A string that is a single `${{ path }}` placeholder is replaced by the
+    value it references, so objects and arrays can be passed through as-is.
+*/
+fn interpolate_json(value: &Value, context: &InterpolationContext) -> Result<Value> {
+    Ok(match value {
+        Value::String(template) => match context.lookup_template(template)? {
+            Some(value) => value,
+            None => Value::String(context.interpolate(template)?),
+        },
+        Value::Array(items) => Value::Array(
+            items
+                .iter()
+                .map(|item| interpolate_json(item, context))
+                .collect::<Result<_>>()?,
+        ),
+        Value::Object(map) => Value::Object(
+            map.iter()
+                .map(|(key, value)| Ok((key.clone(), interpolate_json(value, context)?)))
+                .collect::<Result<_>>()?,
+        ),
+        other => other.clone(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn context() -> InterpolationContext {
+        let mut context = InterpolationContext::new();
+        context.set("auth", "token", "a b&c".to_string());
+        context.set_value("list", "ids", serde_json::json!([1, 2, 3]));
+        context
+    }
+
+    #[test]
+    fn test_resolve_request() {
+        let body: RequestBody = serde_yaml::from_str(
+            r#"
+json:
+  query: "query Epg($ids: [ID!]) { epg(ids: $ids) { title } }"
+  variables:
+    ids: "${{ list.ids }}"
+    token: "Bearer ${{ auth.token }}"
+"#,
+        )
+        .unwrap();
+
+        let mut query = HashMap::new();
+        query.insert("token".to_string(), "${{ auth.token }}".to_string());
+        query.insert("page".to_string(), "1".to_string());
+
+        let request = HttpRequest::resolve(Some("post"), &query, Some(&body), &context()).unwrap();
+        assert_eq!(request.method, Method::POST);
+        assert_eq!(
+            request.url("https://example.com/api?x=1").unwrap(),
+            "https://example.com/api?x=1&page=1&token=a+b%26c"
+        );
+
+        let body = request.body.unwrap();
+        assert_eq!(body.content_type.as_deref(), Some("application/json"));
+        let json: Value = serde_json::from_str(&body.content).unwrap();
+        assert_eq!(json["variables"]["ids"], serde_json::json!([1, 2, 3]));
+        assert_eq!(json["variables"]["token"], "Bearer a b&c");
+    }
+
+    #[test]
+    fn test_resolve_form_body() {
+        let body: RequestBody = serde_yaml::from_str(
+            r#"
+form:
+  user: "me"
+  token: "${{ auth.token }}"
+"#,
+        )
+        .unwrap();
+
+        let request = HttpRequest::resolve(None, &HashMap::new(), Some(&body), &context()).unwrap();
+        assert_eq!(request.method, Method::GET);
+        assert_eq!(
+            request.url("https://example.com").unwrap(),
+            "https://example.com"
+        );
+
+        let body = request.body.unwrap();
+        assert_eq!(
+            body.content_type.as_deref(),
+            Some("application/x-www-form-urlencoded")
+        );
+        assert_eq!(body.content, "token=a+b%26c&user=me");
+
+        assert!(
+            HttpRequest::resolve(Some("NOT A METHOD"), &HashMap::new(), None, &context()).is_err()
+        );
+    }
+}
@@ -0,0 +1,285 @@
+use serde_json::{Map, Value, json};
+
+use super::manifest::Manifest;
+
+/**
+    JSON Schema for source manifests, derived from the `Manifest` types.
+
+    Editors can use it for completion and validation of manifest files, e.g.
+    with a `# yaml-language-server: $schema=<path>` comment at the top.
+*/
+pub fn manifest_schema() -> Value {
+    let mut schema = schemars::schema_for!(Manifest).to_value();
+    add_fragments(&mut schema);
+    schema
+}
+
+/**
+    Add the `extends:`, `params:` and `include:` keys, which are expanded
+    before a manifest is deserialized, to the derived schema.
+
+    A manifest that extends a base may leave out anything the base provides,
+    so it is checked against a `Partial` copy of the schema where mappings
+    merged with the base have no required keys. Any list item may be an
+    `include:` item.
+*/
+fn add_fragments(schema: &mut Value) {
+    let Value::Object(root) = schema else {
+        return;
+    };
+    let mut defs = match root.remove("$defs") {
+        Some(Value::Object(defs)) => defs,
+        _ => Map::new(),
+    };
+
+    let mut manifest = Map::new();
+    for key in ["type", "properties", "required"] {
+        if let Some(value) = root.remove(key) {
+            manifest.insert(key.to_string(), value);
+        }
+    }
+    if let Some(Value::Object(properties)) = manifest.get_mut("properties") {
+        properties.insert(
+            "extends".to_string(),
+            json!({
+                "description": "Base manifest this one is merged over, by path relative to the sources directory.",
+                "type": "string",
+            }),
+        );
+        properties.insert(
+            "params".to_string(),
+            json!({
+                "description": "Parameters for the `${{ param.NAME }}` placeholders of the base manifest.",
+                "type": "object",
+            }),
+        );
+    }
+    defs.insert("Manifest".to_string(), Value::Object(manifest));
+    partial(&mut defs, "Manifest");
+
+    for def in defs.values_mut() {
+        allow_includes(def);
+    }
+    defs.insert(
+        "Include".to_string(),
+        json!({
+            "description": "Replaced by the items of a fragment, by path relative to the sources directory.",
+            "type": "object",
+            "properties": {
+                "include": { "type": "string" },
+                "params": { "type": "object" },
+            },
+            "required": ["include"],
+            "additionalProperties": false,
+        }),
+    );
+
+    root.insert("if".to_string(), json!({ "required": ["extends"] }));
+    root.insert(
+        "then".to_string(),
+        json!({ "$ref": "#/$defs/PartialManifest" }),
+    );
+    root.insert("else".to_string(), json!({ "$ref": "#/$defs/Manifest" }));
+    root.insert("$defs".to_string(), Value::Object(defs));
+}
+
+/**
+    Add a `Partial` copy of the definition `name`, returning its name.
+*/
+fn partial(defs: &mut Map<String, Value>, name: &str) -> String {
+    let partial_name = format!("Partial{}", name);
+    if defs.contains_key(&partial_name) {
+        return partial_name;
+    }
+    let Some(mut def) = defs.get(name).cloned() else {
+        return name.to_string();
+    };
+
+    // Reserve the name first, so recursive types terminate
+    defs.insert(partial_name.clone(), Value::Null);
+    make_partial(&mut def, defs);
+    defs.insert(partial_name.clone(), def);
+    partial_name
+}
+
+/**
+    Drop the required keys of a schema and of the mappings it merges.
+
+    List items are replaced rather than merged, so they stay complete, and
+    so do the variants of a `oneOf`, which would otherwise overlap.
+*/
+fn make_partial(schema: &mut Value, defs: &mut Map<String, Value>) {
+    let Value::Object(map) = schema else {
+        return;
+    };
+    map.remove("required");
+
+    if let Some(name) = map
+        .get("$ref")
+        .and_then(Value::as_str)
+        .and_then(|r| r.strip_prefix("#/$defs/"))
+        .map(str::to_string)
+    {
+        let partial_name = partial(defs, &name);
+        map.insert(
+            "$ref".to_string(),
+            Value::String(format!("#/$defs/{}", partial_name)),
+        );
+    }
+    if let Some(Value::Object(properties)) = map.get_mut("properties") {
+        for property in properties.values_mut() {
+            make_partial(property, defs);
+        }
+    }
+    if let Some(additional) = map.get_mut("additionalProperties") {
+        make_partial(additional, defs);
+    }
+    if let Some(Value::Array(variants)) = map.get_mut("anyOf") {
+        for variant in variants {
+            make_partial(variant, defs);
+        }
+    }
+}
+
+/**
+    Let the items of every list in a schema also be `include:` items.
+*/
+fn allow_includes(schema: &mut Value) {
+    match schema {
+        Value::Object(map) => {
+            for value in map.values_mut() {
+                allow_includes(value);
+            }
+            if let Some(items) = map.get_mut("items")
+                && items.is_object()
+            {
+                let item = items.take();
+                *items = json!({ "anyOf": [item, { "$ref": "#/$defs/Include" }] });
+            }
+        }
+        Value::Array(values) => values.iter_mut().for_each(allow_includes),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn validation_errors(content: &str) -> Vec<String> {
+        let validator = jsonschema::validator_for(&manifest_schema()).expect("valid schema");
+        let instance: serde_json::Value = serde_yaml::from_str(content).expect("valid YAML");
+        validator
+            .iter_errors(&instance)
+            .map(|e| format!("{}: {}", e.instance_path, e))
+            .collect()
+    }
+
+    #[test]
+    fn test_sources_match_schema() {
+        let files = super::super::manifest::embedded_files();
+        assert!(!files.is_empty());
+
+        // The files as written, which editors see
+        for (path, content) in files {
+            let errors = validation_errors(&content);
+            assert!(
+                errors.is_empty(),
+                "{} does not match the schema:\n{}",
+                path.display(),
+                errors.join("\n")
+            );
+        }
+    }
+
+    #[test]
+    fn test_schema_rejects_invalid_manifest() {
+        let yaml = r#"
+source:
+  id: test
+  name: Test
+discovery:
+  steps:
+    - kind: Teleport
+      name: nowhere
+  outputs:
+    id: "x"
+content:
+  steps:
+    - kind: Fetch
+      name: api
+      url: "https://example.com"
+      extract:
+        token:
+          kind: jsonpth
+          path: "$.token"
+  outputs:
+    manifest_url: "x"
+"#;
+        let errors = validation_errors(yaml);
+        assert!(
+            errors.iter().any(|e| e.starts_with("/discovery/steps/0")),
+            "{:?}",
+            errors
+        );
+        assert!(
+            errors.iter().any(|e| e.starts_with("/content/steps/0")),
+            "{:?}",
+            errors
+        );
+    }
+
+    #[test]
+    fn test_schema_fragments() {
+        let content = |extends: &str| {
+            format!(
+                r#"
+{extends}
+source:
+  id: test
+  name: Test
+discovery:
+  steps:
+    - include: templates/list.yaml
+      params:
+        url: "https://example.com"
+  outputs:
+    id: "x"
+content:
+  steps: []
+"#
+            )
+        };
+
+        // Whatever the base provides may be left out
+        let errors = validation_errors(&content("extends: templates/base.yaml"));
+        assert!(errors.is_empty(), "{:?}", errors);
+
+        let errors = validation_errors(&content(""));
+        assert!(
+            errors.iter().any(|e| e.starts_with("/content")),
+            "{:?}",
+            errors
+        );
+
+        // Include items take nothing but `params`, and steps stay complete
+        let errors = validation_errors(
+            &content("extends: templates/base.yaml")
+                .replace("      params:", "      name: list\n      params:"),
+        );
+        assert!(
+            errors.iter().any(|e| e.starts_with("/discovery/steps/0")),
+            "{:?}",
+            errors
+        );
+        let errors = validation_errors(
+            &content("extends: templates/base.yaml")
+                .replace("  steps: []", "  steps:\n    - kind: Navigate"),
+        );
+        assert!(
+            errors.iter().any(|e| e.starts_with("/content/steps/0")),
+            "{:?}",
+            errors
+        );
+    }
+}
@@ -0,0 +1,182 @@
+use std::collections::HashMap;
+use std::path::Path;
+use std::sync::{LazyLock, RwLock};
+
+use anyhow::{Result, anyhow};
+
+use super::expression::url_encode;
+
+/**
+    Process-wide secret store, resolved by `${{secret.NAME}}` placeholders.
+*/
+static SECRETS: LazyLock<RwLock<HashMap<String, String>>> =
+    LazyLock::new(|| RwLock::new(HashMap::new()));
+
+pub const REDACTED: &str = "[REDACTED]";
+
+/**
+    Load secrets from a YAML file containing a flat `NAME: value` map.
+
+    Returns the number of secrets loaded. Previously loaded secrets with the
+    same names are replaced.
+*/
+pub fn load_file(path: &Path) -> Result<usize> {
+    let content = std::fs::read_to_string(path)
+        .map_err(|e| anyhow!("Failed to read secrets file {:?}: {}", path, e))?;
+
+    let values: HashMap<String, serde_yaml::Value> = serde_yaml::from_str(&content)
+        .map_err(|e| anyhow!("Failed to parse secrets file {:?}: {}", path, e))?;
+
+    let mut secrets = HashMap::with_capacity(values.len());
+    for (name, value) in values {
+        let value = match value {
+            serde_yaml::Value::String(s) => s,
+            serde_yaml::Value::Number(n) => n.to_string(),
+            serde_yaml::Value::Bool(b) => b.to_string(),
+            _ => {
+                return Err(anyhow!(
+                    "Secret '{}' in {:?} must be a string, number or bool",
+                    name,
+                    path
+                ));
+            }
+        };
+        secrets.insert(name, value);
+    }
+
+    let count = secrets.len();
+    SECRETS
+        .write()
+        .expect("secrets lock poisoned")
+        .extend(secrets);
+    Ok(count)
+}
+
+/**
+    Get a secret by name.
+*/
+pub fn get(name: &str) -> Option<String> {
+    SECRETS
+        .read()
+        .expect("secrets lock poisoned")
+        .get(name)
+        .cloned()
+}
+
+/**
+    Replace every secret value occurring in `text` with `[REDACTED]`.
+
+    Besides the literal value, the percent-encoded form (`| url_encode`) and
+    the form-encoded form (`query:` parameters) of each secret are replaced,
+    so secrets are also hidden inside URLs.
+*/
+pub fn redact(text: &str) -> String {
+    let secrets = SECRETS.read().expect("secrets lock poisoned");
+
+    let mut values: Vec<String> = secrets
+        .values()
+        .filter(|v| !v.is_empty())
+        .flat_map(|v| {
+            [
+                v.clone(),
+                url_encode(v),
+                url::form_urlencoded::byte_serialize(v.as_bytes()).collect(),
+            ]
+        })
+        .collect();
+
+    // Longest first, so a secret containing another is redacted whole
+    values.sort_by(|a, b| b.len().cmp(&a.len()).then_with(|| a.cmp(b)));
+    values.dedup();
+
+    let mut redacted = text.to_string();
+    for value in values {
+        if redacted.contains(value.as_str()) {
+            redacted = redacted.replace(value.as_str(), REDACTED);
+        }
+    }
+    redacted
+}
+
+/**
+    Redact secret values from every string inside a JSON value.
+*/
+pub fn redact_json(value: &mut serde_json::Value) {
+    match value {
+        serde_json::Value::String(s) => *s = redact(s),
+        serde_json::Value::Array(items) => items.iter_mut().for_each(redact_json),
+        serde_json::Value::Object(map) => map.values_mut().for_each(redact_json),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(content: &str) -> Result<usize> {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("secrets.yaml");
+        std::fs::write(&path, content).unwrap();
+        load_file(&path)
+    }
+
+    #[test]
+    fn test_load_file() {
+        let count = load("SECRETS_TEST_TOKEN: \"tok-7f3a\"\nSECRETS_TEST_PIN: 4821\n").unwrap();
+        assert_eq!(count, 2);
+        assert_eq!(get("SECRETS_TEST_TOKEN").as_deref(), Some("tok-7f3a"));
+        assert_eq!(get("SECRETS_TEST_PIN").as_deref(), Some("4821"));
+
+        for content in [
+            "SECRETS_TEST_LIST: [a, b]\n",
+            "SECRETS_TEST_MAP: { a: b }\n",
+            "SECRETS_TEST_NULL: ~\n",
+        ] {
+            let err = load(content).unwrap_err();
+            assert!(
+                err.to_string().contains("must be a string, number or bool"),
+                "{}",
+                err
+            );
+        }
+        assert!(load("[not, a, map]").is_err());
+        assert!(get("SECRETS_TEST_LIST").is_none());
+    }
+
+    #[test]
+    fn test_redact() {
+        load("SECRETS_TEST_SHORT: \"pa55-wd\"\nSECRETS_TEST_LONG: \"pa55-wd-extended\"\nSECRETS_TEST_SPECIAL: \"a b+c/d=e&f\"\n").unwrap();
+
+        assert_eq!(
+            redact("user=pa55-wd-extended&other=pa55-wd"),
+            "user=[REDACTED]&other=[REDACTED]"
+        );
+
+        // Literal, `| url_encode` and form-encoded `query:` forms
+        assert_eq!(redact("key=a b+c/d=e&f"), "key=[REDACTED]");
+        assert_eq!(
+            redact("https://example.com/?key=a%20b%2Bc%2Fd%3De%26f"),
+            "https://example.com/?key=[REDACTED]"
+        );
+        assert_eq!(
+            redact("https://example.com/?key=a+b%2Bc%2Fd%3De%26f"),
+            "https://example.com/?key=[REDACTED]"
+        );
+
+        let mut value = serde_json::json!({
+            "url": "https://example.com/?key=a+b%2Bc%2Fd%3De%26f",
+            "headers": [["authorization", "Bearer pa55-wd-extended"]],
+            "status": 200,
+        });
+        redact_json(&mut value);
+        assert_eq!(
+            value,
+            serde_json::json!({
+                "url": "https://example.com/?key=[REDACTED]",
+                "headers": [["authorization", "Bearer [REDACTED]"]],
+                "status": 200,
+            })
+        );
+    }
+}
@@ -1,13 +1,15 @@
 use std::collections::HashMap;
 
-use serde::{Deserialize, Serialize};
+use anyhow::{Result, anyhow};
+use schemars::JsonSchema;
+use serde::{Deserialize, Deserializer, Serialize};
 
 /**
     A step in a phase.
 
     Each variant carries only the fields it requires, validated at parse time.
 */
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(tag = "kind")]
 pub enum Step {
     /**
@@ -18,6 +20,10 @@ pub enum Step {
         url: String,
         #[serde(default)]
         wait_for: Option<WaitCondition>,
+        #[serde(default)]
+        retry: Option<RetryPolicy>,
+        #[serde(default)]
+        on_error: OnError,
     },
 
     /**
@@ -27,6 +33,10 @@ pub enum Step {
         name: String,
         request: RequestMatch,
         extract: HashMap<String, Extractor>,
+        #[serde(default)]
+        retry: Option<RetryPolicy>,
+        #[serde(default)]
+        on_error: OnError,
     },
 
     /**
@@ -36,6 +46,10 @@ pub enum Step {
         name: String,
         request: RequestMatch,
         extract: HashMap<String, Extractor>,
+        #[serde(default)]
+        retry: Option<RetryPolicy>,
+        #[serde(default)]
+        on_error: OnError,
     },
 
     /**
@@ -43,6 +57,12 @@ pub enum Step {
 
         When `urls` is provided, each URL is fetched and array results are
         merged — like `SniffMany` but with explicit URLs.
+
+        Responses with a non-2xx status fail the step, unless one of its
+        extractors reads the `status`.
+
+        With `paginate`, a single `url` is fetched page after page and the
+        array results of every page are merged.
     */
     Fetch {
         name: String,
@@ -51,8 +71,20 @@ pub enum Step {
         #[serde(default)]
         urls: Option<Vec<String>>,
         #[serde(default)]
+        method: Option<String>,
+        #[serde(default)]
+        query: HashMap<String, String>,
+        #[serde(default)]
         headers: HashMap<String, String>,
+        #[serde(default)]
+        body: Option<RequestBody>,
+        #[serde(default)]
+        paginate: Option<Box<Paginate>>,
         extract: HashMap<String, Extractor>,
+        #[serde(default)]
+        retry: Option<RetryPolicy>,
+        #[serde(default)]
+        on_error: OnError,
     },
 
     /**
@@ -61,7 +93,17 @@ pub enum Step {
     FetchInBrowser {
         name: String,
         url: String,
+        #[serde(default)]
+        method: Option<String>,
+        #[serde(default)]
+        query: HashMap<String, String>,
+        #[serde(default)]
+        body: Option<RequestBody>,
         extract: HashMap<String, Extractor>,
+        #[serde(default)]
+        retry: Option<RetryPolicy>,
+        #[serde(default)]
+        on_error: OnError,
     },
 
     /**
@@ -70,19 +112,75 @@ pub enum Step {
     Document {
         name: String,
         extract: HashMap<String, Extractor>,
+        #[serde(default)]
+        retry: Option<RetryPolicy>,
+        #[serde(default)]
+        on_error: OnError,
     },
 
     /**
         Execute custom JavaScript in page context.
     */
-    Script { name: String, script: String },
+    Script {
+        name: String,
+        script: String,
+        #[serde(default)]
+        retry: Option<RetryPolicy>,
+        #[serde(default)]
+        on_error: OnError,
+    },
 
     /**
-        Execute browser automation actions (clicks, etc.).
+        Execute browser automation actions (clicks, typing, frames, etc.).
     */
     Automation {
         name: String,
         steps: Vec<AutomationAction>,
+        #[serde(default)]
+        retry: Option<RetryPolicy>,
+        #[serde(default)]
+        on_error: OnError,
+    },
+
+    /**
+        Run one of two step lists depending on a condition.
+
+        Nested steps share the phase context, so outputs produced by whichever
+        branch ran are visible to the steps that follow.
+    */
+    #[serde(alias = "When")]
+    If {
+        name: String,
+        condition: Condition,
+        #[serde(default)]
+        then: Vec<Step>,
+        #[serde(default, rename = "else")]
+        otherwise: Vec<Step>,
+        #[serde(default)]
+        retry: Option<RetryPolicy>,
+        #[serde(default)]
+        on_error: OnError,
+    },
+
+    /**
+        Run nested steps once per item of an array output.
+
+        Each item's fields are bound under `as` (default `item`), so nested
+        steps can reference e.g. `${{item.id}}`. Scalar outputs of the nested
+        steps are merged into the item, and the enriched items replace the
+        original array. Nested steps must not produce outputs of the same
+        name.
+    */
+    ForEach {
+        name: String,
+        array: String,
+        #[serde(default, rename = "as")]
+        binding: Option<String>,
+        steps: Vec<Step>,
+        #[serde(default)]
+        retry: Option<RetryPolicy>,
+        #[serde(default)]
+        on_error: OnError,
     },
 }
 
@@ -99,15 +197,104 @@ impl Step {
             | Step::FetchInBrowser { name, .. }
             | Step::Document { name, .. }
             | Step::Script { name, .. }
-            | Step::Automation { name, .. } => name,
+            | Step::Automation { name, .. }
+            | Step::If { name, .. }
+            | Step::ForEach { name, .. } => name,
+        }
+    }
+
+    /**
+        Get the step kind, as written in manifests.
+    */
+    pub fn kind(&self) -> &'static str {
+        match self {
+            Step::Navigate { .. } => "Navigate",
+            Step::Sniff { .. } => "Sniff",
+            Step::SniffMany { .. } => "SniffMany",
+            Step::Fetch { .. } => "Fetch",
+            Step::FetchInBrowser { .. } => "FetchInBrowser",
+            Step::Document { .. } => "Document",
+            Step::Script { .. } => "Script",
+            Step::Automation { .. } => "Automation",
+            Step::If { .. } => "If",
+            Step::ForEach { .. } => "ForEach",
+        }
+    }
+
+    /**
+        Get the extractors of steps that extract values.
+    */
+    pub fn extractors(&self) -> Option<&HashMap<String, Extractor>> {
+        match self {
+            Step::Sniff { extract, .. }
+            | Step::SniffMany { extract, .. }
+            | Step::Fetch { extract, .. }
+            | Step::FetchInBrowser { extract, .. }
+            | Step::Document { extract, .. } => Some(extract),
+            _ => None,
+        }
+    }
+
+    /**
+        Get the retry policy, regardless of variant.
+    */
+    pub fn retry(&self) -> Option<&RetryPolicy> {
+        match self {
+            Step::Navigate { retry, .. }
+            | Step::Sniff { retry, .. }
+            | Step::SniffMany { retry, .. }
+            | Step::Fetch { retry, .. }
+            | Step::FetchInBrowser { retry, .. }
+            | Step::Document { retry, .. }
+            | Step::Script { retry, .. }
+            | Step::Automation { retry, .. }
+            | Step::If { retry, .. }
+            | Step::ForEach { retry, .. } => retry.as_ref(),
         }
     }
+
+    /**
+        Get the error policy, regardless of variant.
+    */
+    pub fn on_error(&self) -> &OnError {
+        match self {
+            Step::Navigate { on_error, .. }
+            | Step::Sniff { on_error, .. }
+            | Step::SniffMany { on_error, .. }
+            | Step::Fetch { on_error, .. }
+            | Step::FetchInBrowser { on_error, .. }
+            | Step::Document { on_error, .. }
+            | Step::Script { on_error, .. }
+            | Step::Automation { on_error, .. }
+            | Step::If { on_error, .. }
+            | Step::ForEach { on_error, .. } => on_error,
+        }
+    }
+
+    /**
+        Get the steps nested directly inside this step, including fallbacks.
+    */
+    pub fn nested_steps(&self) -> Vec<&Step> {
+        let mut nested: Vec<&Step> = match self {
+            Step::If {
+                then, otherwise, ..
+            } => then.iter().chain(otherwise).collect(),
+            Step::ForEach { steps, .. } => steps.iter().collect(),
+            _ => Vec::new(),
+        };
+
+        if let OnError::Fallback { fallback } = self.on_error() {
+            nested.extend(fallback);
+        }
+
+        nested
+    }
 }
 
 /**
     Wait condition after navigation or actions.
 */
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct WaitCondition {
     #[serde(default)]
     pub selector: Option<String>,
@@ -117,10 +304,208 @@ pub struct WaitCondition {
     pub delay: Option<f64>,
 }
 
+/**
+    Retry policy for a step.
+
+    The step runs up to `attempts` times in total. The delay before the n-th
+    retry is `backoff * 2^(n-1)` seconds, at most `MAX_RETRY_DELAY`.
+*/
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct RetryPolicy {
+    pub attempts: u32,
+    #[serde(default = "default_backoff", deserialize_with = "deserialize_backoff")]
+    #[schemars(range(min = 0))]
+    pub backoff: f64,
+}
+
+/// Longest delay between two attempts of a step
+pub const MAX_RETRY_DELAY: std::time::Duration = std::time::Duration::from_secs(60);
+
+impl RetryPolicy {
+    /**
+        Delay before the given retry (1-based).
+    */
+    pub fn delay(&self, retry: u32) -> std::time::Duration {
+        // Past 2^63 the delay is capped anyway; keeps the factor finite
+        let exponent = retry.saturating_sub(1).min(63) as i32;
+        let seconds = self.backoff * 2f64.powi(exponent);
+        std::time::Duration::try_from_secs_f64(seconds)
+            .map_or(MAX_RETRY_DELAY, |delay| delay.min(MAX_RETRY_DELAY))
+    }
+}
+
+fn default_backoff() -> f64 {
+    1.0
+}
+
+fn deserialize_backoff<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    let backoff = f64::deserialize(deserializer)?;
+    if backoff.is_finite() && backoff >= 0.0 {
+        Ok(backoff)
+    } else {
+        Err(serde::de::Error::custom(format!(
+            "backoff must be a non-negative number of seconds, got {}",
+            backoff
+        )))
+    }
+}
+
+/**
+    What to do when a step fails after all retries.
+
+    Written as `on_error: fail`, `on_error: continue`, or
+    `on_error: { fallback: [steps...] }`.
+*/
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum OnError {
+    Policy(ErrorPolicy),
+    Fallback { fallback: Vec<Step> },
+}
+
+impl Default for OnError {
+    fn default() -> Self {
+        OnError::Policy(ErrorPolicy::Fail)
+    }
+}
+
+/**
+    Simple error policies for `on_error`.
+*/
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ErrorPolicy {
+    /// Abort the phase with the step's error (default).
+    Fail,
+    /// Log the error and carry on with the next step.
+    Continue,
+}
+
+/**
+    Condition for `If` steps.
+
+    Every check that is set must pass; `not` inverts the combined result.
+    A `value` template referencing an undefined variable counts as false;
+    other interpolation errors, like an unknown filter, fail the step.
+    `equals` and `matches` require a `value`.
+*/
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub struct Condition {
+    /// An element matching this CSS selector exists on the current page.
+    #[serde(default)]
+    pub selector: Option<String>,
+    /// This JavaScript expression evaluates to a truthy value.
+    #[serde(default)]
+    pub function: Option<String>,
+    /// This template interpolates to a non-empty string.
+    #[serde(default)]
+    pub value: Option<String>,
+    /// The interpolated `value` equals this string.
+    #[serde(default)]
+    pub equals: Option<String>,
+    /// The interpolated `value` matches this regex.
+    #[serde(default)]
+    pub matches: Option<String>,
+    /// The array output with this name has at least one item.
+    #[serde(default)]
+    pub array: Option<String>,
+    #[serde(default)]
+    pub not: bool,
+}
+
+impl Condition {
+    /**
+        Check that the condition tests something, and that `equals` and
+        `matches` have a `value` to compare.
+    */
+    pub fn check(&self) -> Result<()> {
+        if self.value.is_none() {
+            for (field, set) in [
+                ("equals", self.equals.is_some()),
+                ("matches", self.matches.is_some()),
+            ] {
+                if set {
+                    return Err(anyhow!("Condition '{}' requires a 'value'", field));
+                }
+            }
+        }
+        if self.selector.is_none()
+            && self.function.is_none()
+            && self.value.is_none()
+            && self.array.is_none()
+        {
+            return Err(anyhow!(
+                "Condition needs at least one of 'selector', 'function', 'value' or 'array'"
+            ));
+        }
+        Ok(())
+    }
+}
+
+/**
+    Request body for `Fetch`/`FetchInBrowser` steps.
+
+    Written as `body: { raw: "...", content_type: "..." }`, `body: { json: ... }`
+    or `body: { form: { key: value } }`. Every string in it is interpolated.
+*/
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum RequestBody {
+    Raw {
+        raw: String,
+        #[serde(default)]
+        content_type: Option<String>,
+    },
+    Json {
+        json: serde_json::Value,
+    },
+    Form {
+        form: HashMap<String, String>,
+    },
+}
+
+/**
+    Pagination for `Fetch` steps.
+
+    Every request sees `${{page.number}}` (counting from `start`) and the
+    values the `extract` block found on the previous page as `${{page.NAME}}`,
+    e.g. a cursor for the next request's query or body. When `next` is set,
+    the URL it extracts (absolute or relative) is fetched as the next page.
+
+    Paging stops at the first page without items, after `max_pages` (100 if
+    not set), when `next` finds an empty URL, when `stop_when` holds after a
+    page, or when a page repeats the previous one. An `array` condition in
+    `stop_when` sees only the items of the page just fetched. With
+    `skip_empty`, pages without items are passed over instead, e.g. schedule
+    days with no programmes.
+
+    Errors from `next` and `extract` fail the step like any other extraction
+    error. Give `next` a `default: ""` when the last page has no link.
+*/
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct Paginate {
+    #[serde(default)]
+    pub next: Option<Extractor>,
+    #[serde(default)]
+    pub extract: HashMap<String, Extractor>,
+    #[serde(default = "default_page_start")]
+    pub start: u64,
+    #[serde(default)]
+    pub max_pages: Option<u32>,
+    #[serde(default)]
+    pub stop_when: Option<Condition>,
+    #[serde(default)]
+    pub skip_empty: bool,
+}
+
+fn default_page_start() -> u64 {
+    1
+}
+
 /**
     Request matching criteria for Sniff/SniffMany steps.
 */
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct RequestMatch {
     pub url: String,
     #[serde(default)]
@@ -134,7 +519,7 @@ pub struct RequestMatch {
 /**
     An automation action within an Automation step.
 */
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 #[serde(tag = "kind")]
 pub enum AutomationAction {
     Click {
@@ -147,12 +532,93 @@ pub enum AutomationAction {
         #[serde(default)]
         wait_for: Option<WaitCondition>,
     },
+
+    /**
+        Type text into an input, replacing its value unless `append` is set.
+    */
+    Type {
+        selector: String,
+        text: String,
+        #[serde(default)]
+        append: bool,
+        #[serde(default)]
+        wait_for: Option<WaitCondition>,
+    },
+
+    /**
+        Press a key (`Enter`, `Escape`, `a`, ...) on an element, or on the
+        focused element if no selector is given.
+    */
+    PressKey {
+        key: String,
+        #[serde(default)]
+        selector: Option<String>,
+        #[serde(default)]
+        wait_for: Option<WaitCondition>,
+    },
+
+    /**
+        Move the mouse over an element.
+    */
+    Hover {
+        selector: String,
+        #[serde(default)]
+        wait_for: Option<WaitCondition>,
+    },
+
+    /**
+        Scroll an element into view, or scroll the page by an `x`/`y` offset.
+        With both, the offset is applied after the element is in view.
+    */
+    Scroll {
+        #[serde(default)]
+        selector: Option<String>,
+        #[serde(default)]
+        x: Option<f64>,
+        #[serde(default)]
+        y: Option<f64>,
+        #[serde(default)]
+        wait_for: Option<WaitCondition>,
+    },
+
+    /**
+        Choose an option of a `<select>` by its `value` or visible `label`.
+    */
+    SelectOption {
+        selector: String,
+        #[serde(default)]
+        value: Option<String>,
+        #[serde(default)]
+        label: Option<String>,
+        #[serde(default)]
+        wait_for: Option<WaitCondition>,
+    },
+
+    /**
+        Wait for a selector, function and/or delay before the next action.
+    */
+    WaitFor {
+        #[serde(flatten)]
+        condition: WaitCondition,
+    },
+
+    /**
+        Run the following actions inside the iframe matching `selector`
+        (relative to the current frame), or back in the top-level page when
+        no selector is given. Only same-origin frames can be entered.
+    */
+    SwitchFrame {
+        #[serde(default)]
+        selector: Option<String>,
+        #[serde(default)]
+        wait_for: Option<WaitCondition>,
+    },
 }
 
 /**
     An extractor that pulls data from a response.
 */
-#[derive(Debug, Clone, Deserialize, Serialize)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
 pub struct Extractor {
     pub kind: ExtractorKind,
     #[serde(default)]
@@ -161,19 +627,89 @@ pub struct Extractor {
     pub default: Option<String>,
     #[serde(default)]
     pub regex: Option<String>,
+    /// CSS selector of the `<script>` element holding the JSON (`script_json`)
+    #[serde(default)]
+    pub selector: Option<String>,
+    /// Name the JSON is assigned to in a script, e.g. `window.__INITIAL_STATE__` (`script_json`)
+    #[serde(default)]
+    pub variable: Option<String>,
     #[serde(default)]
     pub each: Option<HashMap<String, String>>,
     #[serde(default)]
     pub unescape: bool,
+    /// Transforms applied to the extracted value, or per field for array extractors
+    #[serde(default)]
+    pub transform: Option<Transforms>,
+}
+
+/**
+    Transform pipelines of an extractor: a list for a scalar extractor, or a
+    map from field name to list for an array extractor.
+*/
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum Transforms {
+    Value(Vec<ValueTransform>),
+    Fields(HashMap<String, Vec<ValueTransform>>),
+}
+
+/**
+    A transform applied to an extracted string, e.g. `trim` or
+    `parse_date: { format: "%d/%m/%Y %H:%M", tz: "Europe/Madrid" }`.
+*/
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+#[serde(untagged)]
+pub enum ValueTransform {
+    Simple(SimpleTransform),
+    ParseDate { parse_date: ParseDate },
+    Split { split: Split },
+}
+
+#[derive(Debug, Clone, Copy, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum SimpleTransform {
+    /// Strip leading and trailing whitespace
+    Trim,
+    /// Resolve a relative URL against the URL the value was extracted from
+    AbsoluteUrl,
+    /// Parse a number and truncate it to an integer
+    ToInt,
+}
+
+/**
+    Parse a date and time into an RFC 3339 UTC timestamp.
+
+    Without a `format`, the value must already be RFC 3339 or an epoch
+    timestamp. `tz` is the IANA time zone of values without an offset
+    (default UTC).
+*/
+#[derive(Debug, Clone, Default, Deserialize, Serialize, JsonSchema)]
+pub struct ParseDate {
+    #[serde(default)]
+    pub format: Option<String>,
+    #[serde(default)]
+    pub tz: Option<String>,
+}
+
+/**
+    Split the value on `separator` and keep the part at `index` (negative
+    indices count from the end).
+*/
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema)]
+pub struct Split {
+    pub separator: String,
+    #[serde(default)]
+    pub index: i64,
 }
 
 /**
     The kind of extractor.
 */
-#[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Deserialize, Serialize, JsonSchema, PartialEq, Eq)]
 #[serde(rename_all = "lowercase")]
 pub enum ExtractorKind {
     Url,
+    Status,
     #[serde(rename = "url_regex")]
     UrlRegex,
     Header,
@@ -195,6 +731,14 @@ pub enum ExtractorKind {
     RegexArray,
     Line,
     Pssh,
+    #[serde(rename = "script_json")]
+    ScriptJson,
+    #[serde(rename = "script_json_array")]
+    ScriptJsonArray,
+    #[serde(rename = "hls_variants")]
+    HlsVariants,
+    #[serde(rename = "dash_representations")]
+    DashRepresentations,
 }
 
 /**
@@ -207,5 +751,19 @@ pub fn is_array_extractor(kind: &ExtractorKind) -> bool {
             | ExtractorKind::CssArray
             | ExtractorKind::XPathArray
             | ExtractorKind::RegexArray
+            | ExtractorKind::ScriptJsonArray
+            | ExtractorKind::HlsVariants
+            | ExtractorKind::DashRepresentations
+    )
+}
+
+/**
+    Check if an extractor kind parses a streaming manifest into renditions.
+    These have a fixed set of fields, so they take no `each`.
+*/
+pub fn is_playlist_extractor(kind: &ExtractorKind) -> bool {
+    matches!(
+        kind,
+        ExtractorKind::HlsVariants | ExtractorKind::DashRepresentations
     )
 }
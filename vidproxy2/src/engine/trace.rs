@@ -0,0 +1,300 @@
+use std::fmt;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use serde::Serialize;
+
+use super::secrets::redact;
+use super::step::Step;
+
+/// Longest extracted value kept in a trace, in characters
+const MAX_VALUE_CHARS: usize = 120;
+
+/// Request URLs kept per step; further requests are only counted
+const MAX_REQUESTS: usize = 10;
+
+/**
+    Timings and outcomes of the steps a phase ran, in the order they
+    started. Values, URLs and errors have secrets redacted.
+*/
+#[derive(Debug, Clone, Serialize)]
+pub struct PhaseTrace {
+    pub phase: String,
+    /// Unix timestamp of the start of the phase
+    pub started_at: i64,
+    pub duration_ms: u64,
+    pub steps: Vec<StepSpan>,
+}
+
+/**
+    One run of a step, including its retries.
+*/
+#[derive(Debug, Clone, Serialize)]
+pub struct StepSpan {
+    pub name: String,
+    pub kind: &'static str,
+    /// Nesting depth under `If`, `ForEach` and fallback steps
+    pub depth: usize,
+    /// Start of the step, relative to the start of the phase
+    pub start_ms: u64,
+    /// `None` while the step is still running
+    pub duration_ms: Option<u64>,
+    pub attempts: u32,
+    pub status: SpanStatus,
+    pub error: Option<String>,
+    /// Time a `Navigate` step spent on its `wait_for` condition
+    pub wait_ms: Option<u64>,
+    /// URLs the step navigated to, matched or fetched
+    pub requests: Vec<String>,
+    /// Number of requests, including those beyond `requests`
+    pub request_count: usize,
+    pub extractors: Vec<ExtractorOutcome>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SpanStatus {
+    Running,
+    Ok,
+    Failed,
+}
+
+/**
+    Whether an extractor produced a value, and the value.
+*/
+#[derive(Debug, Clone, Serialize)]
+pub struct ExtractorOutcome {
+    pub name: String,
+    pub hit: bool,
+    /// The extracted value, or the item count of an array extractor
+    pub value: Option<String>,
+}
+
+impl ExtractorOutcome {
+    pub fn hit(name: &str, value: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            hit: !value.is_empty(),
+            value: Some(truncate(&redact(value))),
+        }
+    }
+
+    pub fn items(name: &str, count: usize) -> Self {
+        Self {
+            name: name.to_string(),
+            hit: count > 0,
+            value: Some(format!("{} item(s)", count)),
+        }
+    }
+
+    pub fn miss(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            hit: false,
+            value: None,
+        }
+    }
+}
+
+/**
+    Collects the spans of a phase as its steps run.
+
+    Steps run one at a time, so requests and waits are attributed to the
+    innermost step that is still running.
+*/
+pub struct Tracer {
+    phase: String,
+    started_at: i64,
+    start: Instant,
+    state: Mutex<TracerState>,
+}
+
+#[derive(Default)]
+struct TracerState {
+    spans: Vec<StepSpan>,
+    /// Indices of the spans still running, innermost last
+    open: Vec<usize>,
+}
+
+impl Tracer {
+    pub fn new(phase: &str) -> Self {
+        Self {
+            phase: phase.to_string(),
+            started_at: crate::util::time::now().timestamp(),
+            start: Instant::now(),
+            state: Mutex::new(TracerState::default()),
+        }
+    }
+
+    /**
+        Open a span for a step, returning its index for `finish`.
+    */
+    pub(super) fn begin(&self, step: &Step) -> usize {
+        let mut state = self.state.lock().unwrap();
+        let span = StepSpan {
+            name: step.name().to_string(),
+            kind: step.kind(),
+            depth: state.open.len(),
+            start_ms: millis(self.start.elapsed()),
+            duration_ms: None,
+            attempts: 0,
+            status: SpanStatus::Running,
+            error: None,
+            wait_ms: None,
+            requests: Vec::new(),
+            request_count: 0,
+            extractors: Vec::new(),
+        };
+        state.spans.push(span);
+        let index = state.spans.len() - 1;
+        state.open.push(index);
+        index
+    }
+
+    /**
+        Close a span with the step's extractor outcomes, and its error if
+        it failed.
+    */
+    pub(super) fn finish(
+        &self,
+        index: usize,
+        extractors: Vec<ExtractorOutcome>,
+        error: Option<String>,
+    ) {
+        let elapsed = millis(self.start.elapsed());
+        let mut state = self.state.lock().unwrap();
+        state.open.retain(|&open| open != index);
+        let Some(span) = state.spans.get_mut(index) else {
+            return;
+        };
+        span.duration_ms = Some(elapsed.saturating_sub(span.start_ms));
+        span.extractors = extractors;
+        span.status = match error {
+            Some(error) => {
+                span.error = Some(redact(&error));
+                SpanStatus::Failed
+            }
+            None => SpanStatus::Ok,
+        };
+    }
+
+    pub(super) fn attempt(&self) {
+        self.with_current(|span| span.attempts += 1);
+    }
+
+    pub(super) fn request(&self, url: &str) {
+        self.with_current(|span| {
+            span.request_count += 1;
+            if span.requests.len() < MAX_REQUESTS {
+                span.requests.push(redact(url));
+            }
+        });
+    }
+
+    pub(super) fn waited(&self, duration: Duration) {
+        self.with_current(|span| {
+            span.wait_ms = Some(span.wait_ms.unwrap_or(0) + millis(duration));
+        });
+    }
+
+    fn with_current(&self, f: impl FnOnce(&mut StepSpan)) {
+        let mut state = self.state.lock().unwrap();
+        if let Some(&index) = state.open.last() {
+            f(&mut state.spans[index]);
+        }
+    }
+
+    /**
+        The spans recorded so far. Spans of steps still running have no
+        duration.
+    */
+    pub fn trace(&self) -> PhaseTrace {
+        PhaseTrace {
+            phase: self.phase.clone(),
+            started_at: self.started_at,
+            duration_ms: millis(self.start.elapsed()),
+            steps: self.state.lock().unwrap().spans.clone(),
+        }
+    }
+}
+
+fn millis(duration: Duration) -> u64 {
+    duration.as_millis().try_into().unwrap_or(u64::MAX)
+}
+
+fn truncate(value: &str) -> String {
+    match value.char_indices().nth(MAX_VALUE_CHARS) {
+        Some((end, _)) => format!("{}...", &value[..end]),
+        None => value.to_string(),
+    }
+}
+
+fn seconds(ms: u64) -> String {
+    format!("{:.1}s", ms as f64 / 1000.0)
+}
+
+/**
+    A table of the spans, one row per step followed by its requests and
+    extractor outcomes.
+*/
+impl fmt::Display for PhaseTrace {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(
+            f,
+            "  {:<28} {:<15} {:>8} {:>9}  Result",
+            "Step", "Kind", "Start", "Duration"
+        )?;
+
+        for span in &self.steps {
+            let indent = "  ".repeat(span.depth);
+            let duration = span.duration_ms.map(seconds).unwrap_or_else(|| "-".into());
+
+            let mut result = match span.status {
+                SpanStatus::Running => "running".to_string(),
+                SpanStatus::Ok => "ok".to_string(),
+                SpanStatus::Failed => "FAILED".to_string(),
+            };
+            if span.attempts > 1 {
+                result.push_str(&format!(" after {} attempts", span.attempts));
+            }
+            if let Some(wait) = span.wait_ms {
+                result.push_str(&format!(", waited {}", seconds(wait)));
+            }
+            if let Some(error) = &span.error {
+                result.push_str(&format!(": {}", error));
+            }
+
+            writeln!(
+                f,
+                "  {:<28} {:<15} {:>8} {:>9}  {}",
+                format!("{}{}", indent, span.name),
+                span.kind,
+                seconds(span.start_ms),
+                duration,
+                result
+            )?;
+
+            for url in &span.requests {
+                writeln!(f, "  {}    -> {}", indent, url)?;
+            }
+            if span.request_count > span.requests.len() {
+                writeln!(
+                    f,
+                    "  {}    -> ... and {} more",
+                    indent,
+                    span.request_count - span.requests.len()
+                )?;
+            }
+            for extractor in &span.extractors {
+                match (&extractor.value, extractor.hit) {
+                    (Some(value), true) => {
+                        writeln!(f, "  {}    {} = {}", indent, extractor.name, value)?
+                    }
+                    _ => writeln!(f, "  {}    {}: miss", indent, extractor.name)?,
+                }
+            }
+        }
+
+        write!(f, "  Total {}", seconds(self.duration_ms))
+    }
+}
@@ -0,0 +1,242 @@
+use std::collections::HashSet;
+
+use anyhow::{Result, anyhow};
+use chrono::{DateTime, NaiveDate, NaiveDateTime, Utc};
+use chrono_tz::Tz;
+
+use super::extractor::ExtractedArray;
+use super::step::{ParseDate, SimpleTransform, Split, Transforms, ValueTransform};
+
+/**
+    Run a transform pipeline on an extracted value. `url` is the address
+    the value was extracted from, for `absolute_url`.
+*/
+pub fn apply_transforms(transforms: &[ValueTransform], value: &str, url: &str) -> Result<String> {
+    transforms
+        .iter()
+        .try_fold(value.to_string(), |value, transform| {
+            apply_transform(transform, &value, url)
+        })
+}
+
+/**
+    Run the per-field pipelines of an array extractor on every item. A field
+    whose pipeline fails is left empty, so one malformed row does not drop
+    the whole array.
+*/
+pub fn transform_items(transforms: Option<&Transforms>, items: &mut ExtractedArray, url: &str) {
+    let Some(Transforms::Fields(fields)) = transforms else {
+        return;
+    };
+
+    for item in items.iter_mut() {
+        for (field, pipeline) in fields {
+            let Some(slot) = item.get_mut(field) else {
+                continue;
+            };
+            if let Some(value) = slot.as_deref() {
+                *slot = apply_transforms(pipeline, value, url).ok();
+            }
+        }
+    }
+}
+
+fn apply_transform(transform: &ValueTransform, value: &str, url: &str) -> Result<String> {
+    match transform {
+        ValueTransform::Simple(SimpleTransform::Trim) => Ok(value.trim().to_string()),
+        ValueTransform::Simple(SimpleTransform::AbsoluteUrl) => absolute_url(value, url),
+        ValueTransform::Simple(SimpleTransform::ToInt) => to_int(value),
+        ValueTransform::ParseDate { parse_date } => parse_date_value(parse_date, value),
+        ValueTransform::Split { split } => split_value(split, value),
+    }
+}
+
+fn absolute_url(value: &str, url: &str) -> Result<String> {
+    let value = value.trim();
+    if let Ok(absolute) = url::Url::parse(value) {
+        return Ok(absolute.to_string());
+    }
+    let base =
+        url::Url::parse(url).map_err(|_| anyhow!("Cannot resolve '{}': no base URL", value))?;
+    base.join(value)
+        .map(|u| u.to_string())
+        .map_err(|e| anyhow!("Cannot resolve '{}' against {}: {}", value, url, e))
+}
+
+fn to_int(value: &str) -> Result<String> {
+    let trimmed = value.trim();
+    if let Ok(n) = trimmed.parse::<i64>() {
+        return Ok(n.to_string());
+    }
+    trimmed
+        .parse::<f64>()
+        .ok()
+        .filter(|n| n.is_finite())
+        .map(|n| (n.trunc() as i64).to_string())
+        .ok_or_else(|| anyhow!("'{}' is not a number", trimmed))
+}
+
+fn split_value(split: &Split, value: &str) -> Result<String> {
+    let parts: Vec<&str> = value.split(split.separator.as_str()).collect();
+    let index = if split.index < 0 {
+        parts.len() as i64 + split.index
+    } else {
+        split.index
+    };
+    usize::try_from(index)
+        .ok()
+        .and_then(|i| parts.get(i))
+        .map(|part| part.to_string())
+        .ok_or_else(|| {
+            anyhow!(
+                "'{}' has no part {} when split on '{}'",
+                value,
+                split.index,
+                split.separator
+            )
+        })
+}
+
+fn parse_date_value(parse_date: &ParseDate, value: &str) -> Result<String> {
+    let value = value.trim();
+    let Some(format) = &parse_date.format else {
+        return crate::util::time::parse_timestamp(value)
+            .map(|dt| dt.to_rfc3339())
+            .ok_or_else(|| anyhow!("'{}' is not an RFC 3339 or epoch timestamp", value));
+    };
+
+    // Formats with an offset (%z, %:z) carry their own zone
+    if let Ok(dt) = DateTime::parse_from_str(value, format) {
+        return Ok(dt.with_timezone(&Utc).to_rfc3339());
+    }
+
+    let naive = NaiveDateTime::parse_from_str(value, format)
+        .or_else(|_| {
+            NaiveDate::parse_from_str(value, format).map(|d| d.and_hms_opt(0, 0, 0).unwrap())
+        })
+        .map_err(|e| anyhow!("'{}' does not match format '{}': {}", value, format, e))?;
+
+    let tz = parse_date.tz.as_deref().map(parse_tz).transpose()?;
+    crate::util::time::local_to_utc(naive, tz)
+        .map(|dt| dt.to_rfc3339())
+        .ok_or_else(|| anyhow!("{} does not exist in the time zone", naive))
+}
+
+/**
+    Parse an IANA time zone name, or fail with a readable error.
+*/
+pub fn parse_tz(name: &str) -> Result<Tz> {
+    crate::util::time::parse_tz(name).ok_or_else(|| anyhow!("Unknown time zone '{}'", name))
+}
+
+/**
+    Statically check an extractor's transforms. `fields` are the fields an
+    array extractor produces, or `None` for a scalar extractor.
+*/
+pub fn check_transforms(transforms: &Transforms, fields: Option<&HashSet<String>>) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    let pipelines: Vec<&Vec<ValueTransform>> = match (transforms, fields) {
+        (Transforms::Value(pipeline), None) => vec![pipeline],
+        (Transforms::Fields(map), Some(fields)) => {
+            let mut names: Vec<&String> = map.keys().collect();
+            names.sort();
+            for name in names {
+                if !fields.contains(name) {
+                    errors.push(format!("transform for unknown field '{}'", name));
+                }
+            }
+            map.values().collect()
+        }
+        (Transforms::Value(_), Some(_)) => {
+            errors.push("array extractors take a map of field name to transforms".to_string());
+            return errors;
+        }
+        (Transforms::Fields(_), None) => {
+            errors.push("scalar extractors take a list of transforms".to_string());
+            return errors;
+        }
+    };
+
+    for transform in pipelines.into_iter().flatten() {
+        match transform {
+            ValueTransform::ParseDate { parse_date } => {
+                if let Some(tz) = &parse_date.tz
+                    && let Err(e) = parse_tz(tz)
+                {
+                    errors.push(e.to_string());
+                }
+                if parse_date.format.as_deref().is_some_and(str::is_empty) {
+                    errors.push("parse_date 'format' is empty".to_string());
+                }
+            }
+            ValueTransform::Split { split } if split.separator.is_empty() => {
+                errors.push("split 'separator' is empty".to_string());
+            }
+            _ => {}
+        }
+    }
+
+    errors
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn pipeline(yaml: &str) -> Vec<ValueTransform> {
+        serde_yaml::from_str(yaml).unwrap()
+    }
+
+    #[test]
+    fn test_transform_pipeline() {
+        let url = "https://example.com/guide/today.html";
+        let transforms = pipeline(
+            r#"
+- trim
+- split: { separator: "|", index: -1 }
+- trim
+- absolute_url
+"#,
+        );
+        assert_eq!(
+            apply_transforms(&transforms, "  Show | ../img/show.jpg ", url).unwrap(),
+            "https://example.com/img/show.jpg"
+        );
+
+        let transforms = pipeline("[to_int]");
+        assert_eq!(apply_transforms(&transforms, " 45.9 ", url).unwrap(), "45");
+        assert!(apply_transforms(&transforms, "45 min", url).is_err());
+    }
+
+    #[test]
+    fn test_parse_date() {
+        let transforms = pipeline(
+            r#"
+- parse_date: { format: "%d/%m/%Y %H:%M", tz: "America/Bogota" }
+"#,
+        );
+        assert_eq!(
+            apply_transforms(&transforms, "08/02/2026 21:30", "").unwrap(),
+            "2026-02-09T02:30:00+00:00"
+        );
+
+        // Summer time
+        let transforms =
+            pipeline(r#"[{ parse_date: { format: "%Y-%m-%d %H:%M", tz: "Europe/Madrid" } }]"#);
+        assert_eq!(
+            apply_transforms(&transforms, "2026-07-01 12:00", "").unwrap(),
+            "2026-07-01T10:00:00+00:00"
+        );
+
+        let transforms = pipeline("[{ parse_date: {} }]");
+        assert_eq!(
+            apply_transforms(&transforms, "1770526800", "").unwrap(),
+            "2026-02-08T05:00:00+00:00"
+        );
+
+        let invalid: Transforms =
+            serde_yaml::from_str(r#"[{ parse_date: { tz: "Mars/Olympus" } }]"#).unwrap();
+        assert_eq!(check_transforms(&invalid, None).len(), 1);
+    }
+}
@@ -0,0 +1,1291 @@
+use std::borrow::Cow;
+use std::collections::{HashMap, HashSet};
+
+use regex::Regex;
+
+use super::browser::step_needs_page;
+use super::expression::{self, PathSegment};
+use super::extractor::check_extractor;
+use super::fragments::{Fragments, check_fragment, expand, is_fragment, used_fragments};
+use super::interpolate::placeholder_regex;
+use super::manifest::{BrowserMode, Manifest};
+use super::step::{
+    AutomationAction, Condition, Extractor, OnError, Paginate, RequestBody, RequestMatch, Step,
+};
+
+/**
+    A problem found in a manifest, positioned in its YAML source (1-based).
+*/
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub severity: Severity,
+    pub line: usize,
+    pub column: usize,
+    pub message: String,
+    /// The base or fragment the position is in, if not the file itself
+    pub fragment: Option<String>,
+}
+
+/**
+    Errors break the manifest at runtime; warnings flag likely mistakes.
+*/
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let severity = match self.severity {
+            Severity::Error => "error",
+            Severity::Warning => "warning",
+        };
+        write!(
+            f,
+            "{}:{}: {}: {}",
+            self.line, self.column, severity, self.message
+        )
+    }
+}
+
+/**
+    Validate a manifest file, expanding its `extends:` and `include:`
+    references first.
+
+    Positions are mapped back from the expanded manifest to the line of the
+    file, or of the base or fragment, it came from. Fragment and base files
+    themselves are only checked for their shape, as their parameters are
+    only known where they are used.
+*/
+pub fn validate_file(content: &str, fragments: &Fragments) -> Vec<Diagnostic> {
+    if is_fragment(content) {
+        return validate_fragment(content);
+    }
+
+    let expanded = match expand(content, fragments) {
+        Ok(Cow::Owned(expanded)) => expanded,
+        Ok(Cow::Borrowed(_)) => return validate(content),
+        Err(e) => {
+            return vec![Diagnostic {
+                severity: Severity::Error,
+                line: 1,
+                column: 1,
+                message: e.to_string(),
+                fragment: None,
+            }];
+        }
+    };
+
+    let mut sources = vec![(None, content.to_string())];
+    sources.extend(
+        used_fragments(content, fragments)
+            .into_iter()
+            .map(|(name, text)| (Some(name), text)),
+    );
+    validate(&expanded)
+        .into_iter()
+        .map(|diagnostic| relocate(diagnostic, &expanded, &sources))
+        .collect()
+}
+
+fn validate_fragment(content: &str) -> Vec<Diagnostic> {
+    let value: serde_yaml::Value = match serde_yaml::from_str(content) {
+        Ok(value) => value,
+        // Reported with its position by the manifest parser
+        Err(_) => return validate(content),
+    };
+    match check_fragment(&value) {
+        Ok(()) => Vec::new(),
+        Err(e) => vec![Diagnostic {
+            severity: Severity::Error,
+            line: 1,
+            column: 1,
+            message: e.to_string(),
+            fragment: None,
+        }],
+    }
+}
+
+/**
+    Move a diagnostic of an expanded manifest to the same line in the file
+    or fragment it came from, found by comparing `key: value` lines parsed
+    on their own within the same top-level section. Lines that can't be
+    found (e.g. with substituted parameters) fall back to the closest line
+    above them that can.
+*/
+fn relocate(
+    mut diagnostic: Diagnostic,
+    expanded: &str,
+    sources: &[(Option<String>, String)],
+) -> Diagnostic {
+    let lines: Vec<&str> = expanded.lines().collect();
+    let last = diagnostic
+        .line
+        .saturating_sub(1)
+        .min(lines.len().saturating_sub(1));
+
+    for index in (0..=last).rev() {
+        let Some(line) = normalize_line(lines[index]) else {
+            continue;
+        };
+        let section = section_of(&lines, index);
+        // Which of the identical lines of the section this is
+        let occurrence = (0..index)
+            .filter(|&i| section_of(&lines, i) == section)
+            .filter(|&i| normalize_line(lines[i]).as_ref() == Some(&line))
+            .count();
+
+        for (fragment, text) in sources {
+            let source_lines: Vec<&str> = text.lines().collect();
+            let matches: Vec<usize> = (0..source_lines.len())
+                .filter(|&i| fragment.is_some() || section_of(&source_lines, i) == section)
+                .filter(|&i| normalize_line(source_lines[i]).as_ref() == Some(&line))
+                .collect();
+            let Some(&found) = matches.get(occurrence).or(matches.last()) else {
+                continue;
+            };
+
+            let source_line = source_lines[found];
+            let offset = if index == last {
+                diagnostic.column.saturating_sub(indent(lines[index]))
+            } else {
+                0
+            };
+            diagnostic.line = found + 1;
+            diagnostic.column = (indent(source_line) + offset).min(source_line.chars().count() + 1);
+            diagnostic.fragment = fragment.clone();
+            return diagnostic;
+        }
+    }
+
+    diagnostic.line = 1;
+    diagnostic.column = 1;
+    diagnostic
+}
+
+/**
+    A YAML line parsed on its own, so list markers, quoting and comments
+    don't matter.
+*/
+fn normalize_line(line: &str) -> Option<serde_yaml::Value> {
+    let mut trimmed = line.trim();
+    while let Some(rest) = trimmed.strip_prefix("- ") {
+        trimmed = rest.trim_start();
+    }
+    if trimmed.is_empty() || trimmed.starts_with('#') {
+        return None;
+    }
+    serde_yaml::from_str(trimmed).ok()
+}
+
+/**
+    The top-level key a line belongs to.
+*/
+fn section_of<'l>(lines: &[&'l str], index: usize) -> Option<&'l str> {
+    lines[..=index]
+        .iter()
+        .rev()
+        .find(|line| !line.starts_with([' ', '\t', '-', '#']) && line.contains(':'))
+        .and_then(|line| line.split(':').next())
+        .map(|key| key.trim_matches(['"', '\'']))
+}
+
+/**
+    1-based column of the first character after indentation and list
+    markers.
+*/
+fn indent(line: &str) -> usize {
+    let trimmed = line.trim_start_matches([' ', '\t', '-']);
+    line[..line.len() - trimmed.len()].chars().count() + 1
+}
+
+/**
+    Statically validate a manifest without running it.
+
+    Checks, in addition to parsing:
+    - `${{...}}` references name a step that ran earlier in the same phase
+      (or `channel`, `env`, `secret`, a `ForEach` binding) and one of its outputs
+    - expressions parse and only use known filters
+    - extractors have their required fields and their regexes, JSONPaths,
+      XPaths and CSS selectors compile
+    - array extractors are not mixed with other extractors in one step (only
+      the array would be produced); in `Sniff` they only see the first
+      matching response, which is flagged as a warning
+    - `ForEach` and `If` array references name an array produced earlier
+
+    Whether `env`/`secret` variables are actually defined is not checked here;
+    that happens when the manifest is loaded.
+*/
+pub fn validate(content: &str) -> Vec<Diagnostic> {
+    let manifest: Manifest = match serde_yaml::from_str(content) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            let (line, column) = e
+                .location()
+                .map(|l| (l.line(), l.column()))
+                .unwrap_or((1, 1));
+            let mut message = e.to_string();
+            if let Some(idx) = message.rfind(" at line ") {
+                message.truncate(idx);
+            }
+            return vec![Diagnostic {
+                severity: Severity::Error,
+                line,
+                column,
+                message,
+                fragment: None,
+            }];
+        }
+    };
+
+    let mut validator = Validator {
+        content,
+        cursor: 0,
+        without_browser: false,
+        diagnostics: Vec::new(),
+    };
+
+    let timezones = [
+        ("source", manifest.source.timezone.as_deref()),
+        (
+            "metadata",
+            manifest
+                .metadata
+                .as_ref()
+                .and_then(|m| m.timezone.as_deref()),
+        ),
+    ];
+    for (section, timezone) in timezones {
+        if let Some(timezone) = timezone
+            && crate::util::time::parse_tz(timezone).is_none()
+        {
+            validator.cursor = find_key(content, section).unwrap_or(0);
+            validator.report_at_key(
+                Severity::Error,
+                "timezone",
+                format!("Unknown time zone '{}' in {}", timezone, section),
+            );
+        }
+    }
+
+    let discovery = &manifest.discovery;
+    let mut outputs = vec![("id", discovery.outputs.id.as_str())];
+    outputs.extend(discovery.outputs.name.as_deref().map(|t| ("name", t)));
+    outputs.extend(discovery.outputs.image.as_deref().map(|t| ("image", t)));
+    outputs.extend(
+        discovery
+            .outputs
+            .expires_at
+            .as_deref()
+            .map(|t| ("expires_at", t)),
+    );
+    validator.without_browser = discovery.browser.browser == BrowserMode::None;
+    validator.phase("discovery", &discovery.steps, Scope::default(), &outputs);
+
+    if let Some(metadata) = &manifest.metadata {
+        let outputs = [("programmes", metadata.outputs.programmes.as_str())];
+        validator.without_browser = metadata.browser.browser == BrowserMode::None;
+        validator.phase("metadata", &metadata.steps, Scope::default(), &outputs);
+    }
+
+    let content_phase = &manifest.content;
+    let mut outputs = vec![("manifest_url", content_phase.outputs.manifest_url.as_str())];
+    outputs.extend(
+        content_phase
+            .outputs
+            .license_url
+            .as_deref()
+            .map(|t| ("license_url", t)),
+    );
+    outputs.extend(
+        content_phase
+            .outputs
+            .expires_at
+            .as_deref()
+            .map(|t| ("expires_at", t)),
+    );
+    let mut headers: Vec<_> = content_phase.outputs.headers.iter().flatten().collect();
+    headers.sort();
+    outputs.extend(headers.iter().map(|(k, v)| (k.as_str(), v.as_str())));
+
+    let mut scope = Scope::default();
+    scope.outputs.insert(
+        "channel".to_string(),
+        Some(["id", "name", "image"].map(String::from).into()),
+    );
+    validator.without_browser = content_phase.browser.browser == BrowserMode::None;
+    validator.phase("content", &content_phase.steps, scope, &outputs);
+
+    let mut diagnostics = validator.diagnostics;
+    diagnostics.sort_by_key(|d| (d.line, d.column));
+    diagnostics
+}
+
+/**
+    What templates can reference at a given point in a phase.
+*/
+#[derive(Debug, Clone, Default)]
+struct Scope {
+    /// Step name → its outputs (`None` if any output may exist, e.g. a binding).
+    outputs: HashMap<String, Option<HashSet<String>>>,
+    /// Array output names, as referenced by `ForEach.array` and `condition.array`.
+    arrays: HashSet<String>,
+}
+
+struct Validator<'a> {
+    content: &'a str,
+    /// Byte offset of the current step (or phase) in the YAML source.
+    cursor: usize,
+    /// Whether the current phase has `browser: none`.
+    without_browser: bool,
+    diagnostics: Vec<Diagnostic>,
+}
+
+impl Validator<'_> {
+    fn phase(&mut self, key: &str, steps: &[Step], mut scope: Scope, outputs: &[(&str, &str)]) {
+        let phase_key = Regex::new(&format!(r"(?m)^{}\s*:", regex::escape(key))).unwrap();
+        let phase_start = phase_key.find(self.content).map(|m| m.start()).unwrap_or(0);
+        self.cursor = phase_start;
+
+        let mut names = HashSet::new();
+        collect_names(steps, &mut names);
+
+        self.steps(steps, &mut scope, &names);
+
+        self.cursor = phase_start;
+        for (field, template) in outputs {
+            let errors = check_template(template, &scope, &names);
+            for error in errors {
+                self.error_at(template, format!("Output '{}': {}", field, error));
+            }
+        }
+    }
+
+    fn steps(&mut self, steps: &[Step], scope: &mut Scope, names: &HashSet<String>) {
+        for step in steps {
+            self.step(step, scope, names);
+        }
+    }
+
+    fn step(&mut self, step: &Step, scope: &mut Scope, names: &HashSet<String>) {
+        let step_name = step.name();
+        let name_key = format!(
+            r#"(?m)name:[ \t]*["']?{}["']?[ \t]*$"#,
+            regex::escape(step_name)
+        );
+        if let Ok(re) = Regex::new(&name_key)
+            && let Some(m) = re.find(&self.content[self.cursor..])
+        {
+            self.cursor += m.start();
+        }
+        let step_start = self.cursor;
+
+        if self.without_browser && step_needs_page(step) {
+            self.error_here(format!(
+                "Step '{}': needs a browser, but the phase has 'browser: none'",
+                step_name
+            ));
+        }
+
+        let mut templates: Vec<&str> = Vec::new();
+        let mut extractors = None;
+        // Scope for the step's own templates, if it binds more than `scope`
+        let mut step_scope: Option<Scope> = None;
+
+        match step {
+            Step::Navigate { url, .. } => templates.push(url),
+            Step::FetchInBrowser {
+                url,
+                method,
+                query,
+                body,
+                extract,
+                ..
+            } => {
+                templates.push(url);
+                request_templates(method, query, body, &mut templates);
+                extractors = Some(extract);
+            }
+            Step::Sniff {
+                request, extract, ..
+            } => {
+                self.request(step_name, request);
+                for (name, extractor) in sorted(extract) {
+                    if super::step::is_array_extractor(&extractor.kind)
+                        && !super::step::is_playlist_extractor(&extractor.kind)
+                    {
+                        self.report_at_key(
+                            Severity::Warning,
+                            name,
+                            format!(
+                                "Step '{}': array extractor '{}' in a Sniff step only reads the first matching response; use SniffMany to collect items across responses",
+                                step_name, name
+                            ),
+                        );
+                    }
+                }
+                extractors = Some(extract);
+            }
+            Step::SniffMany {
+                request, extract, ..
+            } => {
+                self.request(step_name, request);
+                extractors = Some(extract);
+            }
+            Step::Fetch {
+                url,
+                urls,
+                method,
+                query,
+                headers,
+                body,
+                paginate,
+                extract,
+                ..
+            } => {
+                if url.is_none() && urls.is_none() {
+                    self.error_here(format!(
+                        "Step '{}': Fetch requires 'url' or 'urls'",
+                        step_name
+                    ));
+                }
+                if let Some(paginate) = paginate {
+                    step_scope =
+                        Some(self.paginate(step_name, paginate, urls, extract, scope, names));
+                }
+                templates.extend(url.as_deref());
+                templates.extend(urls.iter().flatten().map(String::as_str));
+                templates.extend(sorted(headers).into_iter().map(|(_, v)| v.as_str()));
+                request_templates(method, query, body, &mut templates);
+                extractors = Some(extract);
+            }
+            Step::Document { extract, .. } => extractors = Some(extract),
+            Step::Script { script, .. } => templates.push(script),
+            Step::Automation { steps: actions, .. } => {
+                for action in actions {
+                    match action {
+                        AutomationAction::Click { selector, .. }
+                        | AutomationAction::ClickIframe { selector, .. }
+                        | AutomationAction::Hover { selector, .. } => templates.push(selector),
+                        AutomationAction::Type { selector, text, .. } => {
+                            templates.extend([selector.as_str(), text.as_str()]);
+                        }
+                        AutomationAction::PressKey { key, selector, .. } => {
+                            templates.push(key);
+                            templates.extend(selector.as_deref());
+                        }
+                        AutomationAction::Scroll { selector, .. }
+                        | AutomationAction::SwitchFrame { selector, .. } => {
+                            templates.extend(selector.as_deref());
+                        }
+                        AutomationAction::SelectOption {
+                            selector,
+                            value,
+                            label,
+                            ..
+                        } => {
+                            templates.push(selector);
+                            templates.extend(value.as_deref());
+                            templates.extend(label.as_deref());
+                        }
+                        AutomationAction::WaitFor { condition } => {
+                            templates.extend(condition.selector.as_deref());
+                            templates.extend(condition.function.as_deref());
+                        }
+                    }
+                }
+            }
+            Step::If { condition, .. } => {
+                self.condition(step_name, condition, scope, names);
+            }
+            Step::ForEach {
+                array,
+                binding,
+                steps,
+                ..
+            } => {
+                if !scope.arrays.contains(array) {
+                    self.error_at(
+                        array,
+                        format!(
+                            "Step '{}': unknown array '{}'{}",
+                            step_name,
+                            array,
+                            available(&scope.arrays)
+                        ),
+                    );
+                }
+
+                // Nested steps see the item binding, but their outputs stay
+                // inside each iteration
+                let mut nested = scope.clone();
+                nested
+                    .outputs
+                    .insert(binding.as_deref().unwrap_or("item").to_string(), None);
+                self.steps(steps, &mut nested, names);
+            }
+        }
+
+        let template_scope = step_scope.as_ref().unwrap_or(scope);
+        for template in templates {
+            for error in check_template(template, template_scope, names) {
+                self.cursor = step_start;
+                self.error_at(template, format!("Step '{}': {}", step_name, error));
+            }
+        }
+
+        if let Some(extract) = extractors {
+            let arrays = extract
+                .values()
+                .filter(|e| super::step::is_array_extractor(&e.kind))
+                .count();
+            if arrays > 1 || (arrays == 1 && extract.len() > 1) {
+                self.error_here(format!(
+                    "Step '{}': a step with an array extractor produces only that array; move the other extractors to a separate step",
+                    step_name
+                ));
+            }
+
+            for (name, extractor) in sorted(extract) {
+                self.cursor = step_start;
+                self.extractor(step_name, name, extractor, template_scope, names);
+            }
+            self.cursor = step_start;
+
+            let outputs = extract.keys().cloned().collect();
+            scope.outputs.insert(step_name.to_string(), Some(outputs));
+            scope.arrays.extend(
+                extract
+                    .iter()
+                    .filter(|(_, e)| super::step::is_array_extractor(&e.kind))
+                    .map(|(name, _)| name.clone()),
+            );
+        } else {
+            scope
+                .outputs
+                .entry(step_name.to_string())
+                .or_insert_with(|| Some(HashSet::new()));
+        }
+
+        if let Step::If {
+            then, otherwise, ..
+        } = step
+        {
+            // Either branch may run, so later steps may reference both
+            self.steps(then, scope, names);
+            self.steps(otherwise, scope, names);
+        }
+
+        if let OnError::Fallback { fallback } = step.on_error() {
+            self.steps(fallback, scope, names);
+        }
+    }
+
+    /**
+        Check a `paginate` block, returning the scope its step's templates see.
+    */
+    fn paginate(
+        &mut self,
+        step_name: &str,
+        paginate: &Paginate,
+        urls: &Option<Vec<String>>,
+        extract: &HashMap<String, Extractor>,
+        scope: &Scope,
+        names: &HashSet<String>,
+    ) -> Scope {
+        let start = self.cursor;
+
+        if urls.is_some() {
+            self.report_at_key(
+                Severity::Error,
+                "paginate",
+                format!(
+                    "Step '{}': 'paginate' needs a single 'url', not 'urls'",
+                    step_name
+                ),
+            );
+        }
+        if !extract
+            .values()
+            .any(|e| super::step::is_array_extractor(&e.kind))
+        {
+            self.report_at_key(
+                Severity::Error,
+                "paginate",
+                format!(
+                    "Step '{}': 'paginate' requires an array extractor",
+                    step_name
+                ),
+            );
+        }
+
+        let mut page_outputs: HashSet<String> = paginate.extract.keys().cloned().collect();
+        page_outputs.insert("number".to_string());
+        let mut page_scope = scope.clone();
+        page_scope
+            .outputs
+            .insert("page".to_string(), Some(page_outputs));
+
+        if let Some(next) = &paginate.next {
+            self.cursor = start;
+            self.extractor(step_name, "next", next, &page_scope, names);
+        }
+        for (name, extractor) in sorted(&paginate.extract) {
+            self.cursor = start;
+            self.extractor(step_name, name, extractor, &page_scope, names);
+        }
+        if let Some(condition) = &paginate.stop_when {
+            self.cursor = start;
+            self.condition(step_name, condition, &page_scope, names);
+        }
+
+        self.cursor = start;
+        page_scope
+    }
+
+    fn request(&mut self, step_name: &str, request: &RequestMatch) {
+        if let Err(e) = Regex::new(&request.url) {
+            self.error_at(
+                &request.url,
+                format!(
+                    "Step '{}': invalid request URL regex '{}': {}",
+                    step_name, request.url, e
+                ),
+            );
+        }
+    }
+
+    fn condition(
+        &mut self,
+        step_name: &str,
+        condition: &Condition,
+        scope: &Scope,
+        names: &HashSet<String>,
+    ) {
+        if let Err(e) = condition.check() {
+            self.error_here(format!("Step '{}': {}", step_name, e));
+        }
+
+        for template in [&condition.value, &condition.equals].into_iter().flatten() {
+            for error in check_template(template, scope, names) {
+                self.error_at(template, format!("Step '{}': {}", step_name, error));
+            }
+        }
+
+        if let Some(pattern) = &condition.matches
+            && let Err(e) = Regex::new(pattern)
+        {
+            self.error_at(
+                pattern,
+                format!("Step '{}': invalid regex '{}': {}", step_name, pattern, e),
+            );
+        }
+
+        if let Some(array) = &condition.array
+            && !scope.arrays.contains(array)
+        {
+            self.error_at(
+                array,
+                format!(
+                    "Step '{}': unknown array '{}'{}",
+                    step_name,
+                    array,
+                    available(&scope.arrays)
+                ),
+            );
+        }
+    }
+
+    fn extractor(
+        &mut self,
+        step_name: &str,
+        name: &str,
+        extractor: &Extractor,
+        scope: &Scope,
+        names: &HashSet<String>,
+    ) {
+        // Position subsequent searches at the extractor's key
+        if let Some(offset) = find_key(&self.content[self.cursor..], name) {
+            self.cursor += offset;
+        }
+
+        for error in check_extractor(extractor) {
+            self.error_here(format!(
+                "Step '{}': extractor '{}': {}",
+                step_name, name, error
+            ));
+        }
+
+        let mut templates: Vec<&str> = [
+            &extractor.path,
+            &extractor.regex,
+            &extractor.default,
+            &extractor.selector,
+            &extractor.variable,
+        ]
+        .into_iter()
+        .flatten()
+        .map(String::as_str)
+        .collect();
+        templates.extend(
+            sorted(extractor.each.iter().flatten())
+                .into_iter()
+                .map(|(_, v)| v.as_str()),
+        );
+
+        for template in templates {
+            for error in check_template(template, scope, names) {
+                self.error_at(
+                    template,
+                    format!("Step '{}': extractor '{}': {}", step_name, name, error),
+                );
+            }
+        }
+    }
+
+    fn error_here(&mut self, message: String) {
+        let (line, column) = line_column(self.content, self.cursor);
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            line,
+            column,
+            message,
+            fragment: None,
+        });
+    }
+
+    /**
+        Report an error at the first occurrence of `needle` after the cursor,
+        falling back to the cursor itself.
+    */
+    fn error_at(&mut self, needle: &str, message: String) {
+        let offset = self.content[self.cursor..]
+            .find(needle)
+            .map(|o| self.cursor + o)
+            .unwrap_or(self.cursor);
+        let (line, column) = line_column(self.content, offset);
+        self.diagnostics.push(Diagnostic {
+            severity: Severity::Error,
+            line,
+            column,
+            message,
+            fragment: None,
+        });
+    }
+
+    /**
+        Report a diagnostic at the first `key:` after the cursor, falling back
+        to the cursor itself.
+    */
+    fn report_at_key(&mut self, severity: Severity, key: &str, message: String) {
+        let offset = find_key(&self.content[self.cursor..], key)
+            .map(|o| self.cursor + o)
+            .unwrap_or(self.cursor);
+        let (line, column) = line_column(self.content, offset);
+        self.diagnostics.push(Diagnostic {
+            severity,
+            line,
+            column,
+            message,
+            fragment: None,
+        });
+    }
+}
+
+/**
+    Check the `${{...}}` placeholders in a template against a scope.
+*/
+fn check_template(template: &str, scope: &Scope, names: &HashSet<String>) -> Vec<String> {
+    let mut errors = Vec::new();
+
+    for cap in placeholder_regex().captures_iter(template) {
+        let expr = match expression::parse(&cap[1]) {
+            Ok(expr) => expr,
+            Err(e) => {
+                errors.push(format!("{} in '{}'", e, &cap[0]));
+                continue;
+            }
+        };
+
+        if let Err(e) = expression::check_filters(&expr) {
+            errors.push(format!("{} in '{}'", e, &cap[0]));
+        }
+
+        for path in expression::variables(&expr) {
+            if let Some(error) = check_reference(path, scope, names) {
+                errors.push(format!(
+                    "reference '{}': {}",
+                    expression::display_path(path),
+                    error
+                ));
+            }
+        }
+    }
+
+    errors
+}
+
+fn check_reference(path: &[PathSegment], scope: &Scope, names: &HashSet<String>) -> Option<String> {
+    let Some(PathSegment::Key(root)) = path.first() else {
+        return Some("must start with a step name".to_string());
+    };
+    let output = match path.get(1) {
+        Some(PathSegment::Key(output)) => Some(output),
+        Some(PathSegment::Index(_)) => return Some(format!("'{}' can't be indexed", root)),
+        None => None,
+    };
+
+    if root == "env" || root == "secret" || root == "cookies" {
+        return output
+            .is_none()
+            .then(|| format!("'{}' needs a variable name", root));
+    }
+
+    let Some(outputs) = scope.outputs.get(root) else {
+        return Some(if names.contains(root) {
+            format!("step '{}' is referenced before it runs", root)
+        } else {
+            format!("unknown step '{}'", root)
+        });
+    };
+
+    let Some(output) = output else {
+        return Some(format!("must name an output of step '{}'", root));
+    };
+
+    match outputs {
+        Some(outputs) if !outputs.contains(output) => Some(format!(
+            "step '{}' has no output '{}'{}",
+            root,
+            output,
+            available(outputs)
+        )),
+        _ => None,
+    }
+}
+
+/**
+    Collect the templates of a `Fetch`/`FetchInBrowser` method, query and body.
+*/
+fn request_templates<'a>(
+    method: &'a Option<String>,
+    query: &'a HashMap<String, String>,
+    body: &'a Option<RequestBody>,
+    templates: &mut Vec<&'a str>,
+) {
+    fn json_strings<'a>(value: &'a serde_json::Value, templates: &mut Vec<&'a str>) {
+        match value {
+            serde_json::Value::String(s) => templates.push(s),
+            serde_json::Value::Array(items) => {
+                items.iter().for_each(|item| json_strings(item, templates))
+            }
+            serde_json::Value::Object(map) => {
+                let mut entries: Vec<_> = map.iter().collect();
+                entries.sort_by_key(|(key, _)| *key);
+                entries
+                    .into_iter()
+                    .for_each(|(_, v)| json_strings(v, templates))
+            }
+            _ => {}
+        }
+    }
+
+    templates.extend(method.as_deref());
+    templates.extend(sorted(query).into_iter().map(|(_, v)| v.as_str()));
+    match body {
+        Some(RequestBody::Raw { raw, content_type }) => {
+            templates.push(raw);
+            templates.extend(content_type.as_deref());
+        }
+        Some(RequestBody::Json { json }) => json_strings(json, templates),
+        Some(RequestBody::Form { form }) => {
+            templates.extend(sorted(form).into_iter().map(|(_, v)| v.as_str()))
+        }
+        None => {}
+    }
+}
+
+fn collect_names(steps: &[Step], names: &mut HashSet<String>) {
+    for step in steps {
+        names.insert(step.name().to_string());
+        for nested in step.nested_steps() {
+            collect_names(std::slice::from_ref(nested), names);
+        }
+    }
+}
+
+fn sorted<'a, I, V>(map: I) -> Vec<(&'a String, &'a V)>
+where
+    I: IntoIterator<Item = (&'a String, &'a V)>,
+{
+    let mut entries: Vec<_> = map.into_iter().collect();
+    entries.sort_by(|a, b| a.0.cmp(b.0));
+    entries
+}
+
+fn available(names: &HashSet<String>) -> String {
+    if names.is_empty() {
+        return String::new();
+    }
+    let mut names: Vec<_> = names.iter().map(String::as_str).collect();
+    names.sort();
+    format!(" (available: {})", names.join(", "))
+}
+
+/**
+    Find a YAML mapping key (`key:` at the start of a line) in `content`.
+*/
+fn find_key(content: &str, key: &str) -> Option<usize> {
+    let pattern = format!(r#"(?m)^[ \t-]*["']?{}["']?[ \t]*:"#, regex::escape(key));
+    let re = Regex::new(&pattern).ok()?;
+    let m = re.find(content)?;
+    Some(m.start() + m.as_str().find(key)?)
+}
+
+fn line_column(content: &str, offset: usize) -> (usize, usize) {
+    let before = &content[..offset.min(content.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rfind('\n')
+        .map(|i| before[i + 1..].chars().count())
+        .unwrap_or_else(|| before.chars().count())
+        + 1;
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_embedded_manifests_are_valid() {
+        for (path, content) in super::super::manifest::embedded_files() {
+            let diagnostics: Vec<_> = validate_file(&content, &Fragments::embedded())
+                .into_iter()
+                .filter(|d| d.severity == Severity::Error)
+                .collect();
+            assert!(
+                diagnostics.is_empty(),
+                "{}: {:?}",
+                path.display(),
+                diagnostics
+            );
+        }
+    }
+
+    #[test]
+    fn test_fragment_positions() {
+        let dir = tempfile::tempdir().unwrap();
+        let write = |name: &str, content: &str| {
+            let path = dir.path().join(name);
+            std::fs::create_dir_all(path.parent().unwrap()).unwrap();
+            std::fs::write(path, content).unwrap();
+        };
+        write(
+            "templates/base.yaml",
+            r#"content:
+  outputs:
+    manifest_url: "${{ missing.url }}"
+  steps: []
+"#,
+        );
+        write(
+            "templates/sniff.yaml",
+            r#"# Channel list
+params:
+  pattern: "api/channels"
+
+items:
+  - name: "list"
+    kind: Sniff
+    request:
+      url: "${{ param.pattern }}"
+    extract:
+      channels:
+        kind: jsonpath_array
+        path: "$.items[*]"
+        each:
+          id: "$.id"
+"#,
+        );
+        let manifest = r#"extends: templates/base.yaml
+
+source:
+  id: "example"
+  name: "Example"
+
+discovery:
+  outputs:
+    id: "${{ list.channels.id }}"
+    name: "${{ lst.channels.name }}"
+  steps:
+    - include: templates/sniff.yaml
+      params:
+        pattern: "api/(channels"
+"#;
+        let diagnostics = validate_file(manifest, &Fragments::in_dir(dir.path()));
+        let find = |needle: &str| {
+            diagnostics
+                .iter()
+                .find(|d| d.message.contains(needle))
+                .unwrap_or_else(|| panic!("no diagnostic with {:?} in {:#?}", needle, diagnostics))
+        };
+
+        let own = find("unknown step 'lst'");
+        assert_eq!(
+            (own.fragment.as_deref(), own.line, own.column),
+            (None, 10, 11)
+        );
+
+        // In the base manifest
+        let base = find("unknown step 'missing'");
+        assert_eq!(base.fragment.as_deref(), Some("templates/base.yaml"));
+        assert_eq!(base.line, 3);
+
+        // A substituted parameter falls back to the closest line above it
+        let regex = find("invalid request URL regex");
+        assert_eq!(regex.fragment.as_deref(), Some("templates/sniff.yaml"));
+        assert_eq!(regex.line, 8);
+    }
+
+    #[test]
+    fn test_validate_fragments() {
+        for name in [
+            "templates/mediastream.yaml",
+            "templates/mediastream_sniff.yaml",
+        ] {
+            let content = Fragments::embedded().read(name).unwrap();
+            let diagnostics = validate_file(&content, &Fragments::embedded());
+            assert!(diagnostics.is_empty(), "{}: {:?}", name, diagnostics);
+        }
+
+        for (content, message) in [
+            ("items: \"list\"\n", "'items' of a fragment must be a list"),
+            ("- include: a.yaml\n  name: x\n", "Unexpected key name"),
+            ("params: [a]\ncontent: {}\n", "'params' must be a mapping"),
+        ] {
+            let diagnostics = validate_file(content, &Fragments::embedded());
+            assert_eq!(diagnostics.len(), 1, "{}: {:?}", content, diagnostics);
+            assert!(
+                diagnostics[0].message.contains(message),
+                "{:?}",
+                diagnostics
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_error_location() {
+        let diagnostics = validate("source:\n  id: \"x\"\n  name: [\n");
+        assert_eq!(diagnostics.len(), 1);
+        assert!(diagnostics[0].line >= 3, "{:?}", diagnostics);
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let yaml = r#"source:
+  id: "example"
+  name: "Example"
+
+discovery:
+  outputs:
+    id: "${{ list.channels.id }}"
+    name: "${{ lst.channels.name }}"
+  steps:
+    - name: "page"
+      kind: Navigate
+      url: "https://example.com/${{ later.token }}"
+    - name: "list"
+      kind: Sniff
+      request:
+        url: "api/(channels"
+      extract:
+        channels:
+          kind: jsonpath_array
+          path: "$.items[*]"
+          each:
+            id: "$.id"
+        token:
+          kind: jsonpath
+    - name: "later"
+      kind: Document
+      extract:
+        token:
+          kind: regex
+          path: "token=(["
+
+content:
+  outputs:
+    manifest_url: "${{ channel.id | shout }}"
+  steps:
+    - name: "player"
+      kind: Navigate
+      url: "https://example.com/${{ channel.slug }}"
+"#;
+        let diagnostics = validate(yaml);
+        let find = |needle: &str| {
+            diagnostics
+                .iter()
+                .find(|d| d.message.contains(needle))
+                .unwrap_or_else(|| panic!("no diagnostic with {:?} in {:#?}", needle, diagnostics))
+        };
+
+        assert_eq!(find("unknown step 'lst'").line, 8);
+        assert_eq!(find("step 'later' is referenced before it runs").line, 12);
+        assert_eq!(find("produces only that array").line, 13);
+        assert_eq!(find("invalid request URL regex").line, 16);
+        let warning = find("array extractor 'channels' in a Sniff step");
+        assert_eq!((warning.line, warning.severity), (18, Severity::Warning));
+        assert_eq!(
+            find("extractor 'token': jsonpath extractor requires 'path'").line,
+            23
+        );
+        assert_eq!(find("Invalid regex 'token=(['").line, 28);
+        assert_eq!(find("Unknown filter 'shout'").line, 34);
+        assert_eq!(find("step 'channel' has no output 'slug'").line, 38);
+        assert_eq!(diagnostics.len(), 9, "{:#?}", diagnostics);
+    }
+
+    #[test]
+    fn test_condition_fields() {
+        let yaml = r#"source:
+  id: "example"
+  name: "Example"
+
+discovery:
+  outputs:
+    id: "example"
+  steps:
+    - name: "check"
+      kind: If
+      condition:
+        matches: "^live$"
+      then: []
+    - name: "empty"
+      kind: If
+      condition:
+        not: true
+      then: []
+
+content:
+  outputs:
+    manifest_url: "https://example.com/live.m3u8"
+  steps: []
+"#;
+        let diagnostics = validate(yaml);
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+
+        assert_eq!(diagnostics.len(), 2, "{:#?}", diagnostics);
+        assert!(messages[0].contains("Step 'check': Condition 'matches' requires a 'value'"));
+        assert!(messages[1].contains("Step 'empty': Condition needs at least one of"));
+    }
+
+    #[test]
+    fn test_paginate_scope() {
+        let yaml = r#"source:
+  id: "example"
+  name: "Example"
+
+discovery:
+  outputs:
+    id: "${{ list.channels.id }}"
+  steps:
+    - name: "list"
+      kind: Fetch
+      url: "https://example.com/api?page=${{ page.number }}"
+      query:
+        after: "${{ page.cursor | default('') }}"
+        size: "${{ page.size }}"
+      paginate:
+        extract:
+          cursor:
+            kind: jsonpath
+            path: "$.next"
+        stop_when:
+          value: "${{ page.cursor }}"
+          equals: ""
+      extract:
+        channels:
+          kind: jsonpath_array
+          path: "$.items[*]"
+          each:
+            id: "$.id"
+    - name: "after"
+      kind: Navigate
+      url: "https://example.com/${{ page.number }}"
+
+content:
+  outputs:
+    manifest_url: "https://example.com/${{ channel.id }}.m3u8"
+  steps: []
+"#;
+        let diagnostics = validate(yaml);
+        let messages: Vec<&str> = diagnostics.iter().map(|d| d.message.as_str()).collect();
+
+        assert_eq!(diagnostics.len(), 2, "{:#?}", diagnostics);
+        assert!(messages[0].contains("step 'page' has no output 'size'"));
+        assert_eq!(diagnostics[0].line, 14);
+        assert!(
+            messages[1].contains("unknown step 'page'"),
+            "{:#?}",
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_browser_none() {
+        let yaml = r#"source:
+  id: "example"
+  name: "Example"
+
+discovery:
+  browser: none
+  outputs:
+    id: "${{ page.id }}"
+  steps:
+    - name: "guide"
+      kind: Fetch
+      url: "https://example.com/guide"
+      extract:
+        status:
+          kind: status
+    - name: "page"
+      kind: Document
+      extract:
+        id:
+          kind: css
+          path: "h1"
+
+content:
+  browser: none
+  outputs:
+    manifest_url: "${{ stream.url }}"
+  steps:
+    - name: "open"
+      kind: Navigate
+      url: "https://example.com/${{ channel.id }}"
+    - name: "stream"
+      kind: Sniff
+      request:
+        url: "\\.m3u8"
+      extract:
+        url:
+          kind: url
+"#;
+        let diagnostics = validate(yaml);
+
+        assert_eq!(diagnostics.len(), 2, "{:#?}", diagnostics);
+        assert!(
+            diagnostics[0]
+                .message
+                .contains("Step 'open': needs a browser")
+        );
+        assert_eq!(diagnostics[0].line, 28);
+        assert!(
+            diagnostics[1]
+                .message
+                .contains("Step 'stream': needs a browser")
+        );
+    }
+}
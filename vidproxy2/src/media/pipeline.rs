@@ -385,6 +385,12 @@ impl PipelineStore {
             loop {
                 tokio::select! {
                     _ = tokio::time::sleep(Duration::from_secs(5)) => {
+                        // Removed from the store and stopped: nothing left to monitor
+                        if Arc::strong_count(&pipeline_clone) == 1
+                            && !pipeline_clone.is_running().await
+                        {
+                            return;
+                        }
                         if pipeline_clone.is_running().await {
                             let idle_secs = pipeline_clone.seconds_since_activity();
                             if idle_secs > idle_timeout.as_secs() {
@@ -415,6 +421,20 @@ impl PipelineStore {
         self.pipelines.read().await.get(channel_id).cloned()
     }
 
+    /**
+        Stop a channel's pipeline and remove it from the store.
+    */
+    pub async fn remove(&self, channel_id: &ChannelId) -> bool {
+        let pipeline = self.pipelines.write().await.remove(channel_id);
+        match pipeline {
+            Some(pipeline) => {
+                pipeline.stop().await;
+                true
+            }
+            None => false,
+        }
+    }
+
     pub async fn stop_all(&self) {
         let pipelines = self.pipelines.read().await;
         for pipeline in pipelines.values() {
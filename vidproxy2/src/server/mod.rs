@@ -44,6 +44,10 @@ pub async fn run_server(
         .route("/{source_id}/channels.m3u", get(routes::source_m3u))
         .route("/{source_id}/epg.xml", get(routes::source_epg))
         .route("/{source_id}/{channel_id}/info", get(routes::channel_info))
+        .route(
+            "/{source_id}/{channel_id}/trace",
+            get(routes::channel_trace),
+        )
         .route(
             "/{source_id}/{channel_id}/image",
             get(routes::channel_image),
@@ -9,6 +9,7 @@ use axum::{
 use tokio_util::io::ReaderStream;
 
 use crate::channel::{ChannelId, SourceState};
+use crate::engine::secrets::redact_json;
 
 use super::AppState;
 
@@ -128,18 +129,20 @@ pub async fn source_info(
         })
         .collect();
 
+    let mut info = serde_json::json!({
+        "id": manifest.source.id,
+        "name": manifest.source.name,
+        "status": status,
+        "error": error,
+        "m3u": format!("{}/{}/channels.m3u", base_url, source_id),
+        "epg": format!("{}/{}/epg.xml", base_url, source_id),
+        "channels": channel_list,
+    });
+    redact_json(&mut info);
+
     Ok((
         [(header::CONTENT_TYPE, "application/json; charset=utf-8")],
-        serde_json::json!({
-            "id": manifest.source.id,
-            "name": manifest.source.name,
-            "status": status,
-            "error": error,
-            "m3u": format!("{}/{}/channels.m3u", base_url, source_id),
-            "epg": format!("{}/{}/epg.xml", base_url, source_id),
-            "channels": channel_list,
-        })
-        .to_string(),
+        info.to_string(),
     ))
 }
 
@@ -222,21 +225,47 @@ pub async fn channel_info(
 
     let available = entry.is_live_now();
 
+    let mut info = serde_json::json!({
+        "id": id.to_string(),
+        "source": source_id,
+        "channel_id": channel_id,
+        "name": entry.channel.name,
+        "image": entry.channel.image,
+        "available": available,
+        "manifest_url": stream_info.map(|s| &s.manifest_url),
+        "license_url": stream_info.and_then(|s| s.license_url.as_ref()),
+        "expires_at": stream_info.and_then(|s| s.expires_at).map(|dt| dt.timestamp()),
+        "error": entry.last_error,
+    });
+    redact_json(&mut info);
+
     Ok((
         [(header::CONTENT_TYPE, "application/json; charset=utf-8")],
-        serde_json::json!({
-            "id": id.to_string(),
-            "source": source_id,
-            "channel_id": channel_id,
-            "name": entry.channel.name,
-            "image": entry.channel.image,
-            "available": available,
-            "manifest_url": stream_info.map(|s| &s.manifest_url),
-            "license_url": stream_info.and_then(|s| s.license_url.as_ref()),
-            "expires_at": stream_info.and_then(|s| s.expires_at).map(|dt| dt.timestamp()),
-            "error": entry.last_error,
-        })
-        .to_string(),
+        info.to_string(),
+    ))
+}
+
+/**
+    Trace of the channel's latest content phase run, for debugging slow or
+    failing resolutions. Not found until content has been resolved once.
+*/
+pub async fn channel_trace(
+    State(state): State<AppState>,
+    Path((source_id, channel_id)): Path<(String, String)>,
+) -> Result<impl IntoResponse, StatusCode> {
+    wait_for_source_ready(&state, &source_id).await?;
+
+    let id = ChannelId::new(&source_id, &channel_id);
+    let trace = state
+        .resolver
+        .registry
+        .get_content_trace(&id)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    let body = serde_json::to_string(&trace).map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    Ok((
+        [(header::CONTENT_TYPE, "application/json; charset=utf-8")],
+        body,
     ))
 }
 
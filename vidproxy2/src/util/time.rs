@@ -1,4 +1,8 @@
-use chrono::{DateTime, TimeZone, Utc};
+use std::sync::OnceLock;
+
+use chrono::{DateTime, NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use regex::Regex;
 
 /**
     Get the current time as a UTC datetime.
@@ -41,6 +45,106 @@ pub fn parse_timestamp(s: &str) -> Option<DateTime<Utc>> {
     None
 }
 
+/**
+    Parse an IANA time zone name such as `"America/Bogota"`.
+*/
+pub fn parse_tz(name: &str) -> Option<Tz> {
+    name.trim().parse::<Tz>().ok()
+}
+
+/**
+    Interpret a wall-clock time in a time zone (UTC if `None`).
+
+    Times repeated by a DST change resolve to the earlier one; times skipped
+    by it don't exist and yield `None`.
+*/
+pub fn local_to_utc(naive: NaiveDateTime, tz: Option<Tz>) -> Option<DateTime<Utc>> {
+    match tz {
+        Some(tz) => tz
+            .from_local_datetime(&naive)
+            .earliest()
+            .map(|dt| dt.with_timezone(&Utc)),
+        None => Some(naive.and_utc()),
+    }
+}
+
+/**
+    Parse a date and time without an offset, e.g. `"2026-02-08 20:00"` or
+    `"2026-02-08T20:00:00"`.
+*/
+pub fn parse_local_datetime(s: &str) -> Option<NaiveDateTime> {
+    const FORMATS: &[&str] = &[
+        "%Y-%m-%dT%H:%M:%S%.f",
+        "%Y-%m-%dT%H:%M",
+        "%Y-%m-%d %H:%M:%S%.f",
+        "%Y-%m-%d %H:%M",
+    ];
+    let trimmed = s.trim();
+    FORMATS
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(trimmed, format).ok())
+}
+
+/**
+    Parse a date: `"2026-02-08"`, `"08/02/2026"` (day first), or a full
+    timestamp, whose date is taken in `tz`.
+*/
+pub fn parse_day(s: &str, tz: Option<Tz>) -> Option<NaiveDate> {
+    let trimmed = s.trim();
+    for format in ["%Y-%m-%d", "%d/%m/%Y", "%d-%m-%Y", "%d.%m.%Y"] {
+        if let Ok(date) = NaiveDate::parse_from_str(trimmed, format) {
+            return Some(date);
+        }
+    }
+    if let Some(naive) = parse_local_datetime(trimmed) {
+        return Some(naive.date());
+    }
+    let dt = parse_timestamp(trimmed)?;
+    Some(match tz {
+        Some(tz) => dt.with_timezone(&tz).date_naive(),
+        None => dt.date_naive(),
+    })
+}
+
+/**
+    Parse a time of day without a date.
+
+    Supports 24-hour times (`"20:00"`, `"20h30"`, `"20.30"`) and 12-hour times
+    in the forms schedule pages use (`"8 PM"`, `"8:00 pm"`, `"8:00 p. m."`).
+*/
+pub fn parse_time_of_day(s: &str) -> Option<NaiveTime> {
+    static RE: OnceLock<Regex> = OnceLock::new();
+    let re = RE.get_or_init(|| {
+        Regex::new(r"(?i)^(\d{1,2})(?:[:h.](\d{2}))?(?::(\d{2}))?\s*(?:([ap])\.?\s*m\.?)?$")
+            .expect("time of day regex should compile")
+    });
+
+    let captures = re.captures(s.trim())?;
+    let number = |i: usize| {
+        captures
+            .get(i)
+            .map_or(Some(0), |m| m.as_str().parse::<u32>().ok())
+    };
+    let (mut hour, minute, second) = (number(1)?, number(2)?, number(3)?);
+
+    match captures.get(4).map(|m| m.as_str().to_ascii_lowercase()) {
+        Some(meridiem) => {
+            if !(1..=12).contains(&hour) {
+                return None;
+            }
+            hour %= 12;
+            if meridiem == "p" {
+                hour += 12;
+            }
+        }
+        // A bare hour is too ambiguous to be a time
+        None if captures.get(2).is_none() => return None,
+        None => {}
+    }
+
+    NaiveTime::from_hms_opt(hour, minute, second)
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -69,4 +173,32 @@ mod tests {
         assert!(parse_timestamp("").is_none());
         assert!(parse_timestamp("123").is_none());
     }
+
+    #[test]
+    fn test_parse_time_of_day() {
+        let time = |h, m| NaiveTime::from_hms_opt(h, m, 0);
+        assert_eq!(parse_time_of_day("8:00 p. m."), time(20, 0));
+        assert_eq!(parse_time_of_day("12:30 a.m."), time(0, 30));
+        assert_eq!(parse_time_of_day("12 PM"), time(12, 0));
+        assert_eq!(parse_time_of_day(" 20:15 "), time(20, 15));
+        assert_eq!(parse_time_of_day("7h05"), time(7, 5));
+        assert!(parse_time_of_day("20").is_none());
+        assert!(parse_time_of_day("13:00 pm").is_none());
+        assert!(parse_time_of_day("25:00").is_none());
+    }
+
+    #[test]
+    fn test_local_to_utc() {
+        let tz = parse_tz("America/Bogota");
+        let naive = parse_local_datetime("2026-02-08 20:00").unwrap();
+        assert_eq!(
+            local_to_utc(naive, tz).unwrap().to_rfc3339(),
+            "2026-02-09T01:00:00+00:00"
+        );
+        assert_eq!(
+            parse_day("2026-02-09T01:00:00Z", tz),
+            NaiveDate::from_ymd_opt(2026, 2, 8)
+        );
+        assert!(parse_tz("Mars/Olympus").is_none());
+    }
 }